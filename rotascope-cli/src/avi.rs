@@ -0,0 +1,199 @@
+//! 最小的 MJPEG-in-AVI 写入器：单一视频流，每帧一个 `00dc` 块，末尾写 idx1 索引。
+//!
+//! 头部长度固定，finish 时回到文件开头用最终的帧数和大小重写一次。
+
+use rotascope_core::Result;
+use std::io::{Seek, SeekFrom, Write};
+
+/// RIFF 头 + hdrl 列表 + movi 列表头的总长度
+const HEADER_LEN: u32 = 12 + 8 + HDRL_LEN + 12;
+const HDRL_LEN: u32 = 4 + (8 + 56) + (12 + (8 + 56) + (8 + 40));
+
+const AVIF_HASINDEX: u32 = 0x10;
+const AVIIF_KEYFRAME: u32 = 0x10;
+
+pub struct AviWriter<W: Write + Seek> {
+    inner: W,
+    width: u32,
+    height: u32,
+    fps: u32,
+    /// 每帧在 movi 列表中的 (偏移, 长度)，偏移从 `movi` 标识开始计算
+    index: Vec<(u32, u32)>,
+    movi_len: u32,
+    max_frame_len: u32,
+}
+
+impl<W: Write + Seek> AviWriter<W> {
+    pub fn new(inner: W, width: u32, height: u32, fps: u32) -> Result<Self> {
+        let mut writer = Self {
+            inner,
+            width,
+            height,
+            fps: fps.max(1),
+            index: Vec::new(),
+            movi_len: 4,
+            max_frame_len: 0,
+        };
+        writer.write_headers()?;
+        Ok(writer)
+    }
+
+    /// 追加一帧 JPEG
+    pub fn write_frame(&mut self, jpeg: &[u8]) -> Result<()> {
+        let len = u32::try_from(jpeg.len()).map_err(|_| "Frame too large for AVI".to_string())?;
+        let padded = len + (len & 1);
+        let movi_len = self.movi_len as u64 + 8 + padded as u64;
+        let file_len = HEADER_LEN as u64 + movi_len + 8 + 16 * (self.index.len() as u64 + 1);
+        if file_len > u32::MAX as u64 {
+            return Err("AVI file would exceed 4 GiB".to_string());
+        }
+
+        self.inner.write_all(b"00dc").map_err(|e| e.to_string())?;
+        self.inner.write_all(&len.to_le_bytes()).map_err(|e| e.to_string())?;
+        self.inner.write_all(jpeg).map_err(|e| e.to_string())?;
+        if padded != len {
+            self.inner.write_all(&[0]).map_err(|e| e.to_string())?;
+        }
+        self.index.push((self.movi_len, len));
+        self.movi_len = movi_len as u32;
+        self.max_frame_len = self.max_frame_len.max(len);
+        Ok(())
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.index.len()
+    }
+
+    /// 写入索引并回填头部
+    pub fn finish(mut self) -> Result<W> {
+        let mut idx1 = Vec::with_capacity(8 + 16 * self.index.len());
+        idx1.extend_from_slice(b"idx1");
+        idx1.extend_from_slice(&(16 * self.index.len() as u32).to_le_bytes());
+        for (offset, len) in &self.index {
+            idx1.extend_from_slice(b"00dc");
+            idx1.extend_from_slice(&AVIIF_KEYFRAME.to_le_bytes());
+            idx1.extend_from_slice(&offset.to_le_bytes());
+            idx1.extend_from_slice(&len.to_le_bytes());
+        }
+        self.inner.write_all(&idx1).map_err(|e| e.to_string())?;
+
+        self.inner.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+        self.write_headers()?;
+        self.inner.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+        self.inner.flush().map_err(|e| e.to_string())?;
+        Ok(self.inner)
+    }
+
+    fn write_headers(&mut self) -> Result<()> {
+        let frames = self.index.len() as u32;
+        let riff_len = HEADER_LEN - 8 + self.movi_len - 4 + 8 + 16 * frames;
+        let buffer_size = self.max_frame_len + 8;
+        let mut h = Vec::with_capacity(HEADER_LEN as usize);
+
+        h.extend_from_slice(b"RIFF");
+        put(&mut h, riff_len);
+        h.extend_from_slice(b"AVI ");
+
+        h.extend_from_slice(b"LIST");
+        put(&mut h, HDRL_LEN);
+        h.extend_from_slice(b"hdrl");
+
+        // MainAVIHeader
+        h.extend_from_slice(b"avih");
+        put(&mut h, 56);
+        put(&mut h, 1_000_000 / self.fps);
+        put(&mut h, buffer_size.saturating_mul(self.fps));
+        put(&mut h, 0);
+        put(&mut h, AVIF_HASINDEX);
+        put(&mut h, frames);
+        put(&mut h, 0);
+        put(&mut h, 1);
+        put(&mut h, buffer_size);
+        put(&mut h, self.width);
+        put(&mut h, self.height);
+        h.extend_from_slice(&[0; 16]);
+
+        h.extend_from_slice(b"LIST");
+        put(&mut h, 4 + (8 + 56) + (8 + 40));
+        h.extend_from_slice(b"strl");
+
+        // AVIStreamHeader
+        h.extend_from_slice(b"strh");
+        put(&mut h, 56);
+        h.extend_from_slice(b"vids");
+        h.extend_from_slice(b"MJPG");
+        put(&mut h, 0);
+        put(&mut h, 0); // wPriority + wLanguage
+        put(&mut h, 0);
+        put(&mut h, 1);
+        put(&mut h, self.fps);
+        put(&mut h, 0);
+        put(&mut h, frames);
+        put(&mut h, buffer_size);
+        put(&mut h, u32::MAX);
+        put(&mut h, 0);
+        h.extend_from_slice(&0u16.to_le_bytes());
+        h.extend_from_slice(&0u16.to_le_bytes());
+        h.extend_from_slice(&(self.width.min(i16::MAX as u32) as u16).to_le_bytes());
+        h.extend_from_slice(&(self.height.min(i16::MAX as u32) as u16).to_le_bytes());
+
+        // BITMAPINFOHEADER
+        h.extend_from_slice(b"strf");
+        put(&mut h, 40);
+        put(&mut h, 40);
+        put(&mut h, self.width);
+        put(&mut h, self.height);
+        h.extend_from_slice(&1u16.to_le_bytes());
+        h.extend_from_slice(&24u16.to_le_bytes());
+        h.extend_from_slice(b"MJPG");
+        put(&mut h, self.width * self.height * 3);
+        h.extend_from_slice(&[0; 16]);
+
+        h.extend_from_slice(b"LIST");
+        put(&mut h, self.movi_len);
+        h.extend_from_slice(b"movi");
+
+        debug_assert_eq!(h.len(), HEADER_LEN as usize);
+        self.inner.write_all(&h).map_err(|e| e.to_string())
+    }
+}
+
+fn put(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn u32_at(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_index_and_patches_header() {
+        let mut avi = AviWriter::new(Cursor::new(Vec::new()), 64, 48, 25).unwrap();
+        avi.write_frame(&[0xff, 0xd8, 1, 0xff, 0xd9]).unwrap();
+        avi.write_frame(&[0xff, 0xd8, 0xff, 0xd9]).unwrap();
+        let data = avi.finish().unwrap().into_inner();
+
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32_at(&data, 4) as usize, data.len() - 8);
+        assert_eq!(&data[8..12], b"AVI ");
+        // avih: dwMicroSecPerFrame, dwTotalFrames, dwWidth
+        assert_eq!(u32_at(&data, 32), 40_000);
+        assert_eq!(u32_at(&data, 48), 2);
+        assert_eq!(u32_at(&data, 64), 64);
+
+        let movi = HEADER_LEN as usize - 4;
+        assert_eq!(&data[movi..movi + 4], b"movi");
+        let idx1 = movi + u32_at(&data, movi - 4) as usize;
+        assert_eq!(&data[idx1..idx1 + 4], b"idx1");
+        assert_eq!(u32_at(&data, idx1 + 4), 32);
+        // 第二帧的偏移跳过了第一帧的补齐字节
+        let second = u32_at(&data, idx1 + 8 + 16 + 8) as usize;
+        assert_eq!(&data[movi + second..movi + second + 4], b"00dc");
+        assert_eq!(u32_at(&data, movi + second + 4), 4);
+    }
+}
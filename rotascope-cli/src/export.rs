@@ -0,0 +1,335 @@
+use crate::avi::AviWriter;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{Delay, ExtendedColorType, Frame, ImageFormat, Rgba, RgbaImage};
+use rotascope_core::recording::{RecordedEvent, RecordingReader};
+use rotascope_core::{ClientMessage, Result};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+/// 叠加层重新编码 AVI 帧时使用的 JPEG 质量
+const JPEG_QUALITY: u8 = 85;
+/// 多数播放器会把小于 20ms 的 GIF 帧间隔当作 100ms
+const MIN_GIF_DELAY_MS: u64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// 动画 GIF
+    Gif,
+    /// MJPEG-in-AVI，未叠加时直接复用录制的 JPEG
+    Avi,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "gif" => Some(Self::Gif),
+            "avi" => Some(Self::Avi),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// 在画面左上角叠加显示器编号、录制时间和最近的传感器数据
+    pub overlay: bool,
+    /// AVI 的固定帧率，帧按原始时间戳重复或丢弃
+    pub fps: u32,
+}
+
+#[derive(Debug)]
+pub struct ExportSummary {
+    /// 录制中的视频帧数
+    pub frames: u64,
+    /// 写入输出文件的帧数
+    pub written: u64,
+    pub duration_ms: u64,
+}
+
+enum ExportFrame {
+    Jpeg(Vec<u8>),
+    Image(RgbaImage),
+}
+
+trait FrameSink {
+    /// GIF 需要重新量化，总是需要解码后的像素
+    fn needs_pixels(&self) -> bool;
+    fn push(&mut self, offset_ms: u64, frame: ExportFrame) -> Result<()>;
+    /// 返回写入的帧数
+    fn finish(self: Box<Self>) -> Result<u64>;
+}
+
+/// 将录制文件导出为 GIF 或 AVI
+pub fn export_recording(input: &Path, output: &Path, options: &ExportOptions) -> Result<ExportSummary> {
+    let file = File::open(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+    let reader = RecordingReader::new(BufReader::new(file)).map_err(|e| format!("{}: {}", input.display(), e))?;
+
+    let mut sink: Option<Box<dyn FrameSink>> = None;
+    let mut canvas = (0, 0);
+    let mut sensor = None;
+    let mut frames = 0u64;
+    // (第一帧, 最后一帧) 的录制偏移
+    let mut span: Option<(u64, u64)> = None;
+
+    for entry in reader {
+        let entry = entry.map_err(|e| format!("{}: {}", input.display(), e))?;
+        let (display_index, width, height, data) = match entry.event {
+            RecordedEvent::Frame {
+                display_index,
+                width,
+                height,
+                data,
+            } => (display_index, width, height, data),
+            RecordedEvent::Client(ClientMessage::SensorData {
+                rotation_x,
+                rotation_y,
+                rotation_z,
+            }) => {
+                sensor = Some((rotation_x, rotation_y, rotation_z));
+                continue;
+            }
+            _ => continue,
+        };
+
+        // 输出尺寸以第一帧为准，切换到不同分辨率的显示器时缩放到同一尺寸
+        let sink = match &mut sink {
+            Some(sink) => sink,
+            None => {
+                canvas = (width, height);
+                sink.insert(create_sink(output, options, canvas, entry.offset_ms)?)
+            }
+        };
+        let first = span.map_or(entry.offset_ms, |(first, _)| first);
+        span = Some((first, entry.offset_ms));
+        frames += 1;
+
+        let frame = if options.overlay || sink.needs_pixels() || (width, height) != canvas {
+            let mut image = image::load_from_memory_with_format(&data, ImageFormat::Jpeg)
+                .map_err(|e| format!("Invalid frame in recording: {}", e))?
+                .to_rgba8();
+            if image.dimensions() != canvas {
+                image = image::imageops::resize(&image, canvas.0, canvas.1, FilterType::Triangle);
+            }
+            if options.overlay {
+                draw_overlay(&mut image, &overlay_lines(display_index, entry.offset_ms.saturating_sub(first), sensor));
+            }
+            ExportFrame::Image(image)
+        } else {
+            ExportFrame::Jpeg(data)
+        };
+        sink.push(entry.offset_ms, frame)?;
+    }
+
+    let Some(sink) = sink else {
+        return Err(format!("{} contains no video frames", input.display()));
+    };
+    let written = sink.finish()?;
+    Ok(ExportSummary {
+        frames,
+        written,
+        duration_ms: span.map_or(0, |(first, last)| last.saturating_sub(first)),
+    })
+}
+
+fn create_sink(output: &Path, options: &ExportOptions, (width, height): (u32, u32), start: u64) -> Result<Box<dyn FrameSink>> {
+    let file = File::create(output).map_err(|e| format!("{}: {}", output.display(), e))?;
+    let out = BufWriter::new(file);
+    Ok(match options.format {
+        ExportFormat::Gif => {
+            let mut encoder = GifEncoder::new_with_speed(out, 10);
+            encoder.set_repeat(Repeat::Infinite).map_err(|e| e.to_string())?;
+            Box::new(GifSink {
+                encoder,
+                pending: None,
+                last_delay: 100,
+                written: 0,
+            })
+        }
+        ExportFormat::Avi => Box::new(AviSink {
+            writer: AviWriter::new(out, width, height, options.fps)?,
+            fps: options.fps.max(1) as u64,
+            start,
+            pending: None,
+        }),
+    })
+}
+
+/// GIF 的每帧带有自己的延时，等到下一帧到来时才知道当前帧应显示多久
+struct GifSink {
+    encoder: GifEncoder<BufWriter<File>>,
+    pending: Option<(u64, RgbaImage)>,
+    last_delay: u64,
+    written: u64,
+}
+
+impl GifSink {
+    fn encode(&mut self, image: RgbaImage, delay_ms: u64) -> Result<()> {
+        let delay = Delay::from_numer_denom_ms(delay_ms.max(MIN_GIF_DELAY_MS) as u32, 1);
+        self.encoder
+            .encode_frame(Frame::from_parts(image, 0, 0, delay))
+            .map_err(|e| e.to_string())?;
+        self.written += 1;
+        Ok(())
+    }
+}
+
+impl FrameSink for GifSink {
+    fn needs_pixels(&self) -> bool {
+        true
+    }
+
+    fn push(&mut self, offset_ms: u64, frame: ExportFrame) -> Result<()> {
+        let ExportFrame::Image(image) = frame else {
+            return Err("GIF export needs decoded frames".to_string());
+        };
+        if let Some((previous_offset, previous)) = self.pending.take() {
+            self.last_delay = offset_ms.saturating_sub(previous_offset);
+            self.encode(previous, self.last_delay)?;
+        }
+        self.pending = Some((offset_ms, image));
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<u64> {
+        // 最后一帧沿用前一个帧间隔
+        if let Some((_, image)) = self.pending.take() {
+            let delay = self.last_delay;
+            self.encode(image, delay)?;
+        }
+        Ok(self.written)
+    }
+}
+
+/// AVI 为固定帧率：每个输出时刻写入该时刻之前最近的一帧
+struct AviSink {
+    writer: AviWriter<BufWriter<File>>,
+    fps: u64,
+    /// 第一帧的录制偏移
+    start: u64,
+    pending: Option<Vec<u8>>,
+}
+
+impl AviSink {
+    fn tick_ms(&self, tick: u64) -> u64 {
+        self.start + tick * 1000 / self.fps
+    }
+}
+
+impl FrameSink for AviSink {
+    fn needs_pixels(&self) -> bool {
+        false
+    }
+
+    fn push(&mut self, offset_ms: u64, frame: ExportFrame) -> Result<()> {
+        let jpeg = match frame {
+            ExportFrame::Jpeg(data) => data,
+            ExportFrame::Image(image) => encode_jpeg(&image)?,
+        };
+        if let Some(previous) = self.pending.take() {
+            // 比帧率更密的帧被丢弃，间隔较长的帧被重复
+            while self.tick_ms(self.writer.frame_count() as u64) < offset_ms {
+                self.writer.write_frame(&previous)?;
+            }
+        }
+        self.pending = Some(jpeg);
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<u64> {
+        if let Some(last) = self.pending.take() {
+            self.writer.write_frame(&last)?;
+        }
+        let written = self.writer.frame_count() as u64;
+        self.writer.finish()?;
+        Ok(written)
+    }
+}
+
+fn encode_jpeg(image: &RgbaImage) -> Result<Vec<u8>> {
+    let rgb: Vec<u8> = image.as_raw().chunks_exact(4).flat_map(|px| [px[0], px[1], px[2]]).collect();
+    let mut out = Vec::new();
+    JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY)
+        .encode(&rgb, image.width(), image.height(), ExtendedColorType::Rgb8)
+        .map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+fn overlay_lines(display_index: u8, elapsed_ms: u64, sensor: Option<(f32, f32, f32)>) -> Vec<String> {
+    let mut lines = vec![format!(
+        "D{} {:02}:{:02}.{:03}",
+        display_index,
+        elapsed_ms / 60_000,
+        elapsed_ms / 1000 % 60,
+        elapsed_ms % 1000
+    )];
+    if let Some((x, y, z)) = sensor {
+        lines.push(format!("X{:.1} Y{:.1} Z{:.1}", x, y, z));
+    }
+    lines
+}
+
+/// 3x5 点阵字体，只包含叠加层用到的字符，每行低 3 位从左到右
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        _ => [0; 5],
+    }
+}
+
+/// 在左上角的半透明暗色背景上绘制白色文字
+fn draw_overlay(image: &mut RgbaImage, lines: &[String]) {
+    let scale = (image.width() / 240).max(1);
+    let advance = 4 * scale;
+    let line_height = 7 * scale;
+    let text_width = lines.iter().map(|l| l.chars().count() as u32).max().unwrap_or(0) * advance;
+    let box_width = (text_width + 3 * scale).min(image.width());
+    let box_height = (lines.len() as u32 * line_height + 2 * scale).min(image.height());
+
+    for y in 0..box_height {
+        for x in 0..box_width {
+            let Rgba([r, g, b, a]) = *image.get_pixel(x, y);
+            image.put_pixel(x, y, Rgba([r / 3, g / 3, b / 3, a]));
+        }
+    }
+    for (row, line) in lines.iter().enumerate() {
+        let top = 2 * scale + row as u32 * line_height;
+        for (col, c) in line.chars().enumerate() {
+            let left = 2 * scale + col as u32 * advance;
+            for (gy, bits) in glyph(c).iter().enumerate() {
+                for gx in 0..3 {
+                    if bits & (0b100 >> gx) == 0 {
+                        continue;
+                    }
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            let (x, y) = (left + gx * scale + dx, top + gy as u32 * scale + dy);
+                            if x < image.width() && y < image.height() {
+                                image.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,372 @@
+mod avi;
+mod export;
+mod script;
+
+use clap::{Args, Parser, Subcommand};
+use rotascope_core::client::{ClientAuth, ClientOptions, RotascopeClient};
+use rotascope_core::discovery::discover;
+use rotascope_core::{Result, ServerMessage};
+use export::{ExportFormat, ExportOptions, export_recording};
+use script::{Step, parse_script};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+#[derive(Parser, Debug)]
+#[command(name = "rotascope-cli", version, about = "RotaScope 命令行调试客户端")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 在局域网中查找服务端
+    Discover {
+        /// 等待时间（秒）
+        #[arg(long, default_value_t = 3)]
+        timeout: u64,
+    },
+    /// 连接服务端，打印配置和统计信息
+    Connect(Box<ConnectArgs>),
+    /// 将服务端录制的 .rsrec 文件导出为 GIF 或 MJPEG AVI
+    Export(ExportArgs),
+}
+
+#[derive(Args, Debug)]
+struct ConnectArgs {
+    /// 服务端地址，例如 ws://192.168.1.10:8080 或 wss://192.168.1.10:8080
+    url: String,
+    /// 首次配对使用的配对码
+    #[arg(long, conflicts_with = "device_id")]
+    pair: Option<String>,
+    /// 已配对设备的 ID，与 --token 一起使用
+    #[arg(long, requires = "token")]
+    device_id: Option<String>,
+    #[arg(long, requires = "device_id")]
+    token: Option<String>,
+    /// 服务端证书指纹，wss:// 连接必需
+    #[arg(long)]
+    fingerprint: Option<String>,
+    #[arg(long, default_value = "rotascope-cli")]
+    name: String,
+    /// 恢复之前断开的会话（连接时打印的会话 ID）
+    #[arg(long, value_name = "SESSION_ID")]
+    resume: Option<String>,
+    /// 将收到的帧保存为 <DIR>/frame-000001.jpg ...
+    #[arg(long, value_name = "DIR")]
+    out: Option<PathBuf>,
+    /// 收到指定数量的帧后退出
+    #[arg(long)]
+    frames: Option<u64>,
+    /// 运行指定秒数后退出
+    #[arg(long)]
+    duration: Option<f64>,
+    /// 按脚本发送 SensorData / SwitchDisplay，格式见 script.rs
+    #[arg(long)]
+    script: Option<PathBuf>,
+    /// 统计信息的打印间隔（秒）
+    #[arg(long, default_value_t = 1.0)]
+    stats_interval: f64,
+}
+
+#[derive(Args, Debug)]
+struct ExportArgs {
+    /// 录制文件
+    input: PathBuf,
+    /// 输出文件，格式由扩展名 .gif / .avi 决定
+    output: PathBuf,
+    /// 指定输出格式，忽略扩展名
+    #[arg(long, value_enum)]
+    format: Option<ExportFormat>,
+    /// 叠加显示器编号、录制时间和传感器数据
+    #[arg(long)]
+    overlay: bool,
+    /// AVI 的帧率
+    #[arg(long, default_value_t = 30)]
+    fps: u32,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Discover { timeout } => run_discover(timeout).await,
+        Command::Connect(args) => run_connect(*args).await,
+        Command::Export(args) => run_export(args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run_discover(timeout: u64) -> Result<()> {
+    let servers = discover(Duration::from_secs(timeout), false).await?;
+    if servers.is_empty() {
+        println!("No servers found");
+    }
+    for server in servers {
+        let announcement = &server.announcement;
+        println!(
+            "{}  {}  protocol {}  {} displays{}",
+            announcement.name,
+            server.url().unwrap_or_else(|| "-".to_string()),
+            announcement.protocol_version,
+            announcement.display_count,
+            announcement
+                .tls_fingerprint
+                .as_ref()
+                .map(|fp| format!("  fingerprint {}", fp))
+                .unwrap_or_default(),
+        );
+    }
+    Ok(())
+}
+
+fn run_export(args: ExportArgs) -> Result<()> {
+    let format = args
+        .format
+        .or_else(|| ExportFormat::from_path(&args.output))
+        .ok_or_else(|| format!("Cannot tell the format of {}, use --format gif|avi", args.output.display()))?;
+    let options = ExportOptions {
+        format,
+        overlay: args.overlay,
+        fps: args.fps,
+    };
+    let summary = export_recording(&args.input, &args.output, &options)?;
+    println!(
+        "Exported {} frames ({:.1}s) to {} ({} frames written)",
+        summary.frames,
+        summary.duration_ms as f64 / 1000.0,
+        args.output.display(),
+        summary.written
+    );
+    Ok(())
+}
+
+async fn run_connect(args: ConnectArgs) -> Result<()> {
+    let auth = match (&args.pair, &args.device_id, &args.token) {
+        (Some(code), _, _) => ClientAuth::PairingCode(code.clone()),
+        (None, Some(device_id), Some(token)) => ClientAuth::DeviceToken {
+            device_id: device_id.clone(),
+            token: token.clone(),
+        },
+        _ => ClientAuth::None,
+    };
+    let mut steps = match &args.script {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            parse_script(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        None => Vec::new(),
+    }
+    .into_iter();
+    if let Some(dir) = &args.out {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+
+    let options = ClientOptions {
+        tls_fingerprint: args.fingerprint.clone(),
+        ..ClientOptions::new(&args.name, auth)
+    };
+    let mut client = RotascopeClient::connect(&args.url, options).await?;
+    println!("Connected to {}", args.url);
+    if let Some(device_id) = client.device_id() {
+        println!("Authenticated as device {}", device_id);
+        if let Some(token) = client.issued_token() {
+            println!("Paired, reconnect with --device-id {} --token {}", device_id, token);
+        }
+    }
+
+    if let Some(session_id) = &args.resume {
+        client.resume(session_id).await?;
+    }
+
+    let started = Instant::now();
+    let deadline = args.duration.map(|secs| started + Duration::from_secs_f64(secs));
+    let mut stats = Stats::default();
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(args.stats_interval.max(0.1)));
+    ticker.tick().await;
+    let mut next_step_at = Some(Instant::now());
+
+    let result = 'session: loop {
+        let script_at = next_step_at;
+        tokio::select! {
+            message = client.recv() => {
+                let Some(message) = message else {
+                    println!("Server closed the connection");
+                    break Ok(());
+                };
+                if let Err(e) = handle_message(message, &args, &mut stats) {
+                    break Err(e);
+                }
+                if args.frames.is_some_and(|limit| stats.frames >= limit) {
+                    break Ok(());
+                }
+            }
+            _ = sleep_until(script_at) => {
+                next_step_at = None;
+                for step in steps.by_ref() {
+                    match step {
+                        Step::Wait(duration) => {
+                            next_step_at = Some(Instant::now() + duration);
+                            break;
+                        }
+                        Step::Send(message) => {
+                            if let Err(e) = client.send(&message).await {
+                                break 'session Err(e);
+                            }
+                        }
+                    }
+                }
+            }
+            _ = ticker.tick() => stats.print_interval(),
+            _ = sleep_until(deadline) => break Ok(()),
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+        }
+    };
+
+    stats.print_summary(started.elapsed());
+    let _ = client.close().await;
+    result
+}
+
+async fn sleep_until(at: Option<Instant>) {
+    match at {
+        Some(at) => tokio::time::sleep_until(at.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+fn handle_message(message: ServerMessage, args: &ConnectArgs, stats: &mut Stats) -> Result<()> {
+    match message {
+        ServerMessage::DisplayConfig {
+            total_displays,
+            current_display,
+            resolutions,
+            layout,
+            arrangement,
+        } => {
+            println!(
+                "DisplayConfig: {} displays, current {}, resolutions {:?}, arrangement {:?}",
+                total_displays, current_display, resolutions, arrangement
+            );
+            for (index, display) in layout.iter().enumerate() {
+                println!(
+                    "  [{}] {} at ({}, {}), rotation {}°, scale {}",
+                    index, display.name, display.x, display.y, display.rotation, display.scale
+                );
+            }
+        }
+        ServerMessage::VideoFrame {
+            display_index,
+            width,
+            height,
+            data,
+            ..
+        } => {
+            if width == 0 || height == 0 {
+                return Err(format!("Received a {} byte video frame that is not a JPEG image", data.len()));
+            }
+            stats.frames += 1;
+            stats.bytes += data.len() as u64;
+            stats.last_frame = Some((display_index, width, height));
+            if let Some(dir) = &args.out {
+                let path = dir.join(format!("frame-{:06}.jpg", stats.frames));
+                std::fs::write(&path, &data).map_err(|e| format!("{}: {}", path.display(), e))?;
+            }
+        }
+        ServerMessage::AudioChunk { data, .. } => {
+            stats.audio_chunks += 1;
+            stats.bytes += data.len() as u64;
+        }
+        ServerMessage::ClipboardUpdate { mime_type, data } => {
+            println!("ClipboardUpdate: {} ({} bytes)", mime_type, data.len());
+        }
+        ServerMessage::RecordingState { active, file } => match (active, file) {
+            (true, Some(file)) => println!("Recording to {}", file),
+            (false, Some(file)) => println!("Recording saved to {}", file),
+            _ => println!("Recording {}", if active { "started" } else { "stopped" }),
+        },
+        ServerMessage::ZoomChanged { display_index, zoom } => {
+            println!("Zoom on display {}: {:?}", display_index, zoom);
+        }
+        ServerMessage::WindowList { windows } => {
+            println!("{} windows:", windows.len());
+            for window in windows {
+                println!("  {:#x} {}x{} {}", window.id, window.width, window.height, window.title);
+            }
+        }
+        ServerMessage::DisplaySwitched { current_display } => {
+            println!("Current display: {}", current_display);
+        }
+        ServerMessage::SessionInfo {
+            session_id,
+            resumed,
+            resume_grace_secs,
+        } => {
+            if resumed {
+                println!("Resumed session {}", session_id);
+            } else {
+                println!(
+                    "Session {}, after a drop reconnect within {}s with --resume {}",
+                    session_id, resume_grace_secs, session_id
+                );
+            }
+        }
+        ServerMessage::Heartbeat => {}
+        ServerMessage::Error { message } => return Err(format!("Server error: {}", message)),
+        ServerMessage::AuthChallenge { .. } | ServerMessage::AuthAccepted { .. } => {
+            return Err("Unexpected handshake message after authentication".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    frames: u64,
+    audio_chunks: u64,
+    bytes: u64,
+    /// 最近一帧的 (显示器, 宽, 高)
+    last_frame: Option<(u8, u32, u32)>,
+    /// 上次打印时的 (帧数, 字节数, 时刻)
+    last_print: Option<(u64, u64, Instant)>,
+}
+
+impl Stats {
+    fn print_interval(&mut self) {
+        let now = Instant::now();
+        let (frames, bytes, since) = self.last_print.unwrap_or((0, 0, now));
+        self.last_print = Some((self.frames, self.bytes, now));
+        let secs = now.duration_since(since).as_secs_f64();
+        if secs <= 0.0 {
+            return;
+        }
+        let fps = (self.frames - frames) as f64 / secs;
+        let kbps = (self.bytes - bytes) as f64 * 8.0 / 1000.0 / secs;
+        match self.last_frame {
+            Some((display, width, height)) => println!(
+                "{:.1} fps  {:.0} kbit/s  display {}  {}x{}  ({} frames)",
+                fps, kbps, display, width, height, self.frames
+            ),
+            None => println!("{:.1} fps  {:.0} kbit/s  (no frames yet)", fps, kbps),
+        }
+    }
+
+    fn print_summary(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        println!(
+            "Received {} frames, {} audio chunks, {} bytes in {:.1}s ({:.1} fps)",
+            self.frames,
+            self.audio_chunks,
+            self.bytes,
+            secs,
+            self.frames as f64 / secs
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use rotascope_core::{Arrangement, ClientMessage, Result, SwitchDirection, WindowSelector, Zoom};
+use std::time::Duration;
+
+/// 脚本中的一步：等待一段时间或发送一条消息
+#[derive(Debug)]
+pub enum Step {
+    Wait(Duration),
+    Send(ClientMessage),
+}
+
+/// 解析脚本文件，每行一条指令，`#` 之后为注释：
+///
+/// ```text
+/// wait 500          # 毫秒
+/// switch next       # 或 previous、last；left/right/up/down 按显示器布局切换
+/// switch 2          # 切换到显示器 2
+/// sensor 0 35 0     # rotation_x rotation_y rotation_z
+/// heartbeat
+/// audio on          # 接收桌面音频，或 audio off
+/// record on         # 请求服务端录制本会话，或 record off
+/// resize 1 1280 720 # 调整显示器 1 的分辨率
+/// create 1280 720 work  # 新建显示器，名称可省略
+/// destroy 3         # 销毁客户端创建的显示器
+/// zoom 640 360 2    # 以 (640, 360) 为中心放大 2 倍；zoom rect X Y W H 放大矩形，zoom off 取消
+/// windows           # 列出服务端的窗口
+/// window 0x3a00007  # 把窗口作为新显示器推流，也可以写窗口标题
+/// arrange grid 2    # 或 arrange row、arrange cylinder 30（相邻显示器间隔的角度）
+/// ```
+pub fn parse_script(text: &str) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let step = parse_line(line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+fn parse_line(line: &str) -> Result<Step> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let args: Vec<&str> = words.collect();
+    let number = |s: &str| s.parse::<f32>().map_err(|_| format!("invalid number '{}'", s));
+
+    let step = match (command, args.as_slice()) {
+        ("wait", [ms]) => {
+            let ms = ms.parse::<u64>().map_err(|_| format!("invalid duration '{}'", ms))?;
+            Step::Wait(Duration::from_millis(ms))
+        }
+        ("switch", ["next"]) => Step::Send(ClientMessage::SwitchDisplay {
+            direction: SwitchDirection::Next,
+        }),
+        ("switch", ["previous"]) => Step::Send(ClientMessage::SwitchDisplay {
+            direction: SwitchDirection::Previous,
+        }),
+        ("switch", ["last"]) => Step::Send(ClientMessage::SwitchDisplay {
+            direction: SwitchDirection::Last,
+        }),
+        ("switch", [direction @ ("left" | "right" | "up" | "down")]) => Step::Send(ClientMessage::SwitchDisplay {
+            direction: match *direction {
+                "left" => SwitchDirection::Left,
+                "right" => SwitchDirection::Right,
+                "up" => SwitchDirection::Up,
+                _ => SwitchDirection::Down,
+            },
+        }),
+        ("sensor", [x, y, z]) => Step::Send(ClientMessage::SensorData {
+            rotation_x: number(x)?,
+            rotation_y: number(y)?,
+            rotation_z: number(z)?,
+        }),
+        ("heartbeat", []) => Step::Send(ClientMessage::Heartbeat),
+        ("audio", ["on"]) => Step::Send(ClientMessage::SetAudio { enabled: true }),
+        ("audio", ["off"]) => Step::Send(ClientMessage::SetAudio { enabled: false }),
+        ("record", ["on"]) => Step::Send(ClientMessage::SetRecording { enabled: true }),
+        ("record", ["off"]) => Step::Send(ClientMessage::SetRecording { enabled: false }),
+        ("resize", [index, width, height]) => {
+            let integer = |s: &str| s.parse::<u32>().map_err(|_| format!("invalid integer '{}'", s));
+            Step::Send(ClientMessage::ResizeDisplay {
+                index: index.parse().map_err(|_| format!("invalid display index '{}'", index))?,
+                width: integer(width)?,
+                height: integer(height)?,
+            })
+        }
+        ("create", [width, height, name @ ..]) => {
+            let integer = |s: &str| s.parse::<u32>().map_err(|_| format!("invalid integer '{}'", s));
+            Step::Send(ClientMessage::CreateDisplay {
+                width: integer(width)?,
+                height: integer(height)?,
+                name: name.join(" "),
+            })
+        }
+        ("destroy", [index]) => Step::Send(ClientMessage::DestroyDisplay {
+            index: index.parse().map_err(|_| format!("invalid display index '{}'", index))?,
+        }),
+        ("zoom", ["off"]) => Step::Send(ClientMessage::SetZoom { zoom: Zoom::Off }),
+        ("zoom", ["rect", x, y, width, height]) => {
+            let integer = |s: &str| s.parse::<u32>().map_err(|_| format!("invalid integer '{}'", s));
+            Step::Send(ClientMessage::SetZoom {
+                zoom: Zoom::Rect {
+                    x: integer(x)?,
+                    y: integer(y)?,
+                    width: integer(width)?,
+                    height: integer(height)?,
+                },
+            })
+        }
+        ("zoom", [x, y, factor]) => {
+            let integer = |s: &str| s.parse::<u32>().map_err(|_| format!("invalid integer '{}'", s));
+            Step::Send(ClientMessage::SetZoom {
+                zoom: Zoom::Around {
+                    x: integer(x)?,
+                    y: integer(y)?,
+                    factor: number(factor)?,
+                },
+            })
+        }
+        ("windows", []) => Step::Send(ClientMessage::ListWindows),
+        ("window", [id]) if id.starts_with("0x") => Step::Send(ClientMessage::CaptureWindow {
+            window: WindowSelector::Id(
+                u32::from_str_radix(&id[2..], 16).map_err(|_| format!("invalid window id '{}'", id))?,
+            ),
+        }),
+        ("window", [_, ..]) => Step::Send(ClientMessage::CaptureWindow {
+            window: WindowSelector::Title(args.join(" ")),
+        }),
+        ("arrange", ["row"]) => Step::Send(ClientMessage::ArrangeDisplays {
+            arrangement: Arrangement::Row,
+        }),
+        ("arrange", ["grid", columns]) => Step::Send(ClientMessage::ArrangeDisplays {
+            arrangement: Arrangement::Grid {
+                columns: columns.parse().map_err(|_| format!("invalid column count '{}'", columns))?,
+            },
+        }),
+        ("arrange", ["cylinder", degrees]) => Step::Send(ClientMessage::ArrangeDisplays {
+            arrangement: Arrangement::Cylinder {
+                spacing_degrees: number(degrees)?,
+            },
+        }),
+        ("switch", [index]) => Step::Send(ClientMessage::SwitchDisplay {
+            direction: SwitchDirection::Goto(index.parse().map_err(|_| format!("invalid display index '{}'", index))?),
+        }),
+        _ => return Err(format!("unrecognized command '{}'", line)),
+    };
+    Ok(step)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_and_reports_line_numbers() {
+        let steps = parse_script("# demo\nwait 250\nswitch next\n\nsensor 0 -35.5 0 # tilt\nheartbeat\nrecord on\nresize 1 800 600\ncreate 640 480 my desk\nswitch up\narrange grid 2\nswitch 3\nswitch last\nwindow 0x2a\nwindow my editor\nzoom 10 20 2.5\nzoom rect 0 0 320 240\n").unwrap();
+        assert_eq!(steps.len(), 15);
+        assert!(matches!(steps[0], Step::Wait(d) if d == Duration::from_millis(250)));
+        assert!(matches!(
+            steps[1],
+            Step::Send(ClientMessage::SwitchDisplay { direction: SwitchDirection::Next })
+        ));
+        assert!(matches!(
+            steps[2],
+            Step::Send(ClientMessage::SensorData { rotation_y, .. }) if rotation_y == -35.5
+        ));
+        assert!(matches!(steps[4], Step::Send(ClientMessage::SetRecording { enabled: true })));
+        assert!(matches!(
+            steps[5],
+            Step::Send(ClientMessage::ResizeDisplay { index: 1, width: 800, height: 600 })
+        ));
+        assert!(matches!(
+            &steps[6],
+            Step::Send(ClientMessage::CreateDisplay { width: 640, name, .. }) if name == "my desk"
+        ));
+
+        assert!(matches!(
+            steps[7],
+            Step::Send(ClientMessage::SwitchDisplay { direction: SwitchDirection::Up })
+        ));
+        assert!(matches!(
+            steps[8],
+            Step::Send(ClientMessage::ArrangeDisplays { arrangement: Arrangement::Grid { columns: 2 } })
+        ));
+
+        assert!(matches!(
+            steps[9],
+            Step::Send(ClientMessage::SwitchDisplay { direction: SwitchDirection::Goto(3) })
+        ));
+        assert!(matches!(
+            steps[10],
+            Step::Send(ClientMessage::SwitchDisplay { direction: SwitchDirection::Last })
+        ));
+
+        assert!(matches!(
+            steps[11],
+            Step::Send(ClientMessage::CaptureWindow { window: WindowSelector::Id(0x2a) })
+        ));
+        assert!(matches!(
+            &steps[12],
+            Step::Send(ClientMessage::CaptureWindow { window: WindowSelector::Title(title) }) if title == "my editor"
+        ));
+
+        assert!(matches!(
+            steps[13],
+            Step::Send(ClientMessage::SetZoom { zoom: Zoom::Around { x: 10, y: 20, factor } }) if factor == 2.5
+        ));
+        assert!(matches!(
+            steps[14],
+            Step::Send(ClientMessage::SetZoom { zoom: Zoom::Rect { width: 320, height: 240, .. } })
+        ));
+
+        let err = parse_script("wait 10\nswitch sideways\n").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+    }
+}
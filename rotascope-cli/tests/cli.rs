@@ -0,0 +1,171 @@
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, ExtendedColorType};
+use rotascope_core::recording::{RecordingHeader, RecordingWriter};
+use rotascope_core::{Arrangement, ClientMessage, ServerMessage};
+use rotascope_server::config::ServerConfig;
+use rotascope_server::video::TestPatternSource;
+use rotascope_server::{ServerBuilder, ServerEvent, ServerHandle};
+use std::path::PathBuf;
+use std::process::Output;
+use tokio::process::Command;
+
+async fn start(auth_required: bool) -> ServerHandle {
+    let mut config = ServerConfig::default();
+    config.auth.required = auth_required;
+    config.auth.trust_store = temp_path("trust.json").to_string_lossy().into_owned();
+
+    ServerBuilder::new()
+        .config(config)
+        .listen_addr("127.0.0.1:0")
+        .displays(vec![(0, 64, 48), (1, 64, 48)])
+        .capture_source(|| Ok(Box::new(TestPatternSource::new(64, 48, 30))))
+        .start()
+        .await
+        .unwrap()
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rotascope-cli-{}-{}", std::process::id(), name))
+}
+
+async fn cli(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rotascope-cli"))
+        .args(args)
+        .output()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn dumps_frames_and_runs_script() {
+    let server = start(false).await;
+    let mut events = server.subscribe();
+    let out = temp_path("frames");
+    let script = temp_path("script.txt");
+    std::fs::write(&script, "switch next\nwait 10\nheartbeat\n").unwrap();
+
+    let url = format!("ws://{}", server.local_addr());
+    let output = cli(&[
+        "connect",
+        &url,
+        "--frames",
+        "3",
+        "--out",
+        out.to_str().unwrap(),
+        "--script",
+        script.to_str().unwrap(),
+    ])
+    .await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("DisplayConfig: 2 displays"), "{}", stdout);
+
+    for n in 1..=3 {
+        let frame = std::fs::read(out.join(format!("frame-{:06}.jpg", n))).unwrap();
+        assert!(frame.starts_with(&[0xff, 0xd8]));
+    }
+    // 脚本中的切换请求可能在 CLI 退出后才被服务端处理完
+    let switched = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while let Ok(event) = events.recv().await {
+            if event == (ServerEvent::DisplaySwitched { display: 1 }) {
+                return;
+            }
+        }
+    });
+    switched.await.expect("script switched the display");
+
+    server.shutdown();
+    server.wait().await.unwrap();
+    let _ = std::fs::remove_dir_all(out);
+    let _ = std::fs::remove_file(script);
+}
+
+#[tokio::test]
+async fn exits_non_zero_when_handshake_fails() {
+    let server = start(true).await;
+    let url = format!("ws://{}", server.local_addr());
+
+    let output = cli(&["connect", &url, "--frames", "1"]).await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("requires pairing"));
+
+    let output = cli(&["connect", &url, "--pair", "not-a-code", "--frames", "1"]).await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Authentication failed"));
+
+    server.shutdown();
+    server.wait().await.unwrap();
+    let _ = std::fs::remove_file(temp_path("trust.json"));
+}
+
+fn jpeg(width: u32, height: u32, shade: u8) -> Vec<u8> {
+    let mut out = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new(&mut out)
+        .encode(&vec![shade; (width * height * 3) as usize], width, height, ExtendedColorType::Rgb8)
+        .unwrap();
+    out
+}
+
+#[tokio::test]
+async fn exports_recording_to_gif_and_avi() {
+    let config = ServerMessage::DisplayConfig {
+        total_displays: 2,
+        current_display: 0,
+        resolutions: vec![(64, 48), (32, 24)],
+        layout: Vec::new(),
+        arrangement: Arrangement::Row,
+    };
+    let header = RecordingHeader::new(1_000, Some("phone".to_string()), &config).unwrap();
+    let input = temp_path("session.rsrec");
+    let mut writer = RecordingWriter::new(std::fs::File::create(&input).unwrap(), &header).unwrap();
+    writer.write_frame(1_000, 0, 64, 48, &jpeg(64, 48, 200)).unwrap();
+    let sensor = ClientMessage::SensorData {
+        rotation_x: 0.0,
+        rotation_y: -35.5,
+        rotation_z: 0.0,
+    };
+    writer.write_client(1_050, &sensor).unwrap();
+    writer.write_frame(1_100, 0, 64, 48, &jpeg(64, 48, 200)).unwrap();
+    // 切换到分辨率不同的显示器
+    writer.write_frame(1_300, 1, 32, 24, &jpeg(32, 24, 200)).unwrap();
+    writer.into_inner().unwrap();
+
+    let gif = temp_path("session.gif");
+    let output = cli(&["export", input.to_str().unwrap(), gif.to_str().unwrap(), "--overlay"]).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Exported 3 frames (0.3s)"));
+
+    let decoder = GifDecoder::new(std::io::BufReader::new(std::fs::File::open(&gif).unwrap())).unwrap();
+    let frames = decoder.into_frames().collect_frames().unwrap();
+    let delays: Vec<u32> = frames
+        .iter()
+        .map(|f| {
+            let (numer, denom) = f.delay().numer_denom_ms();
+            numer / denom
+        })
+        .collect();
+    assert_eq!(delays, vec![100, 200, 200]);
+    for frame in &frames {
+        let image = frame.buffer();
+        assert_eq!(image.dimensions(), (64, 48));
+        // 叠加层的背景压暗了左上角，其余画面不变
+        assert!(image.get_pixel(0, 0)[0] < 100);
+        assert!(image.get_pixel(60, 44)[0] > 150);
+    }
+
+    let avi = temp_path("session.avi");
+    let output = cli(&["export", input.to_str().unwrap(), avi.to_str().unwrap(), "--fps", "10"]).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let data = std::fs::read(&avi).unwrap();
+    assert_eq!(&data[0..4], b"RIFF");
+    assert_eq!(&data[8..12], b"AVI ");
+    // 10 fps 下 0/100/200/300ms 各一帧，第二帧在 200ms 处重复
+    assert_eq!(u32::from_le_bytes(data[48..52].try_into().unwrap()), 4);
+
+    let output = cli(&["export", input.to_str().unwrap(), temp_path("session.mp4").to_str().unwrap()]).await;
+    assert!(!output.status.success());
+
+    for path in [input, gif, avi] {
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -10,4 +10,132 @@ mod tests {
 
         assert_eq!(4, 4);
     }
+
+    #[test]
+    fn clipboard_update_roundtrip() {
+        let msg = ClientMessage::ClipboardUpdate {
+            mime_type: clipboard::MIME_TEXT.to_string(),
+            data: b"cargo test".to_vec(),
+        };
+        let bytes = serialize_message(&msg).unwrap();
+        match deserialize_message::<ClientMessage>(&bytes).unwrap() {
+            ClientMessage::ClipboardUpdate { mime_type, data } => {
+                assert_eq!(mime_type, clipboard::MIME_TEXT);
+                assert_eq!(data, b"cargo test");
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn clipboard_validation() {
+        use clipboard::*;
+        assert!(validate_clipboard(MIME_TEXT, b"hello", 16).is_ok());
+        assert!(validate_clipboard(MIME_TEXT, b"hello", 4).is_err());
+        assert!(validate_clipboard(MIME_TEXT, &[0xff, 0xfe], 16).is_err());
+        assert!(validate_clipboard(MIME_PNG, b"\x89PNG\r\n\x1a\n....", 64).is_ok());
+        assert!(validate_clipboard(MIME_PNG, b"GIF89a", 64).is_err());
+        assert!(validate_clipboard("application/zip", b"PK", 64).is_err());
+    }
+
+    #[test]
+    fn audio_chunk_binary_roundtrip() {
+        let bytes = media::encode_audio(AudioCodec::Pcm16, 48_000, 2, 1_234, &[1, 2, 3, 4]);
+        assert_eq!(bytes.len(), 15 + 4);
+        match media::decode(&bytes).unwrap() {
+            ServerMessage::AudioChunk { codec, sample_rate, channels, data, timestamp } => {
+                assert_eq!((codec, sample_rate, channels, timestamp), (AudioCodec::Pcm16, 48_000, 2, 1_234));
+                assert_eq!(data, [1, 2, 3, 4]);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+        assert!(media::decode(&bytes[..10]).is_err());
+        assert!(media::decode(b"{}").is_err());
+    }
+
+    #[test]
+    fn video_frame_binary_roundtrip() {
+        let bytes = media::encode_video(2, 64, 48, 5_678, &[0xff, 0xd8]);
+        assert_eq!(bytes.len(), 18 + 2);
+        match media::decode(&bytes).unwrap() {
+            ServerMessage::VideoFrame { display_index, width, height, data, timestamp } => {
+                assert_eq!((display_index, width, height, timestamp), (2, 64, 48, 5_678));
+                assert_eq!(data, [0xff, 0xd8]);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+        assert!(media::decode(&bytes[..17]).is_err());
+    }
+
+    #[test]
+    fn auth_proof_verification() {
+        let proof = auth::auth_proof("123456", "nonce-1");
+        assert!(auth::verify_auth_proof("123456", "nonce-1", &proof));
+        assert!(!auth::verify_auth_proof("654321", "nonce-1", &proof));
+        assert!(!auth::verify_auth_proof("123456", "nonce-2", &proof));
+        assert!(!auth::verify_auth_proof("123456", "nonce-1", "not hex"));
+    }
+
+    #[test]
+    fn jpeg_dimensions_from_frame_header() {
+        // SOI, APP0(长度 4), SOF0: 精度 8, 高 48, 宽 64
+        let jpeg = [
+            0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x00, 0x00, 0xff, 0xc0, 0x00, 0x11, 0x08, 0x00,
+            0x30, 0x00, 0x40, 0x03,
+        ];
+        assert_eq!(client::jpeg_dimensions(&jpeg), Some((64, 48)));
+        assert_eq!(client::jpeg_dimensions(b"{\"Heartbeat\"}"), None);
+    }
+
+    #[test]
+    fn recording_roundtrip() {
+        use recording::*;
+        let config = ServerMessage::DisplayConfig {
+            total_displays: 2,
+            current_display: 1,
+            resolutions: vec![(64, 48), (64, 48)],
+            layout: Vec::new(),
+            arrangement: Arrangement::Row,
+        };
+        let header = RecordingHeader::new(1_000, Some("phone".to_string()), &config).unwrap();
+        let mut writer = RecordingWriter::new(Vec::new(), &header).unwrap();
+        writer
+            .write(1_040, &RecordedEvent::Frame { display_index: 1, width: 64, height: 48, data: vec![0xff, 0xd8] })
+            .unwrap();
+        writer.write(1_050, &RecordedEvent::Client(ClientMessage::Heartbeat)).unwrap();
+        let bytes = writer.into_inner().unwrap();
+
+        let mut reader = RecordingReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.header(), &header);
+        let entries: Vec<RecordEntry> = reader.by_ref().collect::<Result<_>>().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].offset_ms, 40);
+        assert!(matches!(
+            &entries[0].event,
+            RecordedEvent::Frame { display_index: 1, width: 64, height: 48, data } if data == &[0xff, 0xd8]
+        ));
+        assert!(matches!(entries[1].event, RecordedEvent::Client(ClientMessage::Heartbeat)));
+
+        // 截断的记录报错而不是静默结束
+        let mut truncated = RecordingReader::new(&bytes[..bytes.len() - 1]).unwrap();
+        assert!(truncated.next().unwrap().is_ok());
+        assert!(truncated.next().unwrap().is_err());
+        assert!(RecordingReader::new(&b"not a recording"[..]).is_err());
+    }
+
+    #[tokio::test]
+    async fn discovery_over_loopback_multicast() {
+        use discovery::*;
+        let name = format!("rotascope-test-{}", std::process::id());
+        let announcement = ServiceAnnouncement::new(&name, 18080, 3, Some("ab12"));
+        let _advertiser = ServiceAdvertiser::start(&announcement, true).unwrap();
+
+        let servers = discover(std::time::Duration::from_secs(3), true).await.unwrap();
+        let server = servers
+            .iter()
+            .find(|s| s.announcement.name == name)
+            .expect("advertised server is discovered");
+        assert_eq!(server.announcement, announcement);
+        assert!(server.url().unwrap().starts_with("wss://"));
+    }
 }
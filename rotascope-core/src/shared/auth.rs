@@ -0,0 +1,27 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 握手证明：HMAC-SHA256(secret, nonce) 的十六进制表示。
+/// secret 是配对码或设备令牌，本身不会在连接上传输。
+pub fn auth_proof(secret: &str, nonce: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(nonce.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// 以常量时间校验客户端给出的证明
+pub fn verify_auth_proof(secret: &str, nonce: &str, proof: &str) -> bool {
+    let Ok(proof) = hex::decode(proof) else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(nonce.as_bytes());
+    mac.verify_slice(&proof).is_ok()
+}
+
+/// TLS 证书指纹：DER 编码证书的 SHA-256（小写十六进制），用于配对时固定服务端证书
+pub fn certificate_fingerprint(der: &[u8]) -> String {
+    hex::encode(Sha256::digest(der))
+}
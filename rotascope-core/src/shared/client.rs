@@ -0,0 +1,435 @@
+use crate::auth::{auth_proof, certificate_fingerprint};
+use crate::media;
+use crate::{
+    ClientMessage, Credentials, Result, ServerMessage, SwitchDirection, WindowSelector, Zoom,
+    deserialize_message, serialize_message, timestamp_millis,
+};
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsConnector;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::{Message, Utf8Bytes};
+use tokio_tungstenite::{WebSocketStream, client_async};
+
+trait ClientIo: AsyncRead + AsyncWrite + Unpin + Send + std::fmt::Debug {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + std::fmt::Debug> ClientIo for T {}
+
+type WsStream = WebSocketStream<Box<dyn ClientIo>>;
+
+/// 连接时使用的凭据
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// 服务端未开启认证
+    None,
+    /// 首次配对使用服务端显示的配对码
+    PairingCode(String),
+    /// 已配对设备使用配对时拿到的令牌
+    DeviceToken { device_id: String, token: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    pub device_name: String,
+    pub auth: ClientAuth,
+    /// 服务端证书指纹；wss:// 连接必须提供，只接受该证书
+    pub tls_fingerprint: Option<String>,
+    /// 建立连接和完成握手的超时时间
+    pub timeout: Duration,
+}
+
+impl ClientOptions {
+    pub fn new(device_name: &str, auth: ClientAuth) -> Self {
+        Self {
+            device_name: device_name.to_string(),
+            auth,
+            tls_fingerprint: None,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// RotaScope 的 Rust 客户端：完成握手后持续接收画面和配置
+///
+/// 视频帧带有显示器编号、宽高和服务端时间戳（见 [`media`]）。旧版本的服务端只发送裸 JPEG，
+/// 此时宽高从 JPEG 头读取，显示器编号取最近一次 DisplayConfig，时间戳为接收时刻。
+#[derive(Debug)]
+pub struct RotascopeClient {
+    writer: SplitSink<WsStream, Message>,
+    messages: mpsc::Receiver<ServerMessage>,
+    reader: JoinHandle<()>,
+    device_id: Option<String>,
+    issued_token: Option<String>,
+    /// 最近一次 SessionInfo 中的会话 ID，由读取任务更新
+    session_id: Arc<Mutex<Option<String>>>,
+}
+
+impl RotascopeClient {
+    /// 连接 ws:// 或 wss:// 地址并完成认证握手
+    pub async fn connect(url: &str, options: ClientOptions) -> Result<Self> {
+        tokio::time::timeout(options.timeout, Self::connect_inner(url, &options))
+            .await
+            .map_err(|_| format!("Timed out connecting to {}", url))?
+    }
+
+    async fn connect_inner(url: &str, options: &ClientOptions) -> Result<Self> {
+        let request = url.into_client_request().map_err(|e| e.to_string())?;
+        let uri = request.uri();
+        let secure = match uri.scheme_str() {
+            Some("ws") => false,
+            Some("wss") => true,
+            _ => return Err(format!("Unsupported URL {}", url)),
+        };
+        let host = uri
+            .host()
+            .ok_or_else(|| format!("Missing host in {}", url))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+
+        let tcp = TcpStream::connect((host.as_str(), port))
+            .await
+            .map_err(|e| format!("Failed to connect to {}: {}", url, e))?;
+        let _ = tcp.set_nodelay(true);
+        let stream: Box<dyn ClientIo> = if secure {
+            let fingerprint = options
+                .tls_fingerprint
+                .as_deref()
+                .ok_or_else(|| "wss:// requires the server certificate fingerprint".to_string())?;
+            Box::new(tls_connect(tcp, &host, fingerprint).await?)
+        } else {
+            Box::new(tcp)
+        };
+
+        let (ws, _) = client_async(request, stream)
+            .await
+            .map_err(|e| format!("WebSocket handshake failed: {}", e))?;
+        let (mut writer, mut reader) = ws.split();
+
+        // 开启认证的服务端先发 AuthChallenge，否则直接发 DisplayConfig
+        let mut current_display = 0;
+        let mut pending = Vec::new();
+        let mut device_id = None;
+        let mut issued_token = None;
+        let first = next_message(&mut reader, &mut current_display)
+            .await
+            .ok_or_else(|| "Server closed the connection during handshake".to_string())?;
+        match first {
+            ServerMessage::AuthChallenge { nonce } => {
+                let credentials = match &options.auth {
+                    ClientAuth::None => return Err("Server requires pairing".to_string()),
+                    ClientAuth::PairingCode(code) => Credentials::PairingCode {
+                        proof: auth_proof(code, &nonce),
+                    },
+                    ClientAuth::DeviceToken { device_id, token } => Credentials::DeviceToken {
+                        device_id: device_id.clone(),
+                        proof: auth_proof(token, &nonce),
+                    },
+                };
+                let hello = ClientMessage::Hello {
+                    device_name: options.device_name.clone(),
+                    credentials,
+                };
+                send_message(&mut writer, &hello).await?;
+
+                match next_message(&mut reader, &mut current_display).await {
+                    Some(ServerMessage::AuthAccepted { device_id: id, token }) => {
+                        device_id = Some(id);
+                        issued_token = token;
+                    }
+                    Some(ServerMessage::Error { message }) => {
+                        return Err(format!("Authentication failed: {}", message));
+                    }
+                    Some(other) => return Err(format!("Unexpected handshake message: {:?}", other)),
+                    None => return Err("Server closed the connection during handshake".to_string()),
+                }
+            }
+            other => pending.push(other),
+        }
+
+        let session_id = Arc::new(Mutex::new(None));
+        let (tx, messages) = mpsc::channel(64);
+        for message in pending {
+            let _ = tx.try_send(message);
+        }
+        let reader = tokio::spawn({
+            let session_id = session_id.clone();
+            async move {
+                while let Some(message) = next_message(&mut reader, &mut current_display).await {
+                    if let ServerMessage::SessionInfo { session_id: id, .. } = &message {
+                        *session_id.lock().unwrap() = Some(id.clone());
+                    }
+                    if tx.send(message).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            writer,
+            messages,
+            reader,
+            device_id,
+            issued_token,
+            session_id,
+        })
+    }
+
+    /// 认证通过时服务端分配的设备 ID；服务端未开启认证时为 None
+    pub fn device_id(&self) -> Option<&str> {
+        self.device_id.as_deref()
+    }
+
+    /// 首次配对时签发的长期令牌，调用方需自行保存以便下次用 DeviceToken 连接
+    pub fn issued_token(&self) -> Option<&str> {
+        self.issued_token.as_deref()
+    }
+
+    /// 服务端分配的会话 ID，断线重连后可用 [`Self::resume`] 恢复；尚未收到 SessionInfo 时为 None
+    pub fn session_id(&self) -> Option<String> {
+        self.session_id.lock().unwrap().clone()
+    }
+
+    /// 恢复同一设备之前断开的会话，需在连接后先于其他消息发送；成功时服务端回复
+    /// `SessionInfo { resumed: true, .. }`，随后是 DisplayConfig 和最新的一帧，会话已过期时回复 Error
+    pub async fn resume(&mut self, session_id: &str) -> Result<()> {
+        self.send(&ClientMessage::ResumeSession {
+            session_id: session_id.to_string(),
+        })
+        .await
+    }
+
+    /// 下一条服务端消息；连接关闭后返回 None
+    pub async fn recv(&mut self) -> Option<ServerMessage> {
+        self.messages.recv().await
+    }
+
+    pub async fn send(&mut self, message: &ClientMessage) -> Result<()> {
+        send_message(&mut self.writer, message).await
+    }
+
+    pub async fn send_sensor_data(&mut self, rotation_x: f32, rotation_y: f32, rotation_z: f32) -> Result<()> {
+        self.send(&ClientMessage::SensorData {
+            rotation_x,
+            rotation_y,
+            rotation_z,
+        })
+        .await
+    }
+
+    pub async fn switch_display(&mut self, direction: SwitchDirection) -> Result<()> {
+        self.send(&ClientMessage::SwitchDisplay { direction }).await
+    }
+
+    /// 请求服务端新建一个显示器；成功后会收到新的 DisplayConfig
+    pub async fn create_display(&mut self, width: u32, height: u32, name: &str) -> Result<()> {
+        self.send(&ClientMessage::CreateDisplay {
+            width,
+            height,
+            name: name.to_string(),
+        })
+        .await
+    }
+
+    pub async fn destroy_display(&mut self, index: u8) -> Result<()> {
+        self.send(&ClientMessage::DestroyDisplay { index }).await
+    }
+
+    /// 放大当前显示器的一块区域，`Zoom::Off` 取消放大
+    pub async fn set_zoom(&mut self, zoom: Zoom) -> Result<()> {
+        self.send(&ClientMessage::SetZoom { zoom }).await
+    }
+
+    /// 请求服务端的窗口列表；结果以 WindowList 消息返回
+    pub async fn list_windows(&mut self) -> Result<()> {
+        self.send(&ClientMessage::ListWindows).await
+    }
+
+    /// 把服务端的一个窗口作为新显示器推流；成功后会收到新的 DisplayConfig
+    pub async fn capture_window(&mut self, window: WindowSelector) -> Result<()> {
+        self.send(&ClientMessage::CaptureWindow { window }).await
+    }
+
+    pub async fn heartbeat(&mut self) -> Result<()> {
+        self.send(&ClientMessage::Heartbeat).await
+    }
+
+    /// 开启后服务端推送 AudioChunk；服务端未开启音频时回复 Error
+    pub async fn set_audio(&mut self, enabled: bool) -> Result<()> {
+        self.send(&ClientMessage::SetAudio { enabled }).await
+    }
+
+    pub async fn set_clipboard_sync(&mut self, enabled: bool) -> Result<()> {
+        self.send(&ClientMessage::ClipboardSync { enabled }).await
+    }
+
+    pub async fn send_clipboard(&mut self, mime_type: &str, data: Vec<u8>) -> Result<()> {
+        self.send(&ClientMessage::ClipboardUpdate {
+            mime_type: mime_type.to_string(),
+            data,
+        })
+        .await
+    }
+
+    pub async fn close(mut self) -> Result<()> {
+        self.writer.close().await.map_err(|e| e.to_string())
+    }
+}
+
+impl Drop for RotascopeClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+async fn send_message(writer: &mut SplitSink<WsStream, Message>, message: &ClientMessage) -> Result<()> {
+    let text = String::from_utf8(serialize_message(message)?).map_err(|e| e.to_string())?;
+    writer
+        .send(Message::Text(Utf8Bytes::from(text)))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 读取并解码下一条消息，跳过无法识别的数据；连接关闭时返回 None
+async fn next_message(reader: &mut SplitStream<WsStream>, current_display: &mut u8) -> Option<ServerMessage> {
+    while let Some(result) = reader.next().await {
+        let message = match result.ok()? {
+            Message::Text(text) => deserialize_message(text.as_bytes()).ok(),
+            // DisplayConfig 以二进制 JSON 发送，视频帧和音频块见 media；旧版本的服务端发送不带头部的 JPEG
+            Message::Binary(data) => match data.first() {
+                Some(b'{') => deserialize_message(&data).ok(),
+                Some(&media::KIND_VIDEO | &media::KIND_AUDIO) => media::decode(&data).ok(),
+                _ => {
+                    let (width, height) = jpeg_dimensions(&data).unwrap_or((0, 0));
+                    Some(ServerMessage::VideoFrame {
+                        display_index: *current_display,
+                        width,
+                        height,
+                        data: data.to_vec(),
+                        timestamp: timestamp_millis(),
+                    })
+                }
+            },
+            Message::Close(_) => return None,
+            _ => None,
+        };
+        if let Some(message) = message {
+            if let ServerMessage::DisplayConfig { current_display: display, .. }
+            | ServerMessage::DisplaySwitched { current_display: display } = &message
+            {
+                *current_display = *display;
+            }
+            return Some(message);
+        }
+    }
+    None
+}
+
+/// 从 JPEG 的 SOF 段读取 (宽, 高)
+pub fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let mut i = 2;
+    while i + 4 <= data.len() {
+        if data[i] != 0xff {
+            return None;
+        }
+        let marker = data[i + 1];
+        // 段之间允许填充 0xff
+        if marker == 0xff {
+            i += 1;
+            continue;
+        }
+        // SOF0..SOF15，其中 C4/C8/CC 不是帧头
+        if (0xc0..=0xcf).contains(&marker) && !matches!(marker, 0xc4 | 0xc8 | 0xcc) {
+            let header = data.get(i + 5..i + 9)?;
+            let height = u16::from_be_bytes([header[0], header[1]]) as u32;
+            let width = u16::from_be_bytes([header[2], header[3]]) as u32;
+            return Some((width, height));
+        }
+        let len = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+        i += 2 + len;
+    }
+    None
+}
+
+async fn tls_connect(
+    tcp: TcpStream,
+    host: &str,
+    fingerprint: &str,
+) -> Result<tokio_rustls::client::TlsStream<TcpStream>> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = PinnedCertificate {
+        fingerprint: fingerprint.to_ascii_lowercase(),
+        provider: provider.clone(),
+    };
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    let server_name = ServerName::try_from(host.to_string()).map_err(|e| e.to_string())?;
+    TlsConnector::from(Arc::new(config))
+        .connect(server_name, tcp)
+        .await
+        .map_err(|e| format!("TLS handshake failed: {}", e))
+}
+
+/// 证书固定：服务端使用自签名证书，只比对指纹，不校验证书链和主机名
+#[derive(Debug)]
+struct PinnedCertificate {
+    fingerprint: String,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        if certificate_fingerprint(end_entity) == self.fingerprint {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General("Certificate fingerprint mismatch".to_string()))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
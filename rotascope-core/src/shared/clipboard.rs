@@ -0,0 +1,33 @@
+use crate::Result;
+
+/// UTF-8 纯文本
+pub const MIME_TEXT: &str = "text/plain;charset=utf-8";
+/// PNG 图片
+pub const MIME_PNG: &str = "image/png";
+
+/// 剪贴板内容的默认大小上限 (4 MiB)
+pub const DEFAULT_CLIPBOARD_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+/// 检查一次剪贴板更新是否可以被接受：类型必须受支持，大小不能超过上限
+pub fn validate_clipboard(mime_type: &str, data: &[u8], max_bytes: usize) -> Result<()> {
+    if data.len() > max_bytes {
+        return Err(format!(
+            "Clipboard content too large: {} bytes (limit {})",
+            data.len(),
+            max_bytes
+        ));
+    }
+    match mime_type {
+        MIME_TEXT => std::str::from_utf8(data)
+            .map(|_| ())
+            .map_err(|e| format!("Clipboard text is not valid UTF-8: {}", e)),
+        MIME_PNG => {
+            if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+                Ok(())
+            } else {
+                Err("Clipboard image is not a PNG".to_string())
+            }
+        }
+        other => Err(format!("Unsupported clipboard MIME type: {}", other)),
+    }
+}
@@ -0,0 +1,204 @@
+use crate::{PROTOCOL_VERSION, Result};
+use mdns_sd::{IfKind, ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// RotaScope 服务端在局域网中广播的 DNS-SD 服务类型
+pub const SERVICE_TYPE: &str = "_rotascope._tcp.local.";
+
+const TXT_NAME: &str = "name";
+const TXT_PROTOCOL: &str = "proto";
+const TXT_DISPLAYS: &str = "displays";
+const TXT_FINGERPRINT: &str = "fp";
+
+/// 服务端对外公布的信息（写入 TXT 记录）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceAnnouncement {
+    pub name: String,
+    pub port: u16,
+    pub protocol_version: u32,
+    pub display_count: usize,
+    /// TLS 证书指纹；存在时客户端应使用 wss:// 并固定该证书
+    pub tls_fingerprint: Option<String>,
+}
+
+impl ServiceAnnouncement {
+    pub fn new(name: &str, port: u16, display_count: usize, tls_fingerprint: Option<&str>) -> Self {
+        Self {
+            name: name.to_string(),
+            port,
+            protocol_version: PROTOCOL_VERSION,
+            display_count,
+            tls_fingerprint: tls_fingerprint.map(str::to_string),
+        }
+    }
+
+    fn txt_properties(&self) -> HashMap<String, String> {
+        let mut txt = HashMap::new();
+        txt.insert(TXT_NAME.to_string(), self.name.clone());
+        txt.insert(TXT_PROTOCOL.to_string(), self.protocol_version.to_string());
+        txt.insert(TXT_DISPLAYS.to_string(), self.display_count.to_string());
+        if let Some(fp) = &self.tls_fingerprint {
+            txt.insert(TXT_FINGERPRINT.to_string(), fp.clone());
+        }
+        txt
+    }
+}
+
+/// 在局域网中发现的服务端
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredServer {
+    pub fullname: String,
+    pub addresses: Vec<IpAddr>,
+    pub announcement: ServiceAnnouncement,
+}
+
+impl DiscoveredServer {
+    /// 可直接用于连接的 WebSocket 地址，优先使用 IPv4
+    pub fn url(&self) -> Option<String> {
+        let ip = self
+            .addresses
+            .iter()
+            .find(|ip| ip.is_ipv4())
+            .or_else(|| self.addresses.first())?;
+        let scheme = if self.announcement.tls_fingerprint.is_some() { "wss" } else { "ws" };
+        let host = match ip {
+            IpAddr::V4(v4) => v4.to_string(),
+            IpAddr::V6(v6) => format!("[{}]", v6),
+        };
+        Some(format!("{}://{}:{}", scheme, host, self.announcement.port))
+    }
+
+    fn from_service_info(info: &ServiceInfo) -> Option<Self> {
+        let txt = |key: &str| info.get_property_val_str(key);
+        let mut addresses: Vec<IpAddr> = info.get_addresses().iter().copied().collect();
+        addresses.sort();
+        Some(Self {
+            fullname: info.get_fullname().to_string(),
+            addresses,
+            announcement: ServiceAnnouncement {
+                name: txt(TXT_NAME)?.to_string(),
+                port: info.get_port(),
+                protocol_version: txt(TXT_PROTOCOL)?.parse().ok()?,
+                display_count: txt(TXT_DISPLAYS).and_then(|v| v.parse().ok()).unwrap_or(0),
+                tls_fingerprint: txt(TXT_FINGERPRINT).map(str::to_string),
+            },
+        })
+    }
+}
+
+fn new_daemon(include_loopback: bool) -> Result<ServiceDaemon> {
+    let daemon = ServiceDaemon::new().map_err(|e| e.to_string())?;
+    if include_loopback {
+        daemon
+            .enable_interface(IfKind::LoopbackV4)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(daemon)
+}
+
+/// 服务端的 mDNS 广播，drop 时注销
+pub struct ServiceAdvertiser {
+    daemon: ServiceDaemon,
+    fullname: String,
+    announcement: ServiceAnnouncement,
+}
+
+impl ServiceAdvertiser {
+    pub fn start(announcement: &ServiceAnnouncement, include_loopback: bool) -> Result<Self> {
+        let daemon = new_daemon(include_loopback)?;
+        let mut advertiser = Self {
+            daemon,
+            fullname: String::new(),
+            announcement: announcement.clone(),
+        };
+        advertiser.update(announcement)?;
+        Ok(advertiser)
+    }
+
+    /// 当前广播的信息
+    pub fn announcement(&self) -> &ServiceAnnouncement {
+        &self.announcement
+    }
+
+    /// 重新注册以更新 TXT 记录（例如显示器数量变化）
+    pub fn update(&mut self, announcement: &ServiceAnnouncement) -> Result<()> {
+        // 实例名和主机名中只保留 DNS 标签允许的字符
+        let label: String = announcement
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '-' })
+            .collect();
+        let host_name = format!("{}.local.", label);
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            &label,
+            &host_name,
+            "",
+            announcement.port,
+            announcement.txt_properties(),
+        )
+        .map_err(|e| e.to_string())?
+        .enable_addr_auto();
+
+        if !self.fullname.is_empty() && self.fullname != info.get_fullname() {
+            let _ = self.daemon.unregister(&self.fullname);
+        }
+        self.fullname = info.get_fullname().to_string();
+        self.daemon.register(info).map_err(|e| e.to_string())?;
+        self.announcement = announcement.clone();
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ServiceAdvertiser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServiceAdvertiser")
+            .field("fullname", &self.fullname)
+            .field("announcement", &self.announcement)
+            .finish()
+    }
+}
+
+impl Drop for ServiceAdvertiser {
+    fn drop(&mut self) {
+        if let Ok(done) = self.daemon.unregister(&self.fullname) {
+            let _ = done.recv_timeout(Duration::from_secs(1));
+        }
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// 在局域网中查找服务端，收集 timeout 时间内解析到的全部结果
+pub async fn discover(timeout: Duration, include_loopback: bool) -> Result<Vec<DiscoveredServer>> {
+    let daemon = new_daemon(include_loopback)?;
+    let events = daemon.browse(SERVICE_TYPE).map_err(|e| e.to_string())?;
+
+    let deadline = Instant::now() + timeout;
+    let mut found: HashMap<String, DiscoveredServer> = HashMap::new();
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        match tokio::time::timeout(remaining, events.recv_async()).await {
+            Ok(Ok(ServiceEvent::ServiceResolved(info))) => {
+                if let Some(server) = DiscoveredServer::from_service_info(&info) {
+                    found.insert(server.fullname.clone(), server);
+                }
+            }
+            Ok(Ok(ServiceEvent::ServiceRemoved(_, fullname))) => {
+                found.remove(&fullname);
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(_)) | Err(_) => break,
+        }
+    }
+
+    let _ = daemon.stop_browse(SERVICE_TYPE);
+    let _ = daemon.shutdown();
+    let mut servers: Vec<DiscoveredServer> = found.into_values().collect();
+    servers.sort_by(|a, b| a.fullname.cmp(&b.fullname));
+    Ok(servers)
+}
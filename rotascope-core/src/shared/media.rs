@@ -0,0 +1,77 @@
+//! 音视频数据以二进制 WebSocket 消息发送，不经过 JSON（小端）：
+//!
+//! ```text
+//! 类型 u8 | 头部 | 数据
+//! 视频帧: 0x01 | 显示器 u8 | 宽 u32 | 高 u32 | 时间戳 u64 | JPEG
+//! 音频块: 0x02 | 编码 u8 | 采样率 u32 | 声道数 u8 | 时间戳 u64 | 采样数据
+//! ```
+//!
+//! 类型字节与 JSON 消息的首字节 `{` 和 JPEG 的首字节 0xff 都不同，客户端据此区分二进制消息。
+
+use crate::{AudioCodec, Result, ServerMessage};
+
+pub const KIND_VIDEO: u8 = 0x01;
+pub const KIND_AUDIO: u8 = 0x02;
+
+const VIDEO_HEADER_LEN: usize = 1 + 1 + 4 + 4 + 8;
+const AUDIO_HEADER_LEN: usize = 1 + 1 + 4 + 1 + 8;
+
+pub fn encode_video(display_index: u8, width: u32, height: u32, timestamp: u64, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(VIDEO_HEADER_LEN + data.len());
+    out.push(KIND_VIDEO);
+    out.push(display_index);
+    out.extend_from_slice(&width.to_le_bytes());
+    out.extend_from_slice(&height.to_le_bytes());
+    out.extend_from_slice(&timestamp.to_le_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+pub fn encode_audio(codec: AudioCodec, sample_rate: u32, channels: u8, timestamp: u64, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(AUDIO_HEADER_LEN + data.len());
+    out.push(KIND_AUDIO);
+    out.push(match codec {
+        AudioCodec::Pcm16 => 0,
+    });
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.push(channels);
+    out.extend_from_slice(&timestamp.to_le_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+/// 解码二进制的音视频消息
+pub fn decode(data: &[u8]) -> Result<ServerMessage> {
+    match data.first() {
+        Some(&KIND_VIDEO) => {
+            if data.len() < VIDEO_HEADER_LEN {
+                return Err("Truncated video frame".to_string());
+            }
+            Ok(ServerMessage::VideoFrame {
+                display_index: data[1],
+                width: u32::from_le_bytes([data[2], data[3], data[4], data[5]]),
+                height: u32::from_le_bytes([data[6], data[7], data[8], data[9]]),
+                timestamp: u64::from_le_bytes(data[10..18].try_into().unwrap()),
+                data: data[VIDEO_HEADER_LEN..].to_vec(),
+            })
+        }
+        Some(&KIND_AUDIO) => {
+            if data.len() < AUDIO_HEADER_LEN {
+                return Err("Truncated audio chunk".to_string());
+            }
+            let codec = match data[1] {
+                0 => AudioCodec::Pcm16,
+                other => return Err(format!("Unknown audio codec {}", other)),
+            };
+            Ok(ServerMessage::AudioChunk {
+                codec,
+                sample_rate: u32::from_le_bytes([data[2], data[3], data[4], data[5]]),
+                channels: data[6],
+                timestamp: u64::from_le_bytes(data[7..15].try_into().unwrap()),
+                data: data[AUDIO_HEADER_LEN..].to_vec(),
+            })
+        }
+        Some(other) => Err(format!("Unknown binary message type {}", other)),
+        None => Err("Empty binary message".to_string()),
+    }
+}
@@ -1,3 +1,9 @@
+pub mod auth;
+pub mod client;
+pub mod clipboard;
+pub mod discovery;
+pub mod media;
 pub mod protocol;
+pub mod recording;
 pub use protocol::*;
-pub type Result<T> = std::result::Result<T,String>;
\ No newline at end of file
+pub type Result<T> = std::result::Result<T,String>;
@@ -1,8 +1,16 @@
 use serde::{Deserialize, Serialize};
 use crate::Result;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// 协议版本，不兼容的改动时递增
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ClientMessage {
+    /// 握手：回应服务端的 AuthChallenge，必须是连接上的第一条消息
+    Hello {
+        device_name: String,
+        credentials: Credentials,
+    },
     SensorData {
         rotation_x: f32,
         rotation_y: f32,
@@ -11,17 +19,154 @@ pub enum ClientMessage {
     SwitchDisplay {
         direction: SwitchDirection,
     },
+    /// 按预设方式重新排列所有显示器
+    ArrangeDisplays {
+        arrangement: Arrangement,
+    },
+    /// 单独摆放一个显示器，排列方式随之变为 Custom
+    SetDisplayLayout {
+        index: u8,
+        layout: DisplayLayout,
+    },
+    /// 调整显示器分辨率，服务端随后向所有客户端推送新的 DisplayConfig；超出服务端的限制时回复 Error
+    ResizeDisplay {
+        index: u8,
+        width: u32,
+        height: u32,
+    },
     Heartbeat,
+    // 剪贴板同步（需先通过 ClipboardSync 开启）
+    ClipboardSync {
+        enabled: bool,
+    },
+    ClipboardUpdate {
+        mime_type: String,
+        data: Vec<u8>,
+    },
+    /// 开启/关闭本会话的桌面音频（AudioChunk），默认关闭
+    SetAudio {
+        enabled: bool,
+    },
+    /// 请求服务端开始/停止录制本会话
+    SetRecording {
+        enabled: bool,
+    },
+    /// 新建一个虚拟显示器并切换过去；name 为空时由服务端命名，被拒绝时服务端回复 Error
+    CreateDisplay {
+        width: u32,
+        height: u32,
+        name: String,
+    },
+    /// 销毁之前由客户端创建的显示器
+    DestroyDisplay {
+        index: u8,
+    },
+    /// 网络短暂中断后重连：恢复宽限期内同一设备的旧会话（剪贴板同步、音频、录制等状态），
+    /// 必须是连接上的第一条消息
+    ResumeSession {
+        session_id: String,
+    },
+    /// 放大当前显示器的一块区域；放大期间头部转动用于在区域内平移，不再切换显示器
+    SetZoom {
+        zoom: Zoom,
+    },
+    /// 列出服务端桌面上可以捕获的顶层窗口，服务端回复 WindowList
+    ListWindows,
+    /// 把一个窗口作为新的显示器推流并切换过去；窗口关闭后该显示器随之移除，也可用 DestroyDisplay 移除
+    CaptureWindow {
+        window: WindowSelector,
+    },
+}
+
+/// 放大的区域，坐标为显示器画面的像素
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Zoom {
+    /// 取消放大
+    Off,
+    /// 放大该矩形；宽高比与画面不同时向两侧扩展
+    Rect { x: u32, y: u32, width: u32, height: u32 },
+    /// 以 (x, y) 为中心放大 factor 倍
+    Around { x: u32, y: u32, factor: f32 },
+}
+
+/// CaptureWindow 选择窗口的方式
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum WindowSelector {
+    Id(u32),
+    /// 标题完全相同（不区分大小写）的窗口优先，否则要求只有一个窗口的标题包含该文本
+    Title(String),
+}
+
+/// 服务端桌面上的顶层窗口
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Credentials {
+    /// 首次配对：proof = auth_proof(配对码, nonce)
+    PairingCode { proof: String },
+    /// 已配对设备：proof = auth_proof(令牌, nonce)
+    DeviceToken { device_id: String, proof: String },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SwitchDirection {
     Next,
     Previous,
+    /// 按布局切换到该方向上最近的显示器；该方向没有显示器时保持不变
+    Left,
+    Right,
+    Up,
+    Down,
+    /// 切换到指定编号的显示器
+    Goto(u8),
+    /// 切回上一个观看的显示器，重复使用可在两个显示器之间来回切换
+    Last,
+}
+
+/// 显示器在布局中的位置和姿态
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DisplayLayout {
+    pub name: String,
+    /// 左上角在虚拟桌面上的坐标（像素）
+    pub x: i32,
+    pub y: i32,
+    /// 绕竖直轴的偏航角（度），正值向右转；弧形排列时使每个显示器都朝向用户
+    pub rotation: f32,
+    /// 客户端显示时的缩放比例
+    pub scale: f32,
+}
+
+/// 显示器的整体排列方式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Arrangement {
+    /// 从左到右排成一行
+    #[default]
+    Row,
+    /// 按行优先排成网格
+    Grid { columns: u8 },
+    /// 排成一行并围绕用户弯曲，相邻显示器的中心相隔 spacing_degrees
+    Cylinder { spacing_degrees: f32 },
+    /// 每个显示器单独摆放
+    Custom,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ServerMessage {
+    /// 连接建立后的第一条消息，客户端需用 Hello 回应
+    AuthChallenge {
+        nonce: String,
+    },
+    /// 认证通过；首次配对时附带新签发的长期设备令牌，客户端需自行保存
+    AuthAccepted {
+        device_id: String,
+        token: Option<String>,
+    },
     VideoFrame {
         display_index: u8,
         width: u32,
@@ -29,17 +174,74 @@ pub enum ServerMessage {
         data: Vec<u8>, // JPEG encoded
         timestamp: u64,
     },
+    /// 连接时发送；显示器被添加、移除或调整大小时再次推送给所有客户端
     DisplayConfig {
         total_displays: usize,
         current_display: u8,
         resolutions: Vec<(u32, u32)>,
+        /// 与 resolutions 一一对应；旧版本的服务端不发送
+        #[serde(default)]
+        layout: Vec<DisplayLayout>,
+        #[serde(default)]
+        arrangement: Arrangement,
+    },
+    // 与 VideoFrame 使用同一时钟 (timestamp_millis)，便于音画同步；以二进制发送，见 media
+    AudioChunk {
+        codec: AudioCodec,
+        sample_rate: u32,
+        channels: u8,
+        data: Vec<u8>,
+        timestamp: u64,
     },
     Heartbeat,
+    ClipboardUpdate {
+        mime_type: String,
+        data: Vec<u8>,
+    },
+    /// 录制状态变化；file 为服务端上的录制文件名
+    RecordingState {
+        active: bool,
+        file: Option<String>,
+    },
+    /// 当前显示器变化时推送给所有客户端；SwitchDisplay 未引起变化时只回复给请求方
+    DisplaySwitched {
+        current_display: u8,
+    },
+    /// 显示器的放大设置变化时推送给所有客户端；每个显示器单独保存放大设置
+    ZoomChanged {
+        display_index: u8,
+        zoom: Zoom,
+    },
+    /// ListWindows 的回复
+    WindowList {
+        windows: Vec<WindowInfo>,
+    },
+    /// 会话建立或恢复后发送；断线后在 resume_grace_secs 内可用 ResumeSession 恢复。
+    /// 只有认证过的设备才能恢复会话，未开启认证时不发送
+    SessionInfo {
+        session_id: String,
+        resumed: bool,
+        resume_grace_secs: u64,
+    },
     Error {
         message: String,
     },
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioCodec {
+    /// 16 位有符号小端 PCM，多声道交错存放
+    Pcm16,
+}
+
+/// 服务端消息使用的统一时间戳（Unix 毫秒）
+pub fn timestamp_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 // 序列化辅助函数
 pub fn serialize_message<T: Serialize>(msg: &T) -> Result<Vec<u8>> {
     serde_json::to_vec(msg).map_err(|e|e.to_string())
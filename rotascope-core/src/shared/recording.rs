@@ -0,0 +1,273 @@
+//! 会话录制文件格式（小端）：
+//!
+//! ```text
+//! MAGIC (8 字节) | 格式版本 u32 | 头部长度 u32 | 头部 JSON (RecordingHeader)
+//! 记录: 类型 u8 | 相对录制开始的毫秒数 u64 | 数据长度 u32 | 数据
+//! ```
+//!
+//! 视频帧的数据为 `显示器 u8 | 宽 u32 | 高 u32 | JPEG`，其余消息为 JSON。
+
+use crate::{
+    Arrangement, ClientMessage, DisplayLayout, PROTOCOL_VERSION, Result, ServerMessage, deserialize_message,
+    serialize_message,
+};
+use serde::{Deserialize, Serialize};
+use std::io::{ErrorKind, Read, Write};
+
+pub const MAGIC: &[u8; 8] = b"RSCOPREC";
+pub const FORMAT_VERSION: u32 = 1;
+/// 录制文件的扩展名
+pub const FILE_EXTENSION: &str = "rsrec";
+
+/// 单条记录的上限，防止损坏的文件导致分配过大的内存
+const MAX_RECORD_BYTES: usize = 64 * 1024 * 1024;
+
+const KIND_FRAME: u8 = 1;
+const KIND_SERVER: u8 = 2;
+const KIND_CLIENT: u8 = 3;
+
+/// 录制开始时的会话信息
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordingHeader {
+    pub protocol_version: u32,
+    /// 录制开始时刻 (timestamp_millis)
+    pub started_at: u64,
+    /// 被录制的设备，未开启认证时为 None
+    pub device: Option<String>,
+    // 录制开始时的 DisplayConfig
+    pub total_displays: usize,
+    pub current_display: u8,
+    pub resolutions: Vec<(u32, u32)>,
+    #[serde(default)]
+    pub layout: Vec<DisplayLayout>,
+    #[serde(default)]
+    pub arrangement: Arrangement,
+}
+
+impl RecordingHeader {
+    /// 从录制开始时的 DisplayConfig 构造
+    pub fn new(started_at: u64, device: Option<String>, config: &ServerMessage) -> Result<Self> {
+        let ServerMessage::DisplayConfig {
+            total_displays,
+            current_display,
+            resolutions,
+            layout,
+            arrangement,
+        } = config
+        else {
+            return Err("Recording header needs a DisplayConfig".to_string());
+        };
+        Ok(Self {
+            protocol_version: PROTOCOL_VERSION,
+            started_at,
+            device,
+            total_displays: *total_displays,
+            current_display: *current_display,
+            resolutions: resolutions.clone(),
+            layout: layout.clone(),
+            arrangement: *arrangement,
+        })
+    }
+
+    pub fn display_config(&self) -> ServerMessage {
+        ServerMessage::DisplayConfig {
+            total_displays: self.total_displays,
+            current_display: self.current_display,
+            resolutions: self.resolutions.clone(),
+            layout: self.layout.clone(),
+            arrangement: self.arrangement,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RecordedEvent {
+    /// 发给客户端的视频帧
+    Frame {
+        display_index: u8,
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+    },
+    /// 发给客户端的其他消息
+    Server(ServerMessage),
+    /// 客户端发来的消息
+    Client(ClientMessage),
+}
+
+#[derive(Debug)]
+pub struct RecordEntry {
+    /// 相对录制开始的毫秒数
+    pub offset_ms: u64,
+    pub event: RecordedEvent,
+}
+
+pub struct RecordingWriter<W: Write> {
+    inner: W,
+    started_at: u64,
+}
+
+impl<W: Write> RecordingWriter<W> {
+    pub fn new(mut inner: W, header: &RecordingHeader) -> Result<Self> {
+        let header_json = serialize_message(header)?;
+        inner.write_all(MAGIC).map_err(|e| e.to_string())?;
+        inner.write_all(&FORMAT_VERSION.to_le_bytes()).map_err(|e| e.to_string())?;
+        inner
+            .write_all(&(header_json.len() as u32).to_le_bytes())
+            .map_err(|e| e.to_string())?;
+        inner.write_all(&header_json).map_err(|e| e.to_string())?;
+        Ok(Self {
+            inner,
+            started_at: header.started_at,
+        })
+    }
+
+    /// 写入一条记录；timestamp 与头部的 started_at 使用同一时钟
+    pub fn write(&mut self, timestamp: u64, event: &RecordedEvent) -> Result<()> {
+        match event {
+            RecordedEvent::Frame {
+                display_index,
+                width,
+                height,
+                data,
+            } => self.write_frame(timestamp, *display_index, *width, *height, data),
+            RecordedEvent::Server(message) => self.write_server(timestamp, message),
+            RecordedEvent::Client(message) => self.write_client(timestamp, message),
+        }
+    }
+
+    pub fn write_frame(&mut self, timestamp: u64, display_index: u8, width: u32, height: u32, data: &[u8]) -> Result<()> {
+        let mut payload = Vec::with_capacity(9 + data.len());
+        payload.push(display_index);
+        payload.extend_from_slice(&width.to_le_bytes());
+        payload.extend_from_slice(&height.to_le_bytes());
+        payload.extend_from_slice(data);
+        self.write_record(KIND_FRAME, timestamp, &payload)
+    }
+
+    pub fn write_server(&mut self, timestamp: u64, message: &ServerMessage) -> Result<()> {
+        self.write_record(KIND_SERVER, timestamp, &serialize_message(message)?)
+    }
+
+    pub fn write_client(&mut self, timestamp: u64, message: &ClientMessage) -> Result<()> {
+        self.write_record(KIND_CLIENT, timestamp, &serialize_message(message)?)
+    }
+
+    fn write_record(&mut self, kind: u8, timestamp: u64, payload: &[u8]) -> Result<()> {
+        let offset_ms = timestamp.saturating_sub(self.started_at);
+        let mut record = Vec::with_capacity(13);
+        record.push(kind);
+        record.extend_from_slice(&offset_ms.to_le_bytes());
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        self.inner.write_all(&record).map_err(|e| e.to_string())?;
+        self.inner.write_all(payload).map_err(|e| e.to_string())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.inner.flush().map_err(|e| e.to_string())
+    }
+
+    pub fn into_inner(mut self) -> Result<W> {
+        self.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> std::fmt::Debug for RecordingWriter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecordingWriter")
+            .field("started_at", &self.started_at)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct RecordingReader<R: Read> {
+    inner: R,
+    header: RecordingHeader,
+}
+
+impl<R: Read> RecordingReader<R> {
+    pub fn new(mut inner: R) -> Result<Self> {
+        let mut magic = [0u8; 8];
+        inner
+            .read_exact(&mut magic)
+            .map_err(|_| "Not a RotaScope recording".to_string())?;
+        if &magic != MAGIC {
+            return Err("Not a RotaScope recording".to_string());
+        }
+        let version = read_u32(&mut inner)?;
+        if version != FORMAT_VERSION {
+            return Err(format!("Unsupported recording format version {}", version));
+        }
+        let header_json = read_payload(&mut inner)?;
+        let header = deserialize_message(&header_json)?;
+        Ok(Self { inner, header })
+    }
+
+    pub fn header(&self) -> &RecordingHeader {
+        &self.header
+    }
+
+    /// 下一条记录；文件在记录边界结束时返回 None
+    pub fn next_entry(&mut self) -> Result<Option<RecordEntry>> {
+        let mut kind = [0u8; 1];
+        match self.inner.read_exact(&mut kind) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        }
+        let mut offset = [0u8; 8];
+        self.inner
+            .read_exact(&mut offset)
+            .map_err(|_| "Truncated recording".to_string())?;
+        let offset_ms = u64::from_le_bytes(offset);
+        let payload = read_payload(&mut self.inner)?;
+
+        let event = match kind[0] {
+            KIND_FRAME => {
+                if payload.len() < 9 {
+                    return Err("Truncated frame record".to_string());
+                }
+                RecordedEvent::Frame {
+                    display_index: payload[0],
+                    width: u32::from_le_bytes([payload[1], payload[2], payload[3], payload[4]]),
+                    height: u32::from_le_bytes([payload[5], payload[6], payload[7], payload[8]]),
+                    data: payload[9..].to_vec(),
+                }
+            }
+            KIND_SERVER => RecordedEvent::Server(deserialize_message(&payload)?),
+            KIND_CLIENT => RecordedEvent::Client(deserialize_message(&payload)?),
+            other => return Err(format!("Unknown record type {}", other)),
+        };
+        Ok(Some(RecordEntry { offset_ms, event }))
+    }
+}
+
+impl<R: Read> Iterator for RecordingReader<R> {
+    type Item = Result<RecordEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    reader
+        .read_exact(&mut buf)
+        .map_err(|_| "Truncated recording".to_string())?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_payload(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let len = read_u32(reader)? as usize;
+    if len > MAX_RECORD_BYTES {
+        return Err(format!("Record of {} bytes is too large", len));
+    }
+    let mut payload = vec![0u8; len];
+    reader
+        .read_exact(&mut payload)
+        .map_err(|_| "Truncated recording".to_string())?;
+    Ok(payload)
+}
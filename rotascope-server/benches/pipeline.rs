@@ -0,0 +1,122 @@
+//! 捕获到编码管线的基准：与原来逐字节转换、单线程编码的实现对比
+//!
+//! 运行：cargo bench --bench pipeline
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use image::RgbaImage;
+use rotascope_server::pipeline::{self, BufferPool, PixelOrder};
+use std::hint::black_box;
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+
+/// 模拟桌面画面：大块纯色区域加上细节纹理
+fn bgra_frame() -> Vec<u8> {
+    let mut data = Vec::with_capacity((WIDTH * HEIGHT * 4) as usize);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let window = (x / 480 + y / 270) % 3 == 0;
+            let text = (x * 7 + y * 13) % 11 < 3;
+            let v = if window { 240 } else { (x / 8) as u8 };
+            let v = if text { v / 4 } else { v };
+            data.extend_from_slice(&[v, v.wrapping_add(y as u8), v / 2, 0]);
+        }
+    }
+    data
+}
+
+/// 原实现：每帧新分配，逐像素 extend
+fn legacy_convert(buffer: &[u8]) -> RgbaImage {
+    let mut rgba = Vec::with_capacity(buffer.len());
+    for chunk in buffer.chunks_exact(4) {
+        rgba.extend_from_slice(&[chunk[2], chunk[1], chunk[0], 255]);
+    }
+    RgbaImage::from_raw(WIDTH, HEIGHT, rgba).unwrap()
+}
+
+/// 原实现：先复制一份 RGB，再用 image 的编码器单线程编码
+fn legacy_encode(frame: &RgbaImage, quality: u8) -> Vec<u8> {
+    use image::ExtendedColorType;
+    use image::codecs::jpeg::JpegEncoder;
+
+    let mut rgb = Vec::with_capacity((frame.width() * frame.height() * 3) as usize);
+    for px in frame.as_raw().chunks_exact(4) {
+        rgb.extend_from_slice(&px[..3]);
+    }
+    let mut out = Vec::new();
+    JpegEncoder::new_with_quality(&mut out, quality)
+        .encode(&rgb, frame.width(), frame.height(), ExtendedColorType::Rgb8)
+        .unwrap();
+    out
+}
+
+fn convert(c: &mut Criterion) {
+    let bgra = bgra_frame();
+    let mut group = c.benchmark_group("bgra_to_rgba");
+    group.throughput(Throughput::Bytes(bgra.len() as u64));
+
+    group.bench_function("legacy", |b| b.iter(|| legacy_convert(black_box(&bgra))));
+    let pool = BufferPool::new(1);
+    group.bench_function("pooled", |b| {
+        b.iter(|| {
+            let mut rgba = pool.take(bgra.len());
+            pipeline::bgra_to_rgba(black_box(&bgra), &mut rgba);
+            pool.put(rgba);
+        })
+    });
+    group.bench_function("pooled_parallel", |b| {
+        b.iter(|| {
+            let mut rgba = pool.take(bgra.len());
+            pipeline::bgra_to_rgba_parallel(black_box(&bgra), &mut rgba, WIDTH as usize);
+            pool.put(rgba);
+        })
+    });
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    let frame = legacy_convert(&bgra_frame());
+    let mut group = c.benchmark_group("jpeg_encode");
+    group.throughput(Throughput::Elements(1));
+    group.sample_size(30);
+
+    group.bench_function("legacy", |b| b.iter(|| legacy_encode(black_box(&frame), 70)));
+    for stripes in [1, 0] {
+        let label = if stripes == 0 { "all_cores".to_string() } else { stripes.to_string() };
+        group.bench_with_input(BenchmarkId::new("striped", label), &stripes, |b, &stripes| {
+            b.iter(|| pipeline::encode_jpeg(black_box(&frame), PixelOrder::Rgba, 70, stripes).unwrap())
+        });
+    }
+    // 屏幕画面不转换，直接按 BGRA 编码
+    let bgra = RgbaImage::from_raw(WIDTH, HEIGHT, bgra_frame()).unwrap();
+    group.bench_function("striped_bgra/all_cores", |b| {
+        b.iter(|| pipeline::encode_jpeg(black_box(&bgra), PixelOrder::Bgra, 70, 0).unwrap())
+    });
+    group.finish();
+}
+
+/// 采集 → 编码 → 归还缓冲区的整帧耗时；pipeline 与屏幕采集相同，复制 BGRA 后直接编码
+fn frame(c: &mut Criterion) {
+    let bgra = bgra_frame();
+    let mut group = c.benchmark_group("frame");
+    group.sample_size(30);
+
+    group.bench_function("legacy", |b| {
+        b.iter(|| legacy_encode(&legacy_convert(black_box(&bgra)), 70))
+    });
+    let pool = BufferPool::new(1);
+    group.bench_function("pipeline", |b| {
+        b.iter(|| {
+            let mut buffer = pool.take(bgra.len());
+            buffer.copy_from_slice(black_box(&bgra));
+            let frame = RgbaImage::from_raw(WIDTH, HEIGHT, buffer).unwrap();
+            let data = pipeline::encode_jpeg(&frame, PixelOrder::Bgra, 70, 0).unwrap();
+            pool.put(frame.into_raw());
+            data
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, convert, encode, frame);
+criterion_main!(benches);
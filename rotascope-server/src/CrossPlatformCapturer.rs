@@ -1,17 +1,16 @@
 use std::fmt;
 use std::fmt::{Debug, Formatter};
 use scrap::{Capturer, Display};
-use image::{ExtendedColorType, ImageBuffer, Rgba};
-use std::io::ErrorKind::WouldBlock;
-use std::ops::{Deref, DerefMut};
-use std::sync::Arc;
+use image::{ImageBuffer, Rgba};
 use std::time::Duration;
 use rotascope_core::Result;
+use crate::pipeline::{self, BufferPool};
 
 pub struct CrossPlatformCapturer {
     capturer: Capturer,
     width: usize,
     height: usize,
+    pool: BufferPool,
 }
 
 impl Debug for CrossPlatformCapturer {
@@ -35,86 +34,60 @@ impl CrossPlatformCapturer {
             capturer,
             width,
             height,
+            pool: BufferPool::new(pipeline::POOLED_FRAMES),
         })
     }
 
+    /// 阻塞等待下一帧；捕获线程使用 [`Self::try_capture_frame`]，自己决定没有新画面时如何等待
     pub fn capture_frame(&mut self) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        loop {
+            if let Some(frame) = self.try_capture_frame()? {
+                return Ok(frame);
+            }
+            std::thread::sleep(Duration::from_micros(500));
+        }
+    }
+
+    /// 读取一帧；屏幕还没有更新（WouldBlock）时返回 None。
+    /// 像素保持 scrap 的 BGRA 顺序，由编码器直接读取，见 [`crate::pipeline::PixelOrder`]
+    pub fn try_capture_frame(&mut self) -> Result<Option<ImageBuffer<Rgba<u8>, Vec<u8>>>> {
         use image::RgbaImage;
         use std::io::ErrorKind::WouldBlock;
-        loop {
-            match self.capturer.frame() {
-                Ok(buffer) => {
-                    let expected = self.width * self.height * 4;
-                    if buffer.len() != expected {
-                        return Err(format!(
+        match self.capturer.frame() {
+            Ok(buffer) => {
+                let expected = self.width * self.height * 4;
+                if buffer.len() != expected {
+                    return Err(format!(
                         "Invalid buffer length {}, expected {}",
                         buffer.len(),
                         expected
                     ));
-                    }
-
-                    let mut rgba = Vec::with_capacity(expected);
-
-                    // ---- 高速 BGRA → RGBA 转换（无 bounds check）----
-                    for chunk in buffer.chunks_exact(4) {
-                        unsafe {
-                            // chunk: [B, G, R, X]
-                            let b = *chunk.get_unchecked(0);
-                            let g = *chunk.get_unchecked(1);
-                            let r = *chunk.get_unchecked(2);
+                }
 
-                            rgba.extend_from_slice(&[r, g, b, 255]);
-                        }
-                    }
+                // 复制到复用的缓冲区，不转换通道顺序
+                let mut bgra = self.pool.take(expected);
+                bgra.copy_from_slice(&buffer);
 
-                    let img = RgbaImage::from_raw(self.width as u32, self.height as u32, rgba)
-                        .ok_or_else(||"Failed to create image buffer".to_string())?;
+                let img = RgbaImage::from_raw(self.width as u32, self.height as u32, bgra)
+                    .ok_or_else(|| "Failed to create image buffer".to_string())?;
 
-                    return Ok(img);
-                }
+                Ok(Some(img))
+            }
 
-                Err(ref e) if e.kind() == WouldBlock => {
-                    std::thread::sleep(Duration::from_micros(500)); // 更短延迟
-                }
+            Err(ref e) if e.kind() == WouldBlock => Ok(None),
 
-                Err(e) => return Err(e.to_string()),
-            }
+            Err(e) => Err(e.to_string()),
         }
     }
 
-
-
-}
-pub fn compress_frame(frame: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Result<Vec<u8>> {
-    use image::codecs::jpeg::JpegEncoder;
-    use image::ExtendedColorType;
-
-    let w = frame.width();
-    let h = frame.height();
-    let buffer = frame.as_raw(); // RGBA slice
-
-    let mut rgb = Vec::with_capacity((w * h * 3) as usize);
-
-    // ---- 高速 RGBA → RGB ----
-    for px in buffer.chunks_exact(4) {
-        rgb.extend_from_slice(&px[..3]); // 直接 [R,G,B]
+    /// 归还编码完的帧缓冲区
+    pub fn recycle(&mut self, buffer: Vec<u8>) {
+        self.pool.put(buffer);
     }
 
-    let mut out = Vec::new();
-    let mut encoder = JpegEncoder::new_with_quality(&mut out, 70);
 
-    encoder
-        .encode(&rgb, w, h, ExtendedColorType::Rgb8)
-        .map_err(|e| e.to_string())?;
 
-    Ok(out)
 }
-
-
-
-
-
-
 //
 // // 高性能屏幕流服务器
 // pub struct ScreenStreamServer {
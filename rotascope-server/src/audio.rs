@@ -0,0 +1,363 @@
+use crate::config::{AudioConfig, AudioSourceKind};
+use rotascope_core::{AudioCodec, Result, ServerMessage, timestamp_millis};
+use std::io::Read;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+/// 采样落后于墙上时钟超过该值时重新对齐时间戳（例如音频源中断后恢复）
+const RESYNC_THRESHOLD_MS: u64 = 200;
+
+/// 没有会话订阅时检查订阅者和停止请求的间隔
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: u8,
+}
+
+/// 音频采集源，每次读取固定数量的帧（每帧包含所有声道的一个采样）
+pub trait AudioSource: Send {
+    fn format(&self) -> AudioFormat;
+    fn read_frames(&mut self, frames: usize) -> Result<Vec<i16>>;
+    /// 读取是否按真实时间阻塞；否则由调用方控制节奏
+    fn is_realtime(&self) -> bool {
+        true
+    }
+}
+
+pub trait AudioEncoder: Send {
+    fn codec(&self) -> AudioCodec;
+    fn encode(&mut self, samples: &[i16]) -> Result<Vec<u8>>;
+}
+
+/// 正弦波测试源，不依赖声卡
+#[derive(Debug)]
+pub struct SineSource {
+    format: AudioFormat,
+    frequency: f32,
+    phase: f32,
+}
+
+impl SineSource {
+    pub fn new(format: AudioFormat, frequency: f32) -> Self {
+        Self {
+            format,
+            frequency,
+            phase: 0.0,
+        }
+    }
+}
+
+impl AudioSource for SineSource {
+    fn format(&self) -> AudioFormat {
+        self.format
+    }
+
+    fn read_frames(&mut self, frames: usize) -> Result<Vec<i16>> {
+        let step = std::f32::consts::TAU * self.frequency / self.format.sample_rate as f32;
+        let mut samples = Vec::with_capacity(frames * self.format.channels as usize);
+        for _ in 0..frames {
+            let value = (self.phase.sin() * i16::MAX as f32 * 0.5) as i16;
+            for _ in 0..self.format.channels {
+                samples.push(value);
+            }
+            self.phase = (self.phase + step) % std::f32::consts::TAU;
+        }
+        Ok(samples)
+    }
+
+    fn is_realtime(&self) -> bool {
+        false
+    }
+}
+
+/// 通过 parec 录制 PulseAudio 的 monitor 源（PipeWire 下由 pipewire-pulse 提供）
+#[derive(Debug)]
+pub struct MonitorSource {
+    format: AudioFormat,
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl MonitorSource {
+    pub fn spawn(device: &str, format: AudioFormat) -> Result<Self> {
+        let mut child = Command::new("parec")
+            .arg(format!("--device={}", device))
+            .arg("--raw")
+            .arg("--format=s16le")
+            .arg(format!("--rate={}", format.sample_rate))
+            .arg(format!("--channels={}", format.channels))
+            .arg("--latency-msec=20")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to start parec: {}", e))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| "parec has no stdout".to_string())?;
+
+        Ok(Self {
+            format,
+            child,
+            stdout,
+        })
+    }
+}
+
+impl AudioSource for MonitorSource {
+    fn format(&self) -> AudioFormat {
+        self.format
+    }
+
+    fn read_frames(&mut self, frames: usize) -> Result<Vec<i16>> {
+        let mut bytes = vec![0u8; frames * self.format.channels as usize * 2];
+        self.stdout
+            .read_exact(&mut bytes)
+            .map_err(|e| format!("Audio capture stopped: {}", e))?;
+        Ok(bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect())
+    }
+}
+
+impl Drop for MonitorSource {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PcmEncoder;
+
+impl AudioEncoder for PcmEncoder {
+    fn codec(&self) -> AudioCodec {
+        AudioCodec::Pcm16
+    }
+
+    fn encode(&mut self, samples: &[i16]) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(samples.len() * 2);
+        for sample in samples {
+            out.extend_from_slice(&sample.to_le_bytes());
+        }
+        Ok(out)
+    }
+}
+
+/// 采集 → 编码 → 打时间戳
+pub struct AudioPipeline {
+    source: Box<dyn AudioSource>,
+    encoder: Box<dyn AudioEncoder>,
+    frames_per_chunk: usize,
+    /// 时间戳锚点：(锚点时刻, 锚点之后已输出的帧数)
+    anchor: Option<(u64, u64)>,
+}
+
+impl AudioPipeline {
+    pub fn new(source: Box<dyn AudioSource>, encoder: Box<dyn AudioEncoder>, chunk_ms: u32) -> Self {
+        let frames_per_chunk =
+            (source.format().sample_rate as u64 * chunk_ms.max(1) as u64 / 1000).max(1) as usize;
+        Self {
+            source,
+            encoder,
+            frames_per_chunk,
+            anchor: None,
+        }
+    }
+
+    pub fn from_config(config: &AudioConfig) -> Result<Self> {
+        let format = AudioFormat {
+            sample_rate: config.sample_rate,
+            channels: config.channels,
+        };
+        let source: Box<dyn AudioSource> = match config.source {
+            AudioSourceKind::Monitor => Box::new(MonitorSource::spawn(&config.device, format)?),
+            AudioSourceKind::Sine => Box::new(SineSource::new(format, 440.0)),
+        };
+        Ok(Self::new(source, Box::new(PcmEncoder), config.chunk_ms))
+    }
+
+    pub fn chunk_duration(&self) -> Duration {
+        Duration::from_micros(
+            self.frames_per_chunk as u64 * 1_000_000 / self.source.format().sample_rate as u64,
+        )
+    }
+
+    /// 读取下一个音频块；时间戳是块内第一个采样的采集时刻
+    pub fn next_chunk(&mut self) -> Result<ServerMessage> {
+        let format = self.source.format();
+        let samples = self.source.read_frames(self.frames_per_chunk)?;
+        let data = self.encoder.encode(&samples)?;
+
+        // 读取返回时整块刚好采集完成，所以第一个采样大约在一个块长之前
+        let chunk_ms = self.frames_per_chunk as u64 * 1000 / format.sample_rate as u64;
+        let captured_at = timestamp_millis().saturating_sub(chunk_ms);
+        let (anchor_ts, anchor_frames) = match self.anchor {
+            Some((ts, frames))
+                if ts + frames * 1000 / format.sample_rate as u64 + RESYNC_THRESHOLD_MS
+                    >= captured_at =>
+            {
+                (ts, frames)
+            }
+            _ => (captured_at, 0),
+        };
+        let timestamp = anchor_ts + anchor_frames * 1000 / format.sample_rate as u64;
+        self.anchor = Some((anchor_ts, anchor_frames + self.frames_per_chunk as u64));
+
+        Ok(ServerMessage::AudioChunk {
+            codec: self.encoder.codec(),
+            sample_rate: format.sample_rate,
+            channels: format.channels,
+            data,
+            timestamp,
+        })
+    }
+}
+
+/// 后台音频线程，输出的 AudioChunk 通过广播分发给各个会话
+#[derive(Debug, Clone)]
+pub struct AudioStream {
+    chunks: broadcast::Sender<ServerMessage>,
+}
+
+impl AudioStream {
+    /// 启动音频线程，直到 stop 被取消；只在有会话订阅时采集，没有订阅者时结束 parec
+    pub fn start(config: &AudioConfig, stop: CancellationToken) -> Result<Self> {
+        // 先创建一次采集管线，尽早发现 parec 不可用等问题
+        let pipeline = AudioPipeline::from_config(config)?;
+        // 音频块很小，保留约 1 秒的缓冲，慢的客户端直接丢弃旧数据
+        let (chunks, _) = broadcast::channel(64);
+        let sender = chunks.clone();
+        let config = config.clone();
+
+        std::thread::Builder::new()
+            .name("audio".to_string())
+            .spawn(move || {
+                // 采集管线及其限速起点；丢弃管线时 MonitorSource 会结束 parec
+                let mut running = Some((pipeline, Instant::now(), Duration::ZERO));
+                while !stop.is_cancelled() {
+                    if sender.receiver_count() == 0 {
+                        running = None;
+                        std::thread::sleep(IDLE_POLL_INTERVAL);
+                        continue;
+                    }
+                    let (pipeline, started, elapsed) = match &mut running {
+                        Some(running) => running,
+                        None => match AudioPipeline::from_config(&config) {
+                            Ok(pipeline) => running.insert((pipeline, Instant::now(), Duration::ZERO)),
+                            Err(e) => {
+                                tracing::error!("Audio pipeline stopped: {}", e);
+                                break;
+                            }
+                        },
+                    };
+                    // 非实时的源（如测试用正弦波）按块时长限速
+                    if !pipeline.source.is_realtime() {
+                        *elapsed += pipeline.chunk_duration();
+                        if let Some(wait) = elapsed.checked_sub(started.elapsed()) {
+                            std::thread::sleep(wait);
+                        }
+                    }
+                    match pipeline.next_chunk() {
+                        Ok(chunk) => {
+                            // 最后一个订阅者刚好离开时发送失败是正常的
+                            let _ = sender.send(chunk);
+                        }
+                        Err(e) => {
+                            tracing::error!("Audio pipeline stopped: {}", e);
+                            break;
+                        }
+                    }
+                }
+            })
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self { chunks })
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerMessage> {
+        self.chunks.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMAT: AudioFormat = AudioFormat {
+        sample_rate: 48_000,
+        channels: 2,
+    };
+
+    #[test]
+    fn sine_source_produces_interleaved_frames() {
+        let mut source = SineSource::new(FORMAT, 440.0);
+        let samples = source.read_frames(480).unwrap();
+        assert_eq!(samples.len(), 960);
+        // 两个声道的采样相同
+        assert!(samples.chunks_exact(2).all(|f| f[0] == f[1]));
+        assert!(samples.iter().any(|s| *s > 0));
+        assert!(samples.iter().any(|s| *s < 0));
+    }
+
+    #[test]
+    fn pipeline_emits_pcm_chunks_on_a_continuous_clock() {
+        let mut pipeline = AudioPipeline::new(
+            Box::new(SineSource::new(FORMAT, 440.0)),
+            Box::new(PcmEncoder),
+            20,
+        );
+
+        let mut timestamps = Vec::new();
+        for _ in 0..5 {
+            match pipeline.next_chunk().unwrap() {
+                ServerMessage::AudioChunk {
+                    codec,
+                    sample_rate,
+                    channels,
+                    data,
+                    timestamp,
+                } => {
+                    assert_eq!(codec, AudioCodec::Pcm16);
+                    assert_eq!(sample_rate, 48_000);
+                    assert_eq!(channels, 2);
+                    // 20ms * 48kHz * 2 声道 * 2 字节
+                    assert_eq!(data.len(), 960 * 2 * 2);
+                    timestamps.push(timestamp);
+                }
+                other => panic!("unexpected message: {:?}", other),
+            }
+        }
+
+        assert!(timestamps.windows(2).all(|w| w[1] - w[0] == 20));
+        let now = timestamp_millis();
+        assert!(timestamps[0] <= now && now - timestamps[0] < 1000);
+    }
+
+    #[tokio::test]
+    async fn stream_stops_when_cancelled() {
+        let config = AudioConfig {
+            enabled: true,
+            source: AudioSourceKind::Sine,
+            ..AudioConfig::default()
+        };
+        let stop = CancellationToken::new();
+        let stream = AudioStream::start(&config, stop.clone()).unwrap();
+        let mut chunks = stream.subscribe();
+        // 之后只有音频线程持有发送端，线程退出后广播随之关闭
+        drop(stream);
+        assert!(matches!(chunks.recv().await, Ok(ServerMessage::AudioChunk { .. })));
+
+        stop.cancel();
+        let closed = tokio::time::timeout(Duration::from_secs(1), async {
+            while !matches!(chunks.recv().await, Err(broadcast::error::RecvError::Closed)) {}
+        })
+        .await;
+        assert!(closed.is_ok(), "audio thread kept running after cancellation");
+    }
+}
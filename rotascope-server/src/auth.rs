@@ -0,0 +1,470 @@
+use crate::config::AuthConfig;
+use qrcode::QrCode;
+use qrcode::render::unicode::Dense1x2;
+use rand::Rng;
+use rotascope_core::auth::verify_auth_proof;
+use rotascope_core::{Credentials, Result, timestamp_millis};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 同一地址的锁定时长上限
+const MAX_LOCKOUT: Duration = Duration::from_secs(3600);
+
+/// 超过这段时间没有再失败的地址不再记录
+const FAILURE_MEMORY: Duration = Duration::from_secs(24 * 3600);
+
+/// 已配对的设备
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedDevice {
+    pub device_id: String,
+    pub name: String,
+    pub token: String,
+    pub paired_at: u64,
+}
+
+/// 本地信任库：保存已配对设备的长期令牌
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TrustStore {
+    #[serde(skip)]
+    path: PathBuf,
+    devices: Vec<TrustedDevice>,
+}
+
+impl TrustStore {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut store = if path.exists() {
+            let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+            serde_json::from_str::<TrustStore>(&text)
+                .map_err(|e| format!("Invalid trust store {}: {}", path.display(), e))?
+        } else {
+            TrustStore::default()
+        };
+        store.path = path.to_path_buf();
+        Ok(store)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let text = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        write_private_file(&self.path, text.as_bytes())
+    }
+
+    pub fn find(&self, device_id: &str) -> Option<&TrustedDevice> {
+        self.devices.iter().find(|d| d.device_id == device_id)
+    }
+
+    pub fn add(&mut self, device: TrustedDevice) -> Result<()> {
+        self.devices.retain(|d| d.device_id != device.device_id);
+        self.devices.push(device);
+        self.save()
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+}
+
+/// 令牌等敏感文件只允许当前用户读写
+pub(crate) fn write_private_file(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(|e| e.to_string())?;
+    std::io::Write::write_all(&mut file, contents).map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthOutcome {
+    pub device_id: String,
+    pub device_name: String,
+    /// 仅在首次配对时返回新签发的令牌
+    pub token: Option<String>,
+}
+
+/// 某个地址的认证失败记录
+#[derive(Debug)]
+struct PeerFailures {
+    /// 上次锁定结束后的失败次数
+    failures: u32,
+    /// 已被锁定的次数，决定下一次锁定的时长
+    lockouts: u32,
+    locked_until: Option<Instant>,
+    last_failure: Instant,
+}
+
+#[derive(Debug)]
+struct AuthState {
+    store: TrustStore,
+    pairing_code: String,
+    /// 所有地址的配对码失败次数
+    failed_attempts: u32,
+    /// 失败过多时暂停配对，直到该时刻
+    pairing_locked_until: Option<Instant>,
+    peers: HashMap<IpAddr, PeerFailures>,
+    /// 打印配对信息时使用的地址
+    public_addr: Option<SocketAddr>,
+    /// 服务端 TLS 证书指纹，随配对信息下发用于证书固定
+    tls_fingerprint: Option<String>,
+}
+
+/// 连接认证：一次性配对码 + 长期设备令牌
+#[derive(Debug)]
+pub struct Authenticator {
+    config: AuthConfig,
+    state: Mutex<AuthState>,
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig) -> Result<Self> {
+        let store = TrustStore::load(&config.trust_store)?;
+        tracing::info!("Loaded {} trusted devices", store.len());
+        Ok(Self {
+            config: config.clone(),
+            state: Mutex::new(AuthState {
+                store,
+                pairing_code: new_pairing_code(),
+                failed_attempts: 0,
+                pairing_locked_until: None,
+                peers: HashMap::new(),
+                public_addr: None,
+                tls_fingerprint: None,
+            }),
+        })
+    }
+
+    /// 每次握手使用的随机 nonce
+    pub fn new_nonce() -> String {
+        random_hex(16)
+    }
+
+    /// 当前有效的配对码，便于嵌入方自行展示
+    pub fn pairing_code(&self) -> String {
+        self.state.lock().unwrap().pairing_code.clone()
+    }
+
+    /// 记录监听地址和证书指纹，并打印当前的配对码
+    pub fn announce(&self, listen_addr: SocketAddr, tls_fingerprint: Option<&str>) {
+        let mut state = self.state.lock().unwrap();
+        state.public_addr = Some(public_addr(listen_addr));
+        state.tls_fingerprint = tls_fingerprint.map(str::to_string);
+        print_pairing_info(&state);
+    }
+
+    /// 连续失败过多的地址在锁定期内直接拒绝，不再发出挑战
+    pub fn check_peer(&self, peer: IpAddr) -> Result<()> {
+        check_lockout(&self.state.lock().unwrap(), peer)
+    }
+
+    /// 校验客户端的凭据；失败计入 peer 的失败次数，锁定期内的地址直接拒绝
+    pub fn verify(&self, peer: IpAddr, nonce: &str, device_name: &str, credentials: Credentials) -> Result<AuthOutcome> {
+        let mut state = self.state.lock().unwrap();
+        check_lockout(&state, peer)?;
+        let result = self.check_credentials(&mut state, nonce, device_name, credentials);
+        match &result {
+            Ok(_) => {
+                state.peers.remove(&peer);
+            }
+            Err(_) => self.record_failure(&mut state, peer),
+        }
+        result
+    }
+
+    fn check_credentials(
+        &self,
+        state: &mut AuthState,
+        nonce: &str,
+        device_name: &str,
+        credentials: Credentials,
+    ) -> Result<AuthOutcome> {
+        match credentials {
+            Credentials::DeviceToken { device_id, proof } => {
+                let device = state
+                    .store
+                    .find(&device_id)
+                    .ok_or_else(|| "Unknown device, pairing required".to_string())?;
+                if !verify_auth_proof(&device.token, nonce, &proof) {
+                    return Err("Invalid device token".to_string());
+                }
+                Ok(AuthOutcome {
+                    device_id,
+                    device_name: device.name.clone(),
+                    token: None,
+                })
+            }
+            Credentials::PairingCode { proof } => {
+                if state.pairing_locked_until.is_some_and(|until| until > Instant::now()) {
+                    return Err("Pairing is paused after too many failed attempts".to_string());
+                }
+                if !verify_auth_proof(&state.pairing_code, nonce, &proof) {
+                    // 来自多个地址的猜测：暂停配对并更换配对码，每次暂停只打印一次新的配对信息
+                    state.failed_attempts += 1;
+                    if state.failed_attempts >= self.config.max_failed_pairings {
+                        tracing::warn!("Too many failed pairing attempts, pausing pairing for {}s", self.config.lockout_secs);
+                        state.pairing_locked_until = Some(Instant::now() + Duration::from_secs(self.config.lockout_secs));
+                        rotate_pairing_code(state);
+                    }
+                    return Err("Invalid pairing code".to_string());
+                }
+
+                let device = TrustedDevice {
+                    device_id: random_hex(8),
+                    name: device_name.to_string(),
+                    token: random_hex(32),
+                    paired_at: timestamp_millis(),
+                };
+                state.store.add(device.clone())?;
+                tracing::info!("Paired new device {} ({})", device.name, device.device_id);
+                // 配对码只能使用一次
+                rotate_pairing_code(state);
+
+                Ok(AuthOutcome {
+                    device_id: device.device_id,
+                    device_name: device.name,
+                    token: Some(device.token),
+                })
+            }
+        }
+    }
+
+    /// 同一地址连续失败 max_failed_attempts 次后锁定，每次再被锁定时时长加倍
+    fn record_failure(&self, state: &mut AuthState, peer: IpAddr) {
+        let now = Instant::now();
+        state.peers.retain(|_, p| now.duration_since(p.last_failure) < FAILURE_MEMORY);
+        let record = state.peers.entry(peer).or_insert(PeerFailures {
+            failures: 0,
+            lockouts: 0,
+            locked_until: None,
+            last_failure: now,
+        });
+        record.last_failure = now;
+        record.failures += 1;
+        if record.failures >= self.config.max_failed_attempts {
+            let lockout = Duration::from_secs(self.config.lockout_secs)
+                .saturating_mul(1 << record.lockouts.min(16))
+                .min(MAX_LOCKOUT);
+            tracing::warn!("Too many failed attempts from {}, locked out for {:?}", peer, lockout);
+            record.failures = 0;
+            record.lockouts += 1;
+            record.locked_until = Some(now + lockout);
+        }
+    }
+}
+
+fn check_lockout(state: &AuthState, peer: IpAddr) -> Result<()> {
+    match state.peers.get(&peer).and_then(|p| p.locked_until) {
+        Some(until) if until > Instant::now() => Err(format!(
+            "Too many failed attempts, retry in {}s",
+            (until - Instant::now()).as_secs() + 1
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn rotate_pairing_code(state: &mut AuthState) {
+    state.pairing_code = new_pairing_code();
+    state.failed_attempts = 0;
+    print_pairing_info(state);
+}
+
+fn new_pairing_code() -> String {
+    format!("{:06}", rand::rng().random_range(0..1_000_000))
+}
+
+pub(crate) fn random_hex(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    rand::rng().fill(&mut buf[..]);
+    hex::encode(buf)
+}
+
+/// 客户端扫码得到的配对信息；带 fp 时客户端应使用 wss:// 并校验证书指纹
+pub fn pairing_payload(addr: SocketAddr, code: &str, tls_fingerprint: Option<&str>) -> String {
+    let mut payload = format!("rotascope://pair?host={}&port={}&code={}", addr.ip(), addr.port(), code);
+    if let Some(fp) = tls_fingerprint {
+        payload.push_str("&fp=");
+        payload.push_str(fp);
+    }
+    payload
+}
+
+fn print_pairing_info(state: &AuthState) {
+    let Some(addr) = state.public_addr else {
+        return;
+    };
+    let payload = pairing_payload(addr, &state.pairing_code, state.tls_fingerprint.as_deref());
+    tracing::info!("Pairing code: {}", state.pairing_code);
+    println!("Pairing code: {}  ({})", state.pairing_code, payload);
+    if let Ok(code) = QrCode::new(payload.as_bytes()) {
+        println!("{}", code.render::<Dense1x2>().quiet_zone(true).build());
+    }
+}
+
+/// 监听 0.0.0.0 时，用默认路由所在网卡的地址作为对外地址
+fn public_addr(listen_addr: SocketAddr) -> SocketAddr {
+    if !listen_addr.ip().is_unspecified() {
+        return listen_addr;
+    }
+    let ip = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| {
+            // UDP connect 不会真正发包，只用来确定出口地址
+            socket.connect((Ipv4Addr::new(8, 8, 8, 8), 80))?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    SocketAddr::new(ip, listen_addr.port())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rotascope_core::auth::auth_proof;
+
+    const PEER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+
+    fn authenticator(name: &str) -> Authenticator {
+        let path = std::env::temp_dir().join(format!(
+            "rotascope-trust-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        Authenticator::new(&AuthConfig {
+            trust_store: path.to_string_lossy().into_owned(),
+            ..AuthConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn pairing_issues_token_and_code_is_single_use() {
+        let auth = authenticator("pair");
+        let code = auth.pairing_code();
+
+        let nonce = Authenticator::new_nonce();
+        let outcome = auth
+            .verify(PEER, &nonce, "phone", Credentials::PairingCode { proof: auth_proof(&code, &nonce) })
+            .unwrap();
+        let token = outcome.token.expect("pairing returns a token");
+
+        // 同一个配对码不能再用
+        let nonce = Authenticator::new_nonce();
+        assert!(auth
+            .verify(PEER, &nonce, "other", Credentials::PairingCode { proof: auth_proof(&code, &nonce) })
+            .is_err());
+
+        // 令牌可以重复使用，并且已写入信任库
+        let nonce = Authenticator::new_nonce();
+        let again = auth
+            .verify(
+                PEER,
+                &nonce,
+                "phone",
+                Credentials::DeviceToken {
+                    device_id: outcome.device_id.clone(),
+                    proof: auth_proof(&token, &nonce),
+                },
+            )
+            .unwrap();
+        assert_eq!(again.device_id, outcome.device_id);
+        assert!(again.token.is_none());
+
+        let path = auth.state.lock().unwrap().store.path.clone();
+        let reloaded = TrustStore::load(&path).unwrap();
+        assert!(reloaded.find(&outcome.device_id).is_some());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn wrong_credentials_are_rejected() {
+        let auth = authenticator("reject");
+        let nonce = Authenticator::new_nonce();
+        let wrong = if auth.pairing_code() == "000000" { "000001" } else { "000000" };
+        assert!(auth
+            .verify(PEER, &nonce, "phone", Credentials::PairingCode { proof: auth_proof(wrong, &nonce) })
+            .is_err());
+        assert!(auth
+            .verify(
+                PEER,
+                &nonce,
+                "phone",
+                Credentials::DeviceToken {
+                    device_id: "unknown".to_string(),
+                    proof: auth_proof("token", &nonce),
+                },
+            )
+            .is_err());
+    }
+
+    fn wrong_code(auth: &Authenticator) -> Credentials {
+        let wrong = if auth.pairing_code() == "000000" { "000001" } else { "000000" };
+        let nonce = Authenticator::new_nonce();
+        Credentials::PairingCode { proof: auth_proof(wrong, &nonce) }
+    }
+
+    #[test]
+    fn repeated_failures_lock_out_the_peer() {
+        let auth = authenticator("lockout");
+        let config = AuthConfig::default();
+        for _ in 0..config.max_failed_attempts {
+            assert!(auth.check_peer(PEER).is_ok());
+            let _ = auth.verify(PEER, "nonce", "x", wrong_code(&auth));
+        }
+        assert!(auth.check_peer(PEER).is_err());
+        // 锁定期内正确的配对码也被拒绝，其他地址不受影响
+        let code = auth.pairing_code();
+        let nonce = Authenticator::new_nonce();
+        let proof = auth_proof(&code, &nonce);
+        assert!(auth.verify(PEER, &nonce, "x", Credentials::PairingCode { proof: proof.clone() }).is_err());
+        let other = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 21));
+        assert!(auth.check_peer(other).is_ok());
+        assert!(auth.verify(other, &nonce, "x", Credentials::PairingCode { proof }).is_ok());
+
+        // 再次被锁定时时长加倍
+        let state = auth.state.lock().unwrap();
+        let record = &state.peers[&PEER];
+        assert_eq!(record.lockouts, 1);
+        let remaining = record.locked_until.unwrap() - Instant::now();
+        assert!(remaining <= Duration::from_secs(config.lockout_secs));
+        drop(state);
+        auth.state.lock().unwrap().peers.get_mut(&PEER).unwrap().locked_until = Some(Instant::now());
+        for _ in 0..config.max_failed_attempts {
+            let _ = auth.verify(PEER, "nonce", "x", wrong_code(&auth));
+        }
+        let remaining = auth.state.lock().unwrap().peers[&PEER].locked_until.unwrap() - Instant::now();
+        assert!(remaining > Duration::from_secs(config.lockout_secs));
+    }
+
+    #[test]
+    fn pairing_pauses_after_failures_from_many_peers() {
+        let auth = authenticator("pause");
+        let code = auth.pairing_code();
+        for i in 0..AuthConfig::default().max_failed_pairings {
+            let peer = IpAddr::V4(Ipv4Addr::new(10, 0, (i / 250) as u8, (i % 250) as u8));
+            let _ = auth.verify(peer, "nonce", "x", wrong_code(&auth));
+        }
+        let state = auth.state.lock().unwrap();
+        assert_eq!(state.failed_attempts, 0);
+        assert_ne!(state.pairing_code, code);
+        drop(state);
+
+        let nonce = Authenticator::new_nonce();
+        let proof = auth_proof(&auth.pairing_code(), &nonce);
+        let err = auth.verify(PEER, &nonce, "x", Credentials::PairingCode { proof }).unwrap_err();
+        assert!(err.contains("paused"));
+    }
+}
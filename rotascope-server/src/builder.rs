@@ -0,0 +1,231 @@
+use crate::clipboard::ClipboardBackend;
+use crate::config::{AuthConfig, ServerConfig};
+use crate::metrics::ServerStatus;
+use crate::server::{MultiDisplayServer, ServerEvent};
+use crate::video::{CaptureFactory, CaptureSource, FrameEncoder, JpegEncoder};
+use crate::window::{self, WindowSystem};
+use rotascope_core::{Arrangement, DisplayLayout, Result};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+/// 默认的虚拟显示器：(id, 宽, 高)
+const DEFAULT_DISPLAYS: [(u32, u32, u32); 3] = [(0, 1920, 1080), (1, 1920, 1080), (2, 2560, 1440)];
+
+/// 以库的方式嵌入服务端
+///
+/// ```no_run
+/// # async fn run() -> rotascope_core::Result<()> {
+/// use rotascope_server::ServerBuilder;
+/// use rotascope_server::video::{CaptureFactory, TestPatternSource};
+///
+/// let handle = ServerBuilder::new()
+///     .listen_addr("127.0.0.1:0")
+///     .capture(CaptureFactory::new(|| Ok(Box::new(TestPatternSource::new(320, 240, 30)))))
+///     .start()
+///     .await?;
+/// println!("listening on {}", handle.local_addr());
+/// handle.shutdown();
+/// handle.wait().await
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ServerBuilder {
+    config: ServerConfig,
+    /// 通过 `auth` 设置的认证策略，优先于 config 中的 auth
+    auth: Option<AuthConfig>,
+    listen_addr: String,
+    displays: Vec<(u32, u32, u32)>,
+    /// 未设置时采集主显示器；开启 composite 时采集并拼接所有显示器
+    capture: Option<CaptureFactory>,
+    encoder: Arc<dyn FrameEncoder>,
+    /// 未设置时使用当前平台的窗口系统
+    windows: Option<Arc<dyn WindowSystem>>,
+    /// 未设置时使用当前平台的桌面剪贴板
+    clipboard: Option<Box<dyn ClipboardBackend>>,
+    handle_signals: bool,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self {
+            config: ServerConfig::default(),
+            auth: None,
+            listen_addr: "0.0.0.0:8080".to_string(),
+            displays: DEFAULT_DISPLAYS.to_vec(),
+            capture: None,
+            encoder: Arc::new(JpegEncoder::default()),
+            windows: None,
+            clipboard: None,
+            handle_signals: false,
+        }
+    }
+}
+
+impl ServerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 整体替换配置；通过 `auth` 设置的认证策略不受影响
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn listen_addr(mut self, addr: impl Into<String>) -> Self {
+        self.listen_addr = addr.into();
+        self
+    }
+
+    pub fn displays(mut self, displays: Vec<(u32, u32, u32)>) -> Self {
+        self.displays = displays;
+        self
+    }
+
+    pub fn capture(mut self, capture: CaptureFactory) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// 用闭包创建采集源，等价于 `capture(CaptureFactory::new(..))`
+    pub fn capture_source<F>(self, factory: F) -> Self
+    where
+        F: Fn() -> Result<Box<dyn CaptureSource>> + Send + Sync + 'static,
+    {
+        self.capture(CaptureFactory::new(factory))
+    }
+
+    pub fn encoder(mut self, encoder: impl FrameEncoder + 'static) -> Self {
+        self.encoder = Arc::new(encoder);
+        self
+    }
+
+    /// CaptureWindow 使用的窗口系统，默认为 X11
+    pub fn window_system(mut self, windows: impl WindowSystem + 'static) -> Self {
+        self.windows = Some(Arc::new(windows));
+        self
+    }
+
+    /// clipboard.enabled 时使用的桌面剪贴板，默认为 X11
+    pub fn clipboard_backend(mut self, backend: impl ClipboardBackend + 'static) -> Self {
+        self.clipboard = Some(Box::new(backend));
+        self
+    }
+
+    /// 认证策略，无论在 `config` 之前还是之后调用都会生效；`required: false` 时任何客户端都可以直接连接
+    pub fn auth(mut self, auth: AuthConfig) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// 是否由服务端自己处理 Ctrl-C / SIGTERM；嵌入时通常由宿主负责
+    pub fn handle_signals(mut self, enabled: bool) -> Self {
+        self.handle_signals = enabled;
+        self
+    }
+
+    /// 创建虚拟显示器并开始监听；返回时端口已经绑定
+    pub async fn start(mut self) -> Result<ServerHandle> {
+        if let Some(auth) = self.auth.take() {
+            self.config.auth = auth;
+        }
+        self.config.validate()?;
+        let windows = self
+            .windows
+            .unwrap_or_else(|| window::default_system(self.config.displays.window_fps));
+        let capture = self.capture.unwrap_or_else(|| {
+            if self.config.composite.enabled {
+                CaptureFactory::all_screens()
+            } else {
+                CaptureFactory::primary_screen()
+            }
+        });
+        let server = MultiDisplayServer::new(self.config, self.displays, capture, self.encoder, windows, self.clipboard)?;
+        server.start_virtual_displays().await?;
+
+        let listener = TcpListener::bind(&self.listen_addr)
+            .await
+            .map_err(|e| format!("Failed to bind {}: {}", self.listen_addr, e))?;
+        let local_addr = listener.local_addr().map_err(|e| e.to_string())?;
+
+        let handle_signals = self.handle_signals;
+        let task = tokio::spawn({
+            let server = server.clone();
+            async move { server.serve(listener, handle_signals).await }
+        });
+
+        Ok(ServerHandle {
+            local_addr,
+            server,
+            task,
+        })
+    }
+}
+
+/// 运行中的服务端
+#[derive(Debug)]
+pub struct ServerHandle {
+    local_addr: SocketAddr,
+    server: MultiDisplayServer,
+    task: JoinHandle<Result<()>>,
+}
+
+impl ServerHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn tls_fingerprint(&self) -> Option<&str> {
+        self.server.tls_fingerprint()
+    }
+
+    pub fn pairing_code(&self) -> Option<String> {
+        self.server.pairing_code()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.server.subscribe()
+    }
+
+    /// 当前的运行状态和统计信息，与 GET /status 相同
+    pub async fn status(&self) -> ServerStatus {
+        self.server.status().await
+    }
+
+    /// 运行时添加一个虚拟显示器并通知所有客户端，返回其编号；不受 displays 配置中客户端限制的约束
+    pub async fn add_display(&self, name: &str, width: u32, height: u32) -> Result<u8> {
+        self.server.add_display(name, width, height).await
+    }
+
+    /// 移除虚拟显示器并通知所有客户端；不能移除最后一个显示器
+    pub async fn remove_display(&self, index: u8) -> Result<()> {
+        self.server.remove_display(index).await
+    }
+
+    pub async fn resize_display(&self, index: u8, width: u32, height: u32) -> Result<()> {
+        self.server.resize_display(index, width, height).await
+    }
+
+    /// 按预设方式重新排列显示器，布局会保存到 displays.layout_file
+    pub async fn arrange_displays(&self, arrangement: Arrangement) -> Result<()> {
+        self.server.arrange_displays(arrangement).await
+    }
+
+    /// 单独摆放一个显示器，排列方式随之变为 Custom
+    pub async fn set_display_layout(&self, index: u8, layout: DisplayLayout) -> Result<()> {
+        self.server.set_display_layout(index, layout).await
+    }
+
+    /// 请求停止；用 `wait` 等待停止完成
+    pub fn shutdown(&self) {
+        self.server.shutdown();
+    }
+
+    /// 等待服务端退出（收到关闭请求或信号）
+    pub async fn wait(self) -> Result<()> {
+        self.task.await.map_err(|e| e.to_string())?
+    }
+}
@@ -49,7 +49,7 @@ impl ScreenCapturer {
         Ok(image.into_raw())
     }
 
-    // pub async fn capture_display(&self, display_index: u8) -> Result<FrameData> {
+    // pub async fn capture_display(&self, _display_index: u8) -> Result<FrameData> {
     //     // 这里实现具体的屏幕捕获逻辑
     //     // 对于演示，我们创建一个测试图像
     //
@@ -92,7 +92,7 @@ impl ScreenCapturer {
     //     })
     // }
 
-    pub async fn capture_display(&self, display_index: u8) -> Result<FrameData> {
+    pub async fn capture_display(&self, _display_index: u8) -> Result<FrameData> {
         let width = 1920;
         let height = 1080;
 
@@ -113,8 +113,7 @@ impl ScreenCapturer {
             jpeg_data = self.compress_jpeg(&jpeg_data, 70)?; // 70% 质量
         }
 
-        log::debug!("Generated JPEG: {}x{}, {} bytes", width, height, jpeg_data.len());
-        println!("Generated JPEG: {}x{}, {} bytes", width, height, jpeg_data.len());
+        tracing::debug!("Generated JPEG: {}x{}, {} bytes", width, height, jpeg_data.len());
 
         Ok(FrameData {
             width,
@@ -143,7 +142,7 @@ impl ScreenCapturer {
         Ok(compressed_data)
     }
 
-    fn add_display_text(&self, img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, display_index: u8) {
+    fn add_display_text(&self, img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, _display_index: u8) {
         // 在实际实现中，可以使用 imageproc 添加文本
         // 这里简化为修改一些像素来表示文本
         let text_x = 100;
@@ -151,7 +150,7 @@ impl ScreenCapturer {
 
         for i in 0..50 {
             for j in 0..50 {
-                if i < 10 || i > 40 || j < 10 || j > 40 {
+                if !(10..=40).contains(&i) || !(10..=40).contains(&j) {
                     let x = text_x + i;
                     let y = text_y + j;
                     if x < img.width() && y < img.height() {
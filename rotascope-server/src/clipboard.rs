@@ -0,0 +1,414 @@
+use crate::config::ClipboardConfig;
+use rotascope_core::Result;
+use rotascope_core::clipboard::validate_clipboard;
+use std::fmt;
+use std::sync::mpsc::Receiver;
+use tokio::sync::broadcast;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardContent {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// 一次剪贴板变化
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardChange {
+    pub content: ClipboardContent,
+    /// 写入该内容的会话 ID，来自桌面时为 None；不会再发回给写入它的会话
+    pub origin: Option<String>,
+}
+
+/// 桌面剪贴板的读写，在独立线程中运行
+pub trait ClipboardBackend: Send + fmt::Debug {
+    /// 将 writes 收到的内容写入桌面剪贴板，桌面剪贴板被其他程序改变时调用 changed；
+    /// writes 关闭后返回
+    fn run(
+        self: Box<Self>,
+        writes: Receiver<ClipboardContent>,
+        changed: &mut dyn FnMut(ClipboardContent),
+    ) -> Result<()>;
+}
+
+/// 当前平台的桌面剪贴板
+pub fn default_backend(config: &ClipboardConfig) -> Result<Box<dyn ClipboardBackend>> {
+    platform::connect(config)
+}
+
+/// 桌面剪贴板同步：后台线程监听桌面剪贴板变化并广播，同时接受来自客户端的写入
+#[derive(Debug, Clone)]
+pub struct ClipboardSync {
+    max_bytes: usize,
+    set_tx: std::sync::mpsc::Sender<ClipboardContent>,
+    updates: broadcast::Sender<ClipboardChange>,
+}
+
+impl ClipboardSync {
+    pub fn start(config: &ClipboardConfig, backend: Box<dyn ClipboardBackend>) -> Result<Self> {
+        let (set_tx, set_rx) = std::sync::mpsc::channel();
+        let (updates, _) = broadcast::channel(8);
+
+        let desktop = updates.clone();
+        std::thread::Builder::new()
+            .name("clipboard".to_string())
+            .spawn(move || {
+                let mut changed = |content| {
+                    // 没有订阅者时发送失败是正常的
+                    let _ = desktop.send(ClipboardChange { content, origin: None });
+                };
+                if let Err(e) = backend.run(set_rx, &mut changed) {
+                    tracing::error!("Clipboard worker stopped: {}", e);
+                }
+            })
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            max_bytes: config.max_bytes,
+            set_tx,
+            updates,
+        })
+    }
+
+    /// 订阅剪贴板的变化，包括桌面上的复制和各会话的写入
+    pub fn subscribe(&self) -> broadcast::Receiver<ClipboardChange> {
+        self.updates.subscribe()
+    }
+
+    /// 将会话 origin 发来的内容写入桌面剪贴板，并转发给其他会话
+    pub fn set(&self, content: ClipboardContent, origin: &str) -> Result<()> {
+        validate_clipboard(&content.mime_type, &content.data, self.max_bytes)?;
+        self.set_tx
+            .send(content.clone())
+            .map_err(|_| "Clipboard worker has stopped".to_string())?;
+        let _ = self.updates.send(ClipboardChange {
+            content,
+            origin: Some(origin.to_string()),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{ClipboardBackend, ClipboardContent};
+    use crate::config::ClipboardConfig;
+    use rotascope_core::Result;
+    use rotascope_core::clipboard::{MIME_PNG, MIME_TEXT, validate_clipboard};
+    use std::sync::mpsc::{Receiver, RecvTimeoutError};
+    use std::time::Duration;
+    use x11rb::connection::{Connection, RequestConnection};
+    use x11rb::protocol::Event;
+    use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+    use x11rb::protocol::xproto::{
+        Atom, AtomEnum, ConnectionExt as _, CreateWindowAux, EventMask, PropMode,
+        SelectionNotifyEvent, SelectionRequestEvent, Window, WindowClass,
+        SELECTION_NOTIFY_EVENT,
+    };
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::{COPY_DEPTH_FROM_PARENT, CURRENT_TIME, NONE};
+
+    x11rb::atom_manager! {
+        Atoms: AtomsCookie {
+            CLIPBOARD,
+            TARGETS,
+            INCR,
+            TEXT,
+            UTF8_STRING,
+            TEXT_PLAIN_UTF8: b"text/plain;charset=utf-8",
+            IMAGE_PNG: b"image/png",
+            ROTASCOPE_CLIPBOARD,
+        }
+    }
+
+    /// 处理完 X 事件后等待客户端写入的最长时间
+    const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+    /// ChangeProperty 请求除数据以外的长度（字节）
+    const CHANGE_PROPERTY_HEADER: usize = 24;
+
+    pub fn connect(config: &ClipboardConfig) -> Result<Box<dyn ClipboardBackend>> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(|e| e.to_string())?;
+        Ok(Box::new(X11Clipboard::new(conn, screen_num, config.clone())?))
+    }
+
+    #[derive(Debug)]
+    struct X11Clipboard {
+        conn: RustConnection,
+        window: Window,
+        atoms: Atoms,
+        config: ClipboardConfig,
+        /// 由客户端写入、当前由我们持有的剪贴板内容
+        owned: Option<ClipboardContent>,
+        /// 最近一次看到的内容，用于避免重复广播
+        last_seen: Option<ClipboardContent>,
+    }
+
+    impl ClipboardBackend for X11Clipboard {
+        fn run(
+            mut self: Box<Self>,
+            writes: Receiver<ClipboardContent>,
+            changed: &mut dyn FnMut(ClipboardContent),
+        ) -> Result<()> {
+            // 只有与 X 服务器的连接出错时才退出；单个事件出错（例如请求方窗口已销毁）只记录日志
+            loop {
+                while let Some(event) = self.conn.poll_for_event().map_err(|e| e.to_string())? {
+                    if let Err(e) = self.handle_event(event, changed) {
+                        tracing::warn!("Failed to handle clipboard event: {}", e);
+                    }
+                }
+                // 客户端写入的新内容：接管 CLIPBOARD 选区
+                match writes.recv_timeout(EVENT_POLL_INTERVAL) {
+                    Ok(content) => {
+                        if let Err(e) = self.take_ownership(content) {
+                            tracing::warn!("Failed to take clipboard ownership: {}", e);
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            }
+        }
+    }
+
+    impl X11Clipboard {
+        fn new(conn: RustConnection, screen_num: usize, config: ClipboardConfig) -> Result<Self> {
+            let root = conn.setup().roots[screen_num].root;
+            let window = conn.generate_id().map_err(|e| e.to_string())?;
+            conn.create_window(
+                COPY_DEPTH_FROM_PARENT,
+                window,
+                root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                0,
+                &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+            )
+            .map_err(|e| e.to_string())?;
+            let atoms = Atoms::new(&conn)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| e.to_string())?;
+            // 由 XFixes 通知 CLIPBOARD 的所有者变化，不需要轮询
+            conn.xfixes_query_version(5, 0)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| format!("XFixes is not available: {}", e))?;
+            conn.xfixes_select_selection_input(
+                window,
+                atoms.CLIPBOARD,
+                SelectionEventMask::SET_SELECTION_OWNER
+                    | SelectionEventMask::SELECTION_WINDOW_DESTROY
+                    | SelectionEventMask::SELECTION_CLIENT_CLOSE,
+            )
+            .map_err(|e| e.to_string())?;
+            conn.flush().map_err(|e| e.to_string())?;
+
+            Ok(Self {
+                conn,
+                window,
+                atoms,
+                config,
+                owned: None,
+                last_seen: None,
+            })
+        }
+
+        fn handle_event(&mut self, event: Event, changed: &mut dyn FnMut(ClipboardContent)) -> Result<()> {
+            match event {
+                Event::SelectionRequest(request) => self.answer_request(request)?,
+                Event::SelectionClear(_) => self.owned = None,
+                // 其他程序复制了新内容
+                Event::XfixesSelectionNotify(notify) if notify.owner != self.window && notify.owner != NONE => {
+                    self.request_conversion(self.atoms.TARGETS)?;
+                }
+                Event::SelectionNotify(notify) => {
+                    if let Some(content) = self.read_conversion(notify)?
+                        && self.last_seen.as_ref() != Some(&content)
+                    {
+                        tracing::debug!(
+                            "Desktop clipboard changed: {} ({} bytes)",
+                            content.mime_type,
+                            content.data.len()
+                        );
+                        self.last_seen = Some(content.clone());
+                        changed(content);
+                    }
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+
+        /// 一次 ChangeProperty 能写入的最大内容；还没有实现 INCR，更大的内容无法提供给其他程序
+        fn max_payload(&self) -> usize {
+            self.conn.maximum_request_bytes().saturating_sub(CHANGE_PROPERTY_HEADER)
+        }
+
+        fn take_ownership(&mut self, content: ClipboardContent) -> Result<()> {
+            if content.data.len() > self.max_payload() {
+                return Err(format!(
+                    "Clipboard content of {} bytes exceeds the X request limit of {} bytes",
+                    content.data.len(),
+                    self.max_payload()
+                ));
+            }
+            self.conn
+                .set_selection_owner(self.window, self.atoms.CLIPBOARD, CURRENT_TIME)
+                .map_err(|e| e.to_string())?;
+            self.conn.flush().map_err(|e| e.to_string())?;
+            self.last_seen = Some(content.clone());
+            self.owned = Some(content);
+            Ok(())
+        }
+
+        fn request_conversion(&self, target: Atom) -> Result<()> {
+            self.conn
+                .convert_selection(
+                    self.window,
+                    self.atoms.CLIPBOARD,
+                    target,
+                    self.atoms.ROTASCOPE_CLIPBOARD,
+                    CURRENT_TIME,
+                )
+                .map_err(|e| e.to_string())?;
+            self.conn.flush().map_err(|e| e.to_string())?;
+            Ok(())
+        }
+
+        fn text_targets(&self) -> [Atom; 4] {
+            [
+                self.atoms.UTF8_STRING,
+                self.atoms.TEXT_PLAIN_UTF8,
+                self.atoms.TEXT,
+                AtomEnum::STRING.into(),
+            ]
+        }
+
+        /// 处理 ConvertSelection 的结果：先拿到 TARGETS，再按优先级取图片或文本
+        fn read_conversion(&self, notify: SelectionNotifyEvent) -> Result<Option<ClipboardContent>> {
+            if notify.property == NONE {
+                return Ok(None);
+            }
+            let max_words = (self.config.max_bytes / 4 + 1) as u32;
+            let reply = self
+                .conn
+                .get_property(true, self.window, notify.property, AtomEnum::ANY, 0, max_words)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| e.to_string())?;
+
+            if reply.type_ == self.atoms.INCR || reply.bytes_after > 0 {
+                tracing::debug!("Desktop clipboard content exceeds the size limit, skipped");
+                return Ok(None);
+            }
+
+            if notify.target == self.atoms.TARGETS {
+                let targets: Vec<Atom> = reply.value32().map(|v| v.collect()).unwrap_or_default();
+                if targets.contains(&self.atoms.IMAGE_PNG) {
+                    self.request_conversion(self.atoms.IMAGE_PNG)?;
+                } else if let Some(target) =
+                    self.text_targets().into_iter().find(|t| targets.contains(t))
+                {
+                    self.request_conversion(target)?;
+                }
+                return Ok(None);
+            }
+
+            let mime_type = if notify.target == self.atoms.IMAGE_PNG {
+                MIME_PNG
+            } else if self.text_targets().contains(&notify.target) {
+                MIME_TEXT
+            } else {
+                return Ok(None);
+            };
+            if validate_clipboard(mime_type, &reply.value, self.config.max_bytes).is_err() {
+                return Ok(None);
+            }
+
+            Ok(Some(ClipboardContent {
+                mime_type: mime_type.to_string(),
+                data: reply.value,
+            }))
+        }
+
+        /// 其他程序向我们请求剪贴板内容
+        fn answer_request(&self, request: SelectionRequestEvent) -> Result<()> {
+            // 旧式客户端可能不提供 property
+            let property = if request.property == NONE {
+                request.target
+            } else {
+                request.property
+            };
+
+            let provided = match &self.owned {
+                Some(content) if request.selection == self.atoms.CLIPBOARD => {
+                    let targets: Vec<Atom> = if content.mime_type == MIME_PNG {
+                        vec![self.atoms.TARGETS, self.atoms.IMAGE_PNG]
+                    } else {
+                        let mut t = vec![self.atoms.TARGETS];
+                        t.extend(self.text_targets());
+                        t
+                    };
+
+                    if request.target == self.atoms.TARGETS {
+                        self.conn
+                            .change_property32(
+                                PropMode::REPLACE,
+                                request.requestor,
+                                property,
+                                AtomEnum::ATOM,
+                                &targets,
+                            )
+                            .map_err(|e| e.to_string())?;
+                        true
+                    } else if targets.contains(&request.target) {
+                        self.conn
+                            .change_property8(
+                                PropMode::REPLACE,
+                                request.requestor,
+                                property,
+                                request.target,
+                                &content.data,
+                            )
+                            .map_err(|e| e.to_string())?;
+                        true
+                    } else {
+                        false
+                    }
+                }
+                _ => false,
+            };
+
+            let notify = SelectionNotifyEvent {
+                response_type: SELECTION_NOTIFY_EVENT,
+                sequence: 0,
+                time: request.time,
+                requestor: request.requestor,
+                selection: request.selection,
+                target: request.target,
+                property: if provided { property } else { NONE },
+            };
+            self.conn
+                .send_event(false, request.requestor, EventMask::NO_EVENT, notify)
+                .map_err(|e| e.to_string())?;
+            self.conn.flush().map_err(|e| e.to_string())?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::ClipboardBackend;
+    use crate::config::ClipboardConfig;
+    use rotascope_core::Result;
+
+    pub fn connect(_config: &ClipboardConfig) -> Result<Box<dyn ClipboardBackend>> {
+        Err("Clipboard sync is only supported on Linux (X11)".to_string())
+    }
+}
@@ -0,0 +1,461 @@
+use rotascope_core::Result;
+use rotascope_core::clipboard::DEFAULT_CLIPBOARD_MAX_BYTES;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// 服务端配置，从 config.json 读取；缺省字段使用默认值
+///
+/// 默认只推流和认证，剪贴板、音频、TLS、mDNS、录制和指标接口等功能都需要显式开启
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub clipboard: ClipboardConfig,
+    pub audio: AudioConfig,
+    pub auth: AuthConfig,
+    pub tls: TlsConfig,
+    pub discovery: DiscoveryConfig,
+    pub recording: RecordingConfig,
+    pub metrics: MetricsConfig,
+    pub logging: LoggingConfig,
+    pub heartbeat: HeartbeatConfig,
+    pub resume: ResumeConfig,
+    pub displays: DisplaysConfig,
+    pub zoom: ZoomConfig,
+    pub foveation: FoveationConfig,
+    pub composite: CompositeConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipboardConfig {
+    /// 是否启动剪贴板同步（每个会话仍需单独开启）
+    pub enabled: bool,
+    /// 单次剪贴板内容的大小上限（字节）
+    pub max_bytes: usize,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bytes: DEFAULT_CLIPBOARD_MAX_BYTES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioSourceKind {
+    /// 桌面声音 (PulseAudio/PipeWire monitor 源)
+    Monitor,
+    /// 440Hz 正弦波，用于测试
+    Sine,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    pub enabled: bool,
+    pub source: AudioSourceKind,
+    /// parec 使用的设备名
+    pub device: String,
+    pub sample_rate: u32,
+    pub channels: u8,
+    /// 每个 AudioChunk 的时长（毫秒）
+    pub chunk_ms: u32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            source: AudioSourceKind::Monitor,
+            device: "@DEFAULT_MONITOR@".to_string(),
+            sample_rate: 48_000,
+            channels: 2,
+            chunk_ms: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// 关闭后任何连接都可以直接获取画面（仅用于调试）
+    pub required: bool,
+    /// 已配对设备的信任库文件
+    pub trust_store: String,
+    /// 等待客户端 Hello 的超时（秒）
+    pub handshake_timeout_secs: u64,
+    /// 同一地址连续认证失败多少次后被锁定
+    pub max_failed_attempts: u32,
+    /// 锁定时长（秒），同一地址每次再被锁定时加倍
+    pub lockout_secs: u64,
+    /// 所有地址的配对码失败次数达到该值后暂停配对 lockout_secs 并更换配对码
+    pub max_failed_pairings: u32,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            required: true,
+            trust_store: "trusted_devices.json".to_string(),
+            handshake_timeout_secs: 10,
+            max_failed_attempts: 5,
+            lockout_secs: 60,
+            max_failed_pairings: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// 接受 TLS (wss://) 连接；同一端口上仍可接受明文连接
+    pub enabled: bool,
+    /// 拒绝所有明文连接
+    pub required: bool,
+    /// 证书和私钥 (PEM)，不存在时首次启动自动生成自签名证书
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            required: false,
+            cert_path: "tls/cert.pem".to_string(),
+            key_path: "tls/key.pem".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    /// 通过 mDNS 广播 _rotascope._tcp 服务
+    pub enabled: bool,
+    /// 广播的服务端名称，缺省使用主机名
+    pub name: Option<String>,
+    /// 同时在回环接口上广播（本机调试用）
+    pub include_loopback: bool,
+}
+
+impl DiscoveryConfig {
+    pub fn server_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            std::fs::read_to_string("/etc/hostname")
+                .ok()
+                .or_else(|| std::env::var("COMPUTERNAME").ok())
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| "RotaScope".to_string())
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingConfig {
+    /// 允许客户端通过 SetRecording 录制自己的会话
+    pub enabled: bool,
+    /// 录制文件保存目录
+    pub directory: String,
+    /// 单个录制文件的大小上限（字节），达到后自动结束录制
+    pub max_bytes: u64,
+    /// 单次录制的时长上限（秒）
+    pub max_secs: u64,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: "recordings".to_string(),
+            max_bytes: 1 << 30,
+            max_secs: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// 在 WebSocket 端口上提供 GET /metrics (Prometheus) 和 GET /status (JSON)，两者都不需要认证
+    pub enabled: bool,
+    /// 也回应其他主机的请求；默认只回应本机 (loopback) 的请求
+    pub allow_remote: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeartbeatConfig {
+    /// 服务端定期发送 Ping 和 Heartbeat，并断开长时间没有任何数据的客户端
+    pub enabled: bool,
+    /// 发送间隔（毫秒）
+    pub interval_ms: u64,
+    /// 超过该时间没有收到客户端任何数据（包括 Pong）即断开（毫秒）
+    pub timeout_ms: u64,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_ms: 5_000,
+            timeout_ms: 15_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResumeConfig {
+    /// 客户端异常断开后保留会话状态，允许用 ResumeSession 恢复
+    pub enabled: bool,
+    /// 会话状态的保留时间（秒）
+    pub grace_secs: u64,
+}
+
+impl Default for ResumeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            grace_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ZoomConfig {
+    /// 允许客户端用 SetZoom 放大画面区域
+    pub enabled: bool,
+    /// 最大放大倍数
+    pub max_factor: f32,
+    /// 放大期间用头部转动平移画面
+    pub head_panning: bool,
+    /// 平移一个放大区域宽度（或高度）所需的头部转动角度
+    pub pan_degrees: f32,
+}
+
+impl Default for ZoomConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_factor: 8.0,
+            head_panning: true,
+            pan_degrees: 20.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FoveationConfig {
+    /// 按头部朝向只保留注视区域的原分辨率，降低周边画面的码率；放大期间不生效
+    pub enabled: bool,
+    /// 注视区域占画面宽高的比例
+    pub fovea_size: f32,
+    /// 周边画面的降采样倍数
+    pub periphery_scale: u32,
+    /// 画面宽度对应的水平视场角（度），用于把朝向换算到画面坐标
+    pub fov_degrees: f32,
+}
+
+impl Default for FoveationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            fovea_size: 0.35,
+            periphery_scale: 4,
+            fov_degrees: 60.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompositeConfig {
+    /// 把所有物理显示器按 X11 位置拼成一张画布推流，头部转动在画布上移动视口（不再切换显示器）
+    pub enabled: bool,
+    /// 视口尺寸，超过画布时取画布尺寸
+    pub viewport_width: u32,
+    pub viewport_height: u32,
+    /// 头部从最左转到最右对应的偏航角，视口随之扫过画布的整个宽度
+    pub yaw_degrees: f32,
+    /// 头部从最低到最高对应的俯仰角
+    pub pitch_degrees: f32,
+}
+
+impl Default for CompositeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            viewport_width: 1920,
+            viewport_height: 1080,
+            yaw_degrees: 90.0,
+            pitch_degrees: 40.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayBackendKind {
+    /// 只在内存中记录显示器，不改动系统（测试和无桌面环境时使用）
+    Simulated,
+    /// 用 `xrandr --setmonitor` 在 X 屏幕上创建虚拟显示器
+    Xrandr,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplaysConfig {
+    pub backend: DisplayBackendKind,
+    /// 允许客户端增删、调整和排列显示器
+    pub client_managed: bool,
+    /// 客户端创建显示器时的上限
+    pub max_displays: usize,
+    /// 客户端新建或调整显示器时的尺寸上限
+    pub max_width: u32,
+    pub max_height: u32,
+    /// 显示器布局的保存位置；为 null（默认）时不保存
+    pub layout_file: Option<String>,
+    /// 允许客户端通过 CaptureWindow 把单个窗口作为显示器推流（需要 client_managed）
+    pub window_capture: bool,
+    /// 窗口采集的帧率上限
+    pub window_fps: u32,
+    /// 屏幕采集的帧率上限
+    pub capture_fps: u32,
+}
+
+impl Default for DisplaysConfig {
+    fn default() -> Self {
+        Self {
+            backend: DisplayBackendKind::Simulated,
+            client_managed: true,
+            max_displays: 6,
+            max_width: 3840,
+            max_height: 2160,
+            layout_file: None,
+            window_capture: true,
+            window_fps: 30,
+            capture_fps: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// 每行一个 JSON 对象，包含当前 span 链
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// tracing 过滤规则，例如 "info" 或 "info,rotascope_server=debug"；设置了 RUST_LOG 时以环境变量为准
+    pub filter: String,
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            filter: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// 读取配置文件；文件不存在时返回默认配置
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            tracing::info!("Config file {} not found, using defaults", path.display());
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let config: Self = serde_json::from_str(&text)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.tls.required && !self.tls.enabled {
+            return Err("tls.required needs tls.enabled".to_string());
+        }
+        let auth = &self.auth;
+        if auth.max_failed_attempts == 0 || auth.max_failed_pairings == 0 || auth.lockout_secs == 0 {
+            return Err("auth.max_failed_attempts, auth.max_failed_pairings and auth.lockout_secs must be at least 1".to_string());
+        }
+        let audio = &self.audio;
+        if !(8_000..=192_000).contains(&audio.sample_rate) || !(1..=8).contains(&audio.channels) {
+            return Err("audio.sample_rate must be between 8000 and 192000 and audio.channels between 1 and 8".to_string());
+        }
+        if self.recording.max_bytes == 0 || self.recording.max_secs == 0 {
+            return Err("recording.max_bytes and recording.max_secs must be at least 1".to_string());
+        }
+        let heartbeat = &self.heartbeat;
+        if heartbeat.enabled && (heartbeat.interval_ms == 0 || heartbeat.timeout_ms <= heartbeat.interval_ms) {
+            return Err("heartbeat.timeout_ms must be greater than heartbeat.interval_ms".to_string());
+        }
+        if self.displays.max_displays == 0 || self.displays.max_displays > u8::MAX as usize {
+            return Err(format!("displays.max_displays must be between 1 and {}", u8::MAX));
+        }
+        if self.displays.window_fps == 0 {
+            return Err("displays.window_fps must be at least 1".to_string());
+        }
+        if self.displays.capture_fps == 0 {
+            return Err("displays.capture_fps must be at least 1".to_string());
+        }
+        let zoom = &self.zoom;
+        if !(1.0..f32::INFINITY).contains(&zoom.max_factor) {
+            return Err("zoom.max_factor must be at least 1".to_string());
+        }
+        if !(zoom.pan_degrees.is_finite() && zoom.pan_degrees > 0.0) {
+            return Err("zoom.pan_degrees must be positive".to_string());
+        }
+        let foveation = &self.foveation;
+        if !(foveation.fovea_size > 0.0 && foveation.fovea_size <= 1.0) {
+            return Err("foveation.fovea_size must be in (0, 1]".to_string());
+        }
+        if foveation.periphery_scale == 0 || !(foveation.fov_degrees.is_finite() && foveation.fov_degrees > 0.0) {
+            return Err("foveation.periphery_scale must be at least 1 and foveation.fov_degrees positive".to_string());
+        }
+        let composite = &self.composite;
+        if composite.viewport_width == 0 || composite.viewport_height == 0 {
+            return Err("composite.viewport_width and composite.viewport_height must be at least 1".to_string());
+        }
+        if ![composite.yaw_degrees, composite.pitch_degrees]
+            .iter()
+            .all(|degrees| degrees.is_finite() && *degrees > 0.0)
+        {
+            return Err("composite.yaw_degrees and composite.pitch_degrees must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_config_keeps_side_effecting_features_off() {
+        let config = ServerConfig::load(concat!(env!("CARGO_MANIFEST_DIR"), "/config.json")).unwrap();
+        assert!(!config.clipboard.enabled);
+        assert!(!config.audio.enabled);
+        assert!(!config.tls.enabled);
+        assert!(!config.discovery.enabled);
+        assert!(!config.recording.enabled);
+        assert!(!config.metrics.enabled);
+        assert!(config.displays.layout_file.is_none());
+    }
+}
@@ -0,0 +1,351 @@
+//! 物理桌面拼接：采集每个 scrap 显示器，按它们在 X11 中的位置拼成一张画布，
+//! 推流时只发送画布上的一个视口，视口随头部转动在画布上移动。
+
+use crate::config::CompositeConfig;
+use crate::pipeline::{self, BufferPool};
+use crate::video::CaptureSource;
+use crate::zoom::Region;
+use image::RgbaImage;
+use rotascope_core::Result;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// 物理显示器在桌面坐标系中的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonitorRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// 各显示器在画布中的位置；画布是包含所有显示器的最小矩形，显示器之间的空隙为黑色
+#[derive(Debug, Clone)]
+pub struct DesktopLayout {
+    monitors: Vec<MonitorRect>,
+    origin: (i32, i32),
+    width: u32,
+    height: u32,
+}
+
+impl DesktopLayout {
+    pub fn new(monitors: Vec<MonitorRect>) -> Result<Self> {
+        if monitors.is_empty() {
+            return Err("No monitors to compose".to_string());
+        }
+        if monitors.iter().any(|m| m.width == 0 || m.height == 0) {
+            return Err("Monitor size must not be zero".to_string());
+        }
+        let left = monitors.iter().map(|m| m.x).min().unwrap_or(0);
+        let top = monitors.iter().map(|m| m.y).min().unwrap_or(0);
+        let right = monitors.iter().map(|m| m.x + m.width as i32).max().unwrap_or(0);
+        let bottom = monitors.iter().map(|m| m.y + m.height as i32).max().unwrap_or(0);
+        Ok(Self {
+            monitors,
+            origin: (left, top),
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        })
+    }
+
+    /// 画布尺寸
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn monitors(&self) -> &[MonitorRect] {
+        &self.monitors
+    }
+
+    /// 黑色的空画布（RGBA）
+    pub fn blank_canvas(&self) -> Vec<u8> {
+        [0, 0, 0, 255].repeat(self.width as usize * self.height as usize)
+    }
+
+    /// 把第 index 个显示器的 BGRA 画面转换后写入画布的对应位置
+    pub fn blit(&self, index: usize, bgra: &[u8], canvas: &mut [u8]) -> Result<()> {
+        let monitor = self
+            .monitors
+            .get(index)
+            .ok_or_else(|| format!("No monitor {}", index))?;
+        let row = monitor.width as usize * 4;
+        if bgra.len() != row * monitor.height as usize {
+            return Err(format!(
+                "Monitor {} frame has {} bytes, expected {} for {}x{}",
+                index,
+                bgra.len(),
+                row * monitor.height as usize,
+                monitor.width,
+                monitor.height
+            ));
+        }
+        let stride = self.width as usize * 4;
+        let left = (monitor.x - self.origin.0) as usize * 4;
+        let top = (monitor.y - self.origin.1) as usize;
+        for (y, src) in bgra.chunks_exact(row).enumerate() {
+            let start = (top + y) * stride + left;
+            pipeline::bgra_to_rgba(src, &mut canvas[start..start + row]);
+        }
+        Ok(())
+    }
+}
+
+/// 采集所有物理显示器并拼接成一张画布；只有某个显示器有新画面时才输出新的一帧
+pub struct DesktopCapture {
+    capturers: Vec<platform::MonitorCapturer>,
+    layout: DesktopLayout,
+    /// 各显示器最近一帧拼成的画布，没有更新的显示器保留上一帧
+    canvas: Vec<u8>,
+    pool: BufferPool,
+}
+
+impl fmt::Debug for DesktopCapture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DesktopCapture").field("layout", &self.layout).finish()
+    }
+}
+
+impl DesktopCapture {
+    /// 打开所有显示器；每个显示器的位置和采集器来自同一次枚举，不需要再对应两份列表
+    pub fn new_all() -> Result<Self> {
+        let (monitors, capturers): (Vec<_>, Vec<_>) = platform::open_monitors()?.into_iter().unzip();
+        let layout = DesktopLayout::new(monitors)?;
+        let (width, height) = layout.size();
+        tracing::info!("Composing {} monitors into a {}x{} desktop", capturers.len(), width, height);
+        Ok(Self {
+            capturers,
+            canvas: layout.blank_canvas(),
+            layout,
+            pool: BufferPool::new(pipeline::POOLED_FRAMES),
+        })
+    }
+}
+
+impl CaptureSource for DesktopCapture {
+    fn capture_frame(&mut self) -> Result<RgbaImage> {
+        loop {
+            if let Some(frame) = self.try_capture_frame()? {
+                return Ok(frame);
+            }
+            std::thread::sleep(Duration::from_micros(500));
+        }
+    }
+
+    fn try_capture_frame(&mut self) -> Result<Option<RgbaImage>> {
+        use std::io::ErrorKind::WouldBlock;
+        let mut updated = false;
+        for (index, capturer) in self.capturers.iter_mut().enumerate() {
+            match capturer.frame() {
+                Ok(frame) => {
+                    self.layout.blit(index, &frame, &mut self.canvas)?;
+                    updated = true;
+                }
+                Err(ref e) if e.kind() == WouldBlock => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        if !updated {
+            return Ok(None);
+        }
+        let mut buffer = self.pool.take(self.canvas.len());
+        buffer.copy_from_slice(&self.canvas);
+        let (width, height) = self.layout.size();
+        RgbaImage::from_raw(width, height, buffer)
+            .map(Some)
+            .ok_or_else(|| "Failed to create image buffer".to_string())
+    }
+
+    fn recycle(&mut self, frame: RgbaImage) {
+        self.pool.put(frame.into_raw());
+    }
+}
+
+/// 拼接画布上的视口，跟随头部朝向移动
+#[derive(Debug)]
+pub struct Viewport {
+    config: CompositeConfig,
+    /// (偏航, 俯仰)，单位为度；没有传感器数据时位于画布中央
+    gaze: Mutex<Option<(f32, f32)>>,
+}
+
+impl Viewport {
+    pub fn new(config: &CompositeConfig) -> Self {
+        Self {
+            config: config.clone(),
+            gaze: Mutex::new(None),
+        }
+    }
+
+    /// 记录头部朝向；未开启拼接时返回 false，由调用方按原方式处理
+    pub fn look(&self, yaw: f32, pitch: f32) -> bool {
+        if !self.config.enabled {
+            return false;
+        }
+        *self.gaze.lock().unwrap() = Some((yaw, pitch));
+        true
+    }
+
+    /// 当前朝向在 width x height 画布上对应的视口
+    pub fn region(&self, width: u32, height: u32) -> Region {
+        let gaze = *self.gaze.lock().unwrap();
+        viewport_region(&self.config, width, height, gaze)
+    }
+
+    /// 从画布中裁出视口；未开启时原样返回
+    pub fn apply(&self, frame: RgbaImage) -> RgbaImage {
+        if !self.config.enabled {
+            return frame;
+        }
+        let region = self.region(frame.width(), frame.height());
+        if (region.width, region.height) == frame.dimensions() {
+            return frame;
+        }
+        image::imageops::crop_imm(&frame, region.x, region.y, region.width, region.height).to_image()
+    }
+}
+
+/// 偏航从 -yaw_degrees/2 到 +yaw_degrees/2 时视口从画布最左移到最右，俯仰同理；抬头时视口向上
+pub fn viewport_region(config: &CompositeConfig, width: u32, height: u32, gaze: Option<(f32, f32)>) -> Region {
+    let w = config.viewport_width.clamp(1, width);
+    let h = config.viewport_height.clamp(1, height);
+    let (yaw, pitch) = gaze.unwrap_or((0.0, 0.0));
+    let across = (0.5 + yaw / config.yaw_degrees).clamp(0.0, 1.0);
+    let down = (0.5 - pitch / config.pitch_degrees).clamp(0.0, 1.0);
+    Region {
+        x: ((width - w) as f32 * across).round() as u32,
+        y: ((height - h) as f32 * down).round() as u32,
+        width: w,
+        height: h,
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::MonitorRect;
+    use rotascope_core::Result;
+    use scrap::x11::{Capturer, Server};
+    use std::io;
+    use std::ops::Deref;
+    use std::rc::Rc;
+
+    /// 单个显示器的采集器
+    pub struct MonitorCapturer(Capturer);
+
+    impl MonitorCapturer {
+        /// X11 的采集总是返回整屏画面，不会 WouldBlock
+        pub fn frame(&mut self) -> io::Result<impl Deref<Target = [u8]> + '_> {
+            Ok(self.0.frame())
+        }
+    }
+
+    /// 直接使用 scrap 的 X11 显示器：它们来自每个 X 屏幕上活动的 RandR 显示器，自带在桌面中的位置
+    pub fn open_monitors() -> Result<Vec<(MonitorRect, MonitorCapturer)>> {
+        let server = Server::default().map_err(|e| format!("Cannot connect to the X server: {:?}", e))?;
+        Server::displays(Rc::new(server))
+            .map(|display| {
+                let rect = display.rect();
+                let monitor = MonitorRect {
+                    x: rect.x as i32,
+                    y: rect.y as i32,
+                    width: rect.w as u32,
+                    height: rect.h as u32,
+                };
+                let capturer = Capturer::new(display).map_err(|e| e.to_string())?;
+                Ok((monitor, MonitorCapturer(capturer)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::MonitorRect;
+    use rotascope_core::Result;
+    use scrap::{Capturer, Display};
+    use std::io;
+    use std::ops::Deref;
+
+    /// 单个显示器的采集器
+    pub struct MonitorCapturer(Capturer);
+
+    impl MonitorCapturer {
+        pub fn frame(&mut self) -> io::Result<impl Deref<Target = [u8]> + '_> {
+            self.0.frame()
+        }
+    }
+
+    /// scrap 在其他平台上不提供显示器位置，按枚举顺序从左到右排列
+    pub fn open_monitors() -> Result<Vec<(MonitorRect, MonitorCapturer)>> {
+        let mut x = 0;
+        Display::all()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|display| {
+                let monitor = MonitorRect {
+                    x,
+                    y: 0,
+                    width: display.width() as u32,
+                    height: display.height() as u32,
+                };
+                x += monitor.width as i32;
+                let capturer = Capturer::new(display).map_err(|e| e.to_string())?;
+                Ok((monitor, MonitorCapturer(capturer)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(x: i32, y: i32, width: u32, height: u32) -> MonitorRect {
+        MonitorRect { x, y, width, height }
+    }
+
+    #[test]
+    fn monitors_are_stitched_at_their_positions() {
+        // 右侧的显示器更矮，且下沿对齐；坐标可以为负
+        let layout = DesktopLayout::new(vec![monitor(-4, 0, 4, 3), monitor(0, 1, 2, 2)]).unwrap();
+        assert_eq!(layout.size(), (6, 3));
+
+        let mut canvas = layout.blank_canvas();
+        layout.blit(0, &[10, 20, 30, 0].repeat(12), &mut canvas).unwrap();
+        layout.blit(1, &[1, 2, 3, 0].repeat(4), &mut canvas).unwrap();
+        let image = RgbaImage::from_raw(6, 3, canvas).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [30, 20, 10, 255]);
+        assert_eq!(image.get_pixel(3, 2).0, [30, 20, 10, 255]);
+        assert_eq!(image.get_pixel(4, 1).0, [3, 2, 1, 255]);
+        assert_eq!(image.get_pixel(5, 2).0, [3, 2, 1, 255]);
+        // 右侧显示器上方的空隙保持黑色
+        assert_eq!(image.get_pixel(5, 0).0, [0, 0, 0, 255]);
+
+        let mut canvas = layout.blank_canvas();
+        assert!(layout.blit(1, &[0; 4], &mut canvas).is_err());
+        assert!(DesktopLayout::new(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn viewport_roams_with_head_orientation() {
+        let config = CompositeConfig {
+            enabled: true,
+            viewport_width: 1920,
+            viewport_height: 1080,
+            yaw_degrees: 90.0,
+            pitch_degrees: 40.0,
+        };
+        let region = |gaze| viewport_region(&config, 3840, 2160, gaze);
+
+        assert_eq!(region(None), Region { x: 960, y: 540, width: 1920, height: 1080 });
+        // 向左转到头贴住画布左边缘，再往外转也不会越界
+        assert_eq!(region(Some((-45.0, 0.0))).x, 0);
+        assert_eq!(region(Some((-90.0, 0.0))).x, 0);
+        assert_eq!(region(Some((22.5, 0.0))).x, 1440);
+        // 抬头时视口上移
+        assert_eq!(region(Some((0.0, 20.0))).y, 0);
+        assert_eq!(region(Some((0.0, -10.0))).y, 810);
+        // 视口比画布大时取整个画布
+        assert_eq!(viewport_region(&config, 800, 600, None), Region { x: 0, y: 0, width: 800, height: 600 });
+    }
+}
@@ -0,0 +1,134 @@
+//! 注视点编码：头部朝向所指的区域保持原分辨率，其余部分降采样后再放大，
+//! 周边画面变得平滑，JPEG 编码后体积明显变小，而画面尺寸不变。
+
+use crate::config::FoveationConfig;
+use crate::zoom::Region;
+use image::RgbaImage;
+use image::imageops::FilterType;
+use std::sync::Mutex;
+
+/// 最近一次的头部朝向，捕获线程据此确定注视区域
+#[derive(Debug)]
+pub struct Foveation {
+    config: FoveationConfig,
+    /// (偏航, 俯仰)，单位为度；没有传感器数据时注视画面中心
+    gaze: Mutex<Option<(f32, f32)>>,
+}
+
+impl Foveation {
+    pub fn new(config: &FoveationConfig) -> Self {
+        Self {
+            config: config.clone(),
+            gaze: Mutex::new(None),
+        }
+    }
+
+    /// 记录客户端上报的朝向；多个客户端时以最新的为准
+    pub fn look(&self, yaw: f32, pitch: f32) {
+        *self.gaze.lock().unwrap() = Some((yaw, pitch));
+    }
+
+    /// 当前朝向在 width x height 画面中对应的注视区域
+    pub fn fovea(&self, width: u32, height: u32) -> Region {
+        let gaze = *self.gaze.lock().unwrap();
+        fovea_region(&self.config, width, height, gaze)
+    }
+
+    /// 合成注视点画面；未开启时原样返回
+    pub fn apply(&self, frame: RgbaImage) -> RgbaImage {
+        if !self.config.enabled {
+            return frame;
+        }
+        let fovea = self.fovea(frame.width(), frame.height());
+        compose(&frame, fovea, self.config.periphery_scale)
+    }
+}
+
+/// 偏航角 ±fov/2 对应画面左右边缘，俯仰按画面宽高比换算；抬头时注视区域向上
+pub fn fovea_region(config: &FoveationConfig, width: u32, height: u32, gaze: Option<(f32, f32)>) -> Region {
+    let w = ((width as f32 * config.fovea_size).round() as u32).clamp(1, width);
+    let h = ((height as f32 * config.fovea_size).round() as u32).clamp(1, height);
+    let (center_x, center_y) = match gaze {
+        Some((yaw, pitch)) => {
+            let vertical_fov = config.fov_degrees * height as f32 / width as f32;
+            (
+                width as f32 * (0.5 + yaw / config.fov_degrees),
+                height as f32 * (0.5 - pitch / vertical_fov),
+            )
+        }
+        None => (width as f32 / 2.0, height as f32 / 2.0),
+    };
+    let x = (center_x - w as f32 / 2.0).round().clamp(0.0, (width - w) as f32) as u32;
+    let y = (center_y - h as f32 / 2.0).round().clamp(0.0, (height - h) as f32) as u32;
+    Region {
+        x,
+        y,
+        width: w,
+        height: h,
+    }
+}
+
+/// 周边降采样 scale 倍后放大回原尺寸，再贴上原分辨率的注视区域
+pub fn compose(frame: &RgbaImage, fovea: Region, scale: u32) -> RgbaImage {
+    let (width, height) = frame.dimensions();
+    if scale <= 1 || (fovea.width, fovea.height) == (width, height) {
+        return frame.clone();
+    }
+    let small = image::imageops::resize(
+        frame,
+        (width / scale).max(1),
+        (height / scale).max(1),
+        FilterType::Triangle,
+    );
+    let mut composed = image::imageops::resize(&small, width, height, FilterType::Triangle);
+    let sharp = image::imageops::crop_imm(frame, fovea.x, fovea.y, fovea.width, fovea.height).to_image();
+    image::imageops::replace(&mut composed, &sharp, fovea.x as i64, fovea.y as i64);
+    composed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> FoveationConfig {
+        FoveationConfig {
+            enabled: true,
+            fovea_size: 0.25,
+            periphery_scale: 4,
+            fov_degrees: 60.0,
+        }
+    }
+
+    #[test]
+    fn fovea_follows_head_orientation_and_stays_inside() {
+        let config = config();
+        let region = |gaze| fovea_region(&config, 640, 480, gaze);
+
+        assert_eq!(region(None), Region { x: 240, y: 180, width: 160, height: 120 });
+        assert_eq!(region(Some((0.0, 0.0))), region(None));
+        // 向右转 15 度（视场的四分之一）
+        assert_eq!(region(Some((15.0, 0.0))).x, 400);
+        // 抬头时注视区域上移，转到视场外时贴住边缘
+        assert!(region(Some((0.0, 10.0))).y < 180);
+        assert_eq!(region(Some((-90.0, -90.0))), Region { x: 0, y: 360, width: 160, height: 120 });
+    }
+
+    #[test]
+    fn composed_frame_keeps_geometry_and_a_sharp_fovea() {
+        let frame = RgbaImage::from_fn(64, 48, |x, y| {
+            let v = if (x + y) % 2 == 0 { 255 } else { 0 };
+            image::Rgba([v, v, v, 255])
+        });
+        let fovea = Region { x: 40, y: 8, width: 16, height: 12 };
+        let composed = compose(&frame, fovea, 4);
+
+        assert_eq!(composed.dimensions(), frame.dimensions());
+        for (x, y) in [(40, 8), (55, 19), (47, 13)] {
+            assert_eq!(composed.get_pixel(x, y), frame.get_pixel(x, y));
+        }
+        // 周边的棋盘格被平滑成灰色
+        let periphery = composed.get_pixel(5, 30)[0];
+        assert!((64..=192).contains(&periphery), "{}", periphery);
+        assert_eq!(compose(&frame, fovea, 1), frame);
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 单个会话的心跳状态：最近一次收到数据的时刻、未回应的 Ping 和往返时间
+#[derive(Debug)]
+pub struct Heartbeat {
+    last_seen: Mutex<Instant>,
+    /// 最近一次发出的 Ping (序号, 发送时刻)
+    pending: Mutex<Option<(u64, Instant)>>,
+    next_sequence: AtomicU64,
+    rtt: Mutex<Option<Duration>>,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            last_seen: Mutex::new(Instant::now()),
+            pending: Mutex::new(None),
+            next_sequence: AtomicU64::new(1),
+            rtt: Mutex::new(None),
+        }
+    }
+}
+
+impl Heartbeat {
+    /// 收到客户端的任意数据（消息、Ping、Pong）
+    pub fn touch(&self) {
+        *self.last_seen.lock().unwrap() = Instant::now();
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        self.last_seen.lock().unwrap().elapsed()
+    }
+
+    /// 生成下一个 Ping 的负载并记录发送时刻
+    pub fn next_ping(&self) -> Vec<u8> {
+        let sequence = self.next_sequence.fetch_add(1, Ordering::Relaxed);
+        *self.pending.lock().unwrap() = Some((sequence, Instant::now()));
+        sequence.to_be_bytes().to_vec()
+    }
+
+    /// 收到 Pong；负载与最近的 Ping 对应时返回并记录往返时间
+    pub fn on_pong(&self, payload: &[u8]) -> Option<Duration> {
+        let sequence = u64::from_be_bytes(payload.try_into().ok()?);
+        let mut pending = self.pending.lock().unwrap();
+        let (expected, sent) = (*pending)?;
+        if sequence != expected {
+            return None;
+        }
+        *pending = None;
+        let rtt = sent.elapsed();
+        *self.rtt.lock().unwrap() = Some(rtt);
+        Some(rtt)
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        *self.rtt.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pong_must_match_the_latest_ping() {
+        let heartbeat = Heartbeat::default();
+        let first = heartbeat.next_ping();
+        let second = heartbeat.next_ping();
+        assert_ne!(first, second);
+
+        assert_eq!(heartbeat.on_pong(&first), None);
+        assert_eq!(heartbeat.on_pong(b"garbage"), None);
+        assert!(heartbeat.rtt().is_none());
+
+        let rtt = heartbeat.on_pong(&second).unwrap();
+        assert_eq!(heartbeat.rtt(), Some(rtt));
+        // 同一个 Pong 不会被计算两次
+        assert_eq!(heartbeat.on_pong(&second), None);
+    }
+}
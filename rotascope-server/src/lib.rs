@@ -0,0 +1,30 @@
+pub mod audio;
+pub mod auth;
+pub mod builder;
+#[allow(dead_code)]
+mod capture;
+pub mod clipboard;
+pub mod config;
+pub mod desktop;
+pub mod foveation;
+mod heartbeat;
+mod http;
+pub mod logging;
+pub mod metrics;
+pub mod pipeline;
+pub mod recording;
+mod resume;
+pub mod ring;
+pub mod server;
+pub mod tls;
+pub mod video;
+pub mod virtual_display;
+pub mod window;
+pub mod zoom;
+#[allow(non_snake_case)]
+mod CrossPlatformCapturer;
+#[allow(non_snake_case, dead_code)]
+mod ScreenCapturer;
+
+pub use builder::{ServerBuilder, ServerHandle};
+pub use server::{MultiDisplayServer, ServerEvent};
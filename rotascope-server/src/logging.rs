@@ -0,0 +1,25 @@
+use crate::config::{LogFormat, LoggingConfig};
+use rotascope_core::Result;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+/// 安装全局 tracing subscriber，同时接收依赖库通过 `log` 输出的日志。
+///
+/// span 关闭时输出其耗时 (time.busy / time.idle)：过滤规则包含
+/// `rotascope_server=debug` 时，每帧的 frame / capture / encode / send span 都会输出，
+/// 可以按 seq 字段追踪单帧在管线中的延迟。
+pub fn init(config: &LoggingConfig) -> Result<()> {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(&config.filter)
+            .map_err(|e| format!("Invalid logging.filter '{}': {}", config.filter, e))?,
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE);
+    let result = match config.format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).try_init(),
+    };
+    result.map_err(|e| e.to_string())
+}
@@ -1,29 +1,46 @@
-mod capture;
-mod virtual_display;
-mod server;
-mod CrossPlatformCapturer;
-mod ScreenCapturer;
-
-use env_logger::Env;
-use server::*;
-use std::sync::Arc;
 use rotascope_core::Result;
-
+use rotascope_server::ServerBuilder;
+use rotascope_server::config::ServerConfig;
+use rotascope_server::logging;
+use rotascope_server::recording::{ReplaySource, read_recording_header};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-
-    log::info!("Starting Multi-Display PC Server...");
-    println!("Starting Multi-Display PC Server...");
-
-    let server = Arc::new(MultiDisplayServer::new(3)?); // 3个虚拟显示器
-
-    // 启动虚拟显示器
-    server.start_virtual_displays().await?;
-
-    // 启动网络服务器
-    server.start_server("0.0.0.0:8080").await?;
-
-    Ok(())
-}
\ No newline at end of file
+    // 用法: rotascope-server [config.json] [--replay <录制文件>]
+    let mut config_path = "config.json".to_string();
+    let mut replay = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--replay" => replay = Some(args.next().ok_or("--replay needs a recording file")?),
+            _ => config_path = arg,
+        }
+    }
+    let config = ServerConfig::load(&config_path)?;
+    logging::init(&config.logging)?;
+    tracing::info!("Starting Multi-Display PC Server...");
+
+    // 3个虚拟显示器，采集主显示器
+    let mut builder = ServerBuilder::new()
+        .config(config)
+        .listen_addr("0.0.0.0:8080")
+        .handle_signals(true);
+    if let Some(path) = replay {
+        // 回放时按录制时的显示器配置创建虚拟显示器
+        let header = read_recording_header(&path)?;
+        tracing::info!("Replaying {}", path);
+        builder = builder
+            .displays(
+                header
+                    .resolutions
+                    .iter()
+                    .enumerate()
+                    .map(|(id, (w, h))| (id as u32, *w, *h))
+                    .collect(),
+            )
+            .capture_source(move || Ok(Box::new(ReplaySource::open(&path)?)));
+    }
+    let server = builder.start().await?;
+
+    server.wait().await
+}
@@ -0,0 +1,430 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 速率统计的窗口
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+const SECONDS_BUCKETS: &[f64] = &[0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+const BYTES_BUCKETS: &[f64] = &[
+    8_192.0, 32_768.0, 65_536.0, 131_072.0, 262_144.0, 524_288.0, 1_048_576.0, 4_194_304.0,
+];
+
+/// Prometheus 风格的直方图，桶上限不含 +Inf
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    /// f64 的位模式
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        if let Some(bucket) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let _ = self.sum.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + value).to_bits())
+        });
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum(&self) -> f64 {
+        f64::from_bits(self.sum.load(Ordering::Relaxed))
+    }
+
+    /// 平均值，没有样本时为 0
+    pub fn mean(&self) -> f64 {
+        match self.count() {
+            0 => 0.0,
+            count => self.sum() / count as f64,
+        }
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let mut cumulative = 0;
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count());
+        let _ = writeln!(out, "{}_sum {}", name, self.sum());
+        let _ = writeln!(out, "{}_count {}", name, self.count());
+    }
+}
+
+/// 最近一个统计窗口内的事件速率（每秒）
+#[derive(Debug)]
+pub struct RateMeter {
+    state: Mutex<RateState>,
+}
+
+#[derive(Debug)]
+struct RateState {
+    window_start: Instant,
+    count: u64,
+    rate: f64,
+}
+
+impl RateMeter {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(RateState {
+                window_start: Instant::now(),
+                count: 0,
+                rate: 0.0,
+            }),
+        }
+    }
+
+    pub fn record(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.roll(Instant::now());
+        state.count += 1;
+    }
+
+    pub fn rate(&self) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        state.roll(Instant::now());
+        state.rate
+    }
+}
+
+impl Default for RateMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateState {
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed >= RATE_WINDOW {
+            self.rate = self.count as f64 / elapsed.as_secs_f64();
+            self.count = 0;
+            self.window_start = now;
+        }
+    }
+}
+
+/// 全局指标，捕获线程和各会话共享
+#[derive(Debug)]
+pub struct Metrics {
+    started: Instant,
+    pub capture_seconds: Histogram,
+    pub encode_seconds: Histogram,
+    /// 帧从采集完成到开始编码，在环形缓冲中等待的时间
+    pub queue_wait_seconds: Histogram,
+    pub frame_bytes: Histogram,
+    pub capture_rate: RateMeter,
+    /// 编码跟不上、在环形缓冲中被新帧覆盖的帧
+    pub frames_overwritten: AtomicU64,
+    pub frames_sent: AtomicU64,
+    /// 客户端发送队列已满而丢弃的视频帧
+    pub frames_dropped: AtomicU64,
+    pub sensor_messages: AtomicU64,
+    pub sensor_rate: RateMeter,
+    /// 接受过的 WebSocket 连接数，也用于给连接编号
+    pub connections: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            capture_seconds: Histogram::new(SECONDS_BUCKETS),
+            encode_seconds: Histogram::new(SECONDS_BUCKETS),
+            queue_wait_seconds: Histogram::new(SECONDS_BUCKETS),
+            frame_bytes: Histogram::new(BYTES_BUCKETS),
+            capture_rate: RateMeter::new(),
+            frames_overwritten: AtomicU64::new(0),
+            frames_sent: AtomicU64::new(0),
+            frames_dropped: AtomicU64::new(0),
+            sensor_messages: AtomicU64::new(0),
+            sensor_rate: RateMeter::new(),
+            connections: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Metrics {
+    /// 记录一帧的捕获和编码耗时
+    pub fn record_frame(&self, capture: Duration, encode: Duration, bytes: usize) {
+        self.capture_seconds.observe(capture.as_secs_f64());
+        self.encode_seconds.observe(encode.as_secs_f64());
+        self.frame_bytes.observe(bytes as f64);
+        self.capture_rate.record();
+    }
+
+    /// 记录一帧在环形缓冲中等待编码的时间
+    pub fn record_queue_wait(&self, wait: Duration) {
+        self.queue_wait_seconds.observe(wait.as_secs_f64());
+    }
+
+    pub fn record_sensor_message(&self) {
+        self.sensor_messages.fetch_add(1, Ordering::Relaxed);
+        self.sensor_rate.record();
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// 单个会话的发送统计
+#[derive(Debug)]
+pub struct ClientStats {
+    /// 连接编号，见 [`Metrics::connections`]
+    pub connection: u64,
+    connected_at: Instant,
+    pub frames_sent: AtomicU64,
+    pub frames_dropped: AtomicU64,
+    pub fps: RateMeter,
+}
+
+impl ClientStats {
+    pub fn new(connection: u64) -> Self {
+        Self {
+            connection,
+            connected_at: Instant::now(),
+            frames_sent: AtomicU64::new(0),
+            frames_dropped: AtomicU64::new(0),
+            fps: RateMeter::new(),
+        }
+    }
+
+    pub fn connected_for(&self) -> Duration {
+        self.connected_at.elapsed()
+    }
+}
+
+/// `/status` 返回的 JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerStatus {
+    pub version: String,
+    pub protocol_version: u32,
+    pub uptime_secs: u64,
+    pub total_displays: usize,
+    pub current_display: u8,
+    pub connected_clients: usize,
+    /// 异常断开后等待恢复的会话数
+    pub parked_sessions: usize,
+    pub capture: CaptureStatus,
+    pub frames_sent: u64,
+    pub frames_dropped: u64,
+    pub sensor_messages_per_second: f64,
+    pub clients: Vec<ClientStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureStatus {
+    pub frames: u64,
+    pub fps: f64,
+    pub avg_capture_ms: f64,
+    pub avg_encode_ms: f64,
+    /// 帧等待编码的平均时间
+    pub avg_queue_wait_ms: f64,
+    /// 编码跟不上而被覆盖的帧数
+    pub frames_overwritten: u64,
+    pub avg_frame_bytes: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientStatus {
+    /// 连接编号；/status 不需要认证，因此不包含地址或设备名等身份信息
+    pub connection: u64,
+    pub connected_secs: u64,
+    pub fps: f64,
+    pub frames_sent: u64,
+    pub frames_dropped: u64,
+    /// 发送队列中等待的消息数
+    pub queue_depth: usize,
+    /// 最近一次 Ping/Pong 的往返时间，尚未测得时为 None
+    pub rtt_ms: Option<f64>,
+}
+
+impl ServerStatus {
+    /// Prometheus 文本格式 (0.0.4)
+    pub fn render_prometheus(&self, metrics: &Metrics) -> String {
+        let mut out = String::new();
+        metrics
+            .capture_seconds
+            .render(&mut out, "rotascope_capture_seconds", "Time spent capturing a frame");
+        metrics
+            .encode_seconds
+            .render(&mut out, "rotascope_encode_seconds", "Time spent encoding a frame");
+        metrics.queue_wait_seconds.render(
+            &mut out,
+            "rotascope_capture_queue_wait_seconds",
+            "Time a captured frame waited before encoding started",
+        );
+        metrics
+            .frame_bytes
+            .render(&mut out, "rotascope_frame_bytes", "Size of encoded video frames");
+
+        let mut single = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        };
+        single(
+            "rotascope_uptime_seconds",
+            "gauge",
+            "Seconds since the server started",
+            self.uptime_secs.to_string(),
+        );
+        single(
+            "rotascope_capture_fps",
+            "gauge",
+            "Frames captured per second",
+            self.capture.fps.to_string(),
+        );
+        single(
+            "rotascope_capture_frames_overwritten_total",
+            "counter",
+            "Captured frames replaced by a newer frame before they were encoded",
+            self.capture.frames_overwritten.to_string(),
+        );
+        single(
+            "rotascope_frames_sent_total",
+            "counter",
+            "Video frames sent to clients",
+            self.frames_sent.to_string(),
+        );
+        single(
+            "rotascope_frames_dropped_total",
+            "counter",
+            "Video frames dropped because a client queue was full",
+            self.frames_dropped.to_string(),
+        );
+        single(
+            "rotascope_sensor_messages_total",
+            "counter",
+            "SensorData messages received",
+            metrics.sensor_messages.load(Ordering::Relaxed).to_string(),
+        );
+        single(
+            "rotascope_sensor_messages_per_second",
+            "gauge",
+            "SensorData messages received per second",
+            self.sensor_messages_per_second.to_string(),
+        );
+        single(
+            "rotascope_connections_total",
+            "counter",
+            "WebSocket connections accepted",
+            metrics.connections.load(Ordering::Relaxed).to_string(),
+        );
+        single(
+            "rotascope_connected_clients",
+            "gauge",
+            "Connected WebSocket clients",
+            self.connected_clients.to_string(),
+        );
+        single(
+            "rotascope_parked_sessions",
+            "gauge",
+            "Disconnected sessions waiting to be resumed",
+            self.parked_sessions.to_string(),
+        );
+        single(
+            "rotascope_current_display",
+            "gauge",
+            "Index of the display being streamed",
+            self.current_display.to_string(),
+        );
+
+        let clients = &self.clients;
+        per_client(&mut out, "rotascope_client_fps", "gauge", "Frames sent per second to a client", clients, |c| {
+            c.fps.to_string()
+        });
+        per_client(
+            &mut out,
+            "rotascope_client_frames_sent_total",
+            "counter",
+            "Video frames sent to a client",
+            clients,
+            |c| c.frames_sent.to_string(),
+        );
+        per_client(
+            &mut out,
+            "rotascope_client_frames_dropped_total",
+            "counter",
+            "Video frames dropped for a client",
+            clients,
+            |c| c.frames_dropped.to_string(),
+        );
+        per_client(
+            &mut out,
+            "rotascope_client_queue_depth",
+            "gauge",
+            "Messages waiting in a client send queue",
+            clients,
+            |c| c.queue_depth.to_string(),
+        );
+        per_client(
+            &mut out,
+            "rotascope_client_rtt_seconds",
+            "gauge",
+            "Round-trip time of the latest heartbeat ping",
+            clients,
+            |c| c.rtt_ms.map_or("NaN".to_string(), |ms| (ms / 1000.0).to_string()),
+        );
+        out
+    }
+}
+
+/// 按连接编号打标签的一组指标
+fn per_client(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    clients: &[ClientStatus],
+    value: impl Fn(&ClientStatus) -> String,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for client in clients {
+        let _ = writeln!(out, "{}{{client=\"{}\"}} {}", name, client.connection, value(client));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let histogram = Histogram::new(&[1.0, 10.0]);
+        for value in [0.5, 2.0, 3.0, 50.0] {
+            histogram.observe(value);
+        }
+        let mut out = String::new();
+        histogram.render(&mut out, "test", "help");
+        assert!(out.contains("test_bucket{le=\"1\"} 1\n"));
+        assert!(out.contains("test_bucket{le=\"10\"} 3\n"));
+        assert!(out.contains("test_bucket{le=\"+Inf\"} 4\n"));
+        assert!(out.contains("test_sum 55.5\n"));
+        assert_eq!(histogram.mean(), 55.5 / 4.0);
+    }
+}
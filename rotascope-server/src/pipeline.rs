@@ -0,0 +1,242 @@
+//! 捕获到编码的帧处理管线：
+//!
+//! 采集（复制到复用的缓冲区，屏幕画面保持 BGRA）→ 缩放 / 注视点 → 按条带并行编码 JPEG → 拼接成一张图。
+//!
+//! 编码阶段直接把 RGBA 或 BGRA 交给 jpeg-encoder 转换为 YCbCr（支持 AVX2 时走 SIMD），不再单独转换通道顺序；
+//! 画面按 MCU 行切成条带，各条带在 rayon 线程池中独立编码，再用重启标记（RST）拼成一张标准 JPEG，
+//! 客户端照常解码。
+
+use image::RgbaImage;
+use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
+use rayon::prelude::*;
+use rotascope_core::Result;
+use std::sync::{Arc, Mutex};
+
+/// 4:2:0 采样下一个 MCU 的边长（像素）
+const MCU_SIZE: u32 = 16;
+
+/// 低于这个行数的画面不分条带，线程调度的开销比编码本身还大
+const MIN_STRIPE_ROWS: u32 = 64;
+
+/// 同时在途的帧不超过这个数量，多余的缓冲区直接释放
+pub const POOLED_FRAMES: usize = 3;
+
+/// 帧缓冲池：编码完的帧把缓冲区还回来，下一次采集直接复用，避免每帧重新分配几 MB 内存
+#[derive(Debug, Clone)]
+pub struct BufferPool {
+    buffers: Arc<Mutex<Vec<Vec<u8>>>>,
+    capacity: usize,
+}
+
+impl BufferPool {
+    /// 最多保留 capacity 个空闲缓冲区，多余的直接释放
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buffers: Arc::new(Mutex::new(Vec::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// 取出长度为 len 的缓冲区；内容是上一次使用留下的数据，由调用方覆盖
+    pub fn take(&self, len: usize) -> Vec<u8> {
+        let mut buffer = self.buffers.lock().unwrap().pop().unwrap_or_default();
+        buffer.resize(len, 0);
+        buffer
+    }
+
+    pub fn put(&self, buffer: Vec<u8>) {
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.capacity {
+            buffers.push(buffer);
+        }
+    }
+
+    /// 当前空闲的缓冲区数量
+    pub fn available(&self) -> usize {
+        self.buffers.lock().unwrap().len()
+    }
+}
+
+/// 帧缓冲区的通道顺序。裁剪和缩放与通道顺序无关，只有编码时需要区分
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelOrder {
+    Rgba,
+    /// scrap 的屏幕画面；第四个字节可能不是 alpha，编码时忽略
+    Bgra,
+}
+
+/// BGRA / BGRX 转换为 RGBA，alpha 固定为 255；按 32 位整数交换字节，编译器会向量化为 SIMD 指令
+pub fn bgra_to_rgba(src: &[u8], dst: &mut [u8]) {
+    for (from, to) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+        let pixel = u32::from_le_bytes([from[0], from[1], from[2], from[3]]);
+        let rgba = ((pixel >> 16) & 0xff) | (pixel & 0xff00) | ((pixel & 0xff) << 16) | 0xff00_0000;
+        to.copy_from_slice(&rgba.to_le_bytes());
+    }
+}
+
+/// 大画面按行分块，在多个核心上并行转换
+pub fn bgra_to_rgba_parallel(src: &[u8], dst: &mut [u8], width: usize) {
+    let row = width * 4;
+    let block = row * MIN_STRIPE_ROWS as usize;
+    if row == 0 || src.len() <= block {
+        return bgra_to_rgba(src, dst);
+    }
+    dst.par_chunks_mut(block)
+        .zip(src.par_chunks(block))
+        .for_each(|(to, from)| bgra_to_rgba(from, to));
+}
+
+/// 编码一帧 JPEG，order 为 frame 中像素的实际通道顺序；stripes 为条带数，0 表示与线程池的线程数相同
+pub fn encode_jpeg(frame: &RgbaImage, order: PixelOrder, quality: u8, stripes: usize) -> Result<Vec<u8>> {
+    let (width, height) = frame.dimensions();
+    let color = match order {
+        PixelOrder::Rgba => ColorType::Rgba,
+        PixelOrder::Bgra => ColorType::Bgra,
+    };
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(format!("Frame {}x{} is too large for JPEG", width, height));
+    }
+    let stripes = if stripes == 0 { rayon::current_num_threads() } else { stripes };
+    let mcus_per_row = width.div_ceil(MCU_SIZE);
+    // 每个条带是整数个 MCU 行，最后一个条带可以不满；重启间隔不能超过 u16
+    let mcu_rows = height.div_ceil(MCU_SIZE);
+    let rows_per_stripe = mcu_rows
+        .div_ceil(stripes.max(1) as u32)
+        .max(MIN_STRIPE_ROWS / MCU_SIZE)
+        .min(u16::MAX as u32 / mcus_per_row.max(1));
+    if rows_per_stripe == 0 || rows_per_stripe >= mcu_rows {
+        return encode_stripe(frame.as_raw(), width, height, color, quality);
+    }
+
+    let stripe_height = rows_per_stripe * MCU_SIZE;
+    let row_bytes = width as usize * 4;
+    let parts = frame
+        .as_raw()
+        .par_chunks(stripe_height as usize * row_bytes)
+        .map(|rows| encode_stripe(rows, width, (rows.len() / row_bytes) as u32, color, quality))
+        .collect::<Result<Vec<_>>>()?;
+    stitch(&parts, height, (mcus_per_row * rows_per_stripe) as u16)
+}
+
+/// 单独编码一个条带；采样方式固定为 4:2:0，保证各条带的 MCU 对齐
+fn encode_stripe(rows: &[u8], width: u32, height: u32, color: ColorType, quality: u8) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(rows.len() / 8);
+    let mut encoder = Encoder::new(&mut out, quality);
+    encoder.set_sampling_factor(SamplingFactor::F_2_2);
+    encoder
+        .encode(rows, width as u16, height as u16, color)
+        .map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+/// 各条带使用相同的量化表和哈夫曼表，取第一个条带的文件头，把高度改为整帧高度并声明重启间隔，
+/// 之后依次接上各条带的熵编码数据，条带之间插入 RST0..RST7
+fn stitch(parts: &[Vec<u8>], height: u32, restart_interval: u16) -> Result<Vec<u8>> {
+    let first = parts.first().ok_or_else(|| "No stripes to stitch".to_string())?;
+    let (sos, _) = scan_bounds(first)?;
+    let mut out = Vec::with_capacity(parts.iter().map(Vec::len).sum::<usize>() + 64);
+    out.extend_from_slice(&first[..sos]);
+    let sof = find_segment(&out, 0xc0).ok_or_else(|| "Stripe has no SOF0 segment".to_string())?;
+    out[sof + 5..sof + 7].copy_from_slice(&(height as u16).to_be_bytes());
+    out.extend_from_slice(&[0xff, 0xdd, 0x00, 0x04]);
+    out.extend_from_slice(&restart_interval.to_be_bytes());
+
+    for (index, part) in parts.iter().enumerate() {
+        let (sos, data) = scan_bounds(part)?;
+        if index == 0 {
+            out.extend_from_slice(&part[sos..data]);
+        } else {
+            out.extend_from_slice(&[0xff, 0xd0 + ((index - 1) % 8) as u8]);
+        }
+        // 熵编码数据到 EOI 为止
+        out.extend_from_slice(&part[data..part.len() - 2]);
+    }
+    out.extend_from_slice(&[0xff, 0xd9]);
+    Ok(out)
+}
+
+/// 返回 SOS 段的起始位置和其后熵编码数据的起始位置
+fn scan_bounds(jpeg: &[u8]) -> Result<(usize, usize)> {
+    let sos = find_segment(jpeg, 0xda).ok_or_else(|| "Stripe has no SOS segment".to_string())?;
+    let length = u16::from_be_bytes([jpeg[sos + 2], jpeg[sos + 3]]) as usize;
+    if !jpeg.ends_with(&[0xff, 0xd9]) || sos + 2 + length > jpeg.len() - 2 {
+        return Err("Stripe is not a complete JPEG".to_string());
+    }
+    Ok((sos, sos + 2 + length))
+}
+
+/// 从 SOI 之后逐段查找标记，返回该段 0xFF 的位置；遇到 SOS 后停止
+fn find_segment(jpeg: &[u8], marker: u8) -> Option<usize> {
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xff {
+        if jpeg[pos + 1] == marker {
+            return Some(pos);
+        }
+        if jpeg[pos + 1] == 0xda {
+            return None;
+        }
+        pos += 2 + u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([(x * 3) as u8, (y * 2) as u8, ((x + y) * 5) as u8, 255])
+        })
+    }
+
+    #[test]
+    fn converts_bgra_pixels() {
+        let src = [1, 2, 3, 0, 4, 5, 6, 9];
+        let mut dst = [0; 8];
+        bgra_to_rgba(&src, &mut dst);
+        assert_eq!(dst, [3, 2, 1, 255, 6, 5, 4, 255]);
+
+        let src: Vec<u8> = (0..200 * 100 * 4).map(|i| i as u8).collect();
+        let (mut serial, mut parallel) = (vec![0; src.len()], vec![0; src.len()]);
+        bgra_to_rgba(&src, &mut serial);
+        bgra_to_rgba_parallel(&src, &mut parallel, 200);
+        assert_eq!(serial, parallel);
+    }
+
+    #[test]
+    fn striped_jpeg_decodes_like_a_single_pass() {
+        // 高度不是 MCU 的整数倍，最后一个条带不满
+        let frame = frame(200, 300);
+        let single = encode_jpeg(&frame, PixelOrder::Rgba, 70, 1).unwrap();
+        let striped = encode_jpeg(&frame, PixelOrder::Rgba, 70, 4).unwrap();
+        assert_ne!(single, striped);
+
+        let single = image::load_from_memory(&single).unwrap().to_rgba8();
+        let striped = image::load_from_memory(&striped).unwrap().to_rgba8();
+        assert_eq!(striped.dimensions(), (200, 300));
+        assert_eq!(striped, single);
+    }
+
+    #[test]
+    fn bgra_frames_encode_like_rgba() {
+        let frame = frame(200, 300);
+        let mut bgra = frame.clone();
+        // bgra_to_rgba 交换 R 和 B，同样可以把 RGBA 转成 BGRA
+        bgra_to_rgba(frame.as_raw(), &mut bgra);
+        let rgba = encode_jpeg(&frame, PixelOrder::Rgba, 70, 4).unwrap();
+        assert_eq!(encode_jpeg(&bgra, PixelOrder::Bgra, 70, 4).unwrap(), rgba);
+    }
+
+    #[test]
+    fn pool_reuses_buffers() {
+        let pool = BufferPool::new(1);
+        let buffer = pool.take(16);
+        let address = buffer.as_ptr();
+        pool.put(buffer);
+        pool.put(vec![0; 4]);
+        assert_eq!(pool.available(), 1);
+        let buffer = pool.take(8);
+        assert_eq!((buffer.len(), buffer.as_ptr()), (8, address));
+    }
+}
@@ -0,0 +1,320 @@
+use crate::config::RecordingConfig;
+use crate::video::CaptureSource;
+use image::{ImageFormat, RgbaImage};
+use rotascope_core::recording::{
+    FILE_EXTENSION, RecordEntry, RecordedEvent, RecordingHeader, RecordingReader, RecordingWriter,
+};
+use rotascope_core::{ClientMessage, Result, ServerMessage, timestamp_millis};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// 等待写入线程处理的记录数上限；写入跟不上时丢弃新的记录，不阻塞会话
+const QUEUE_LEN: usize = 64;
+
+type Writer = RecordingWriter<CountingWriter>;
+type Job = Box<dyn FnOnce(&mut Writer) -> Result<()> + Send>;
+
+/// 统计写入的字节数，用于 max_bytes
+#[derive(Debug)]
+struct CountingWriter {
+    inner: BufWriter<File>,
+    written: Arc<AtomicU64>,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug)]
+struct ActiveRecording {
+    /// 记录交给写入线程，会话任务中不做文件 I/O
+    jobs: SyncSender<Job>,
+    writer: JoinHandle<()>,
+    file: String,
+    written: Arc<AtomicU64>,
+    started: Instant,
+    max_bytes: u64,
+    max_duration: Duration,
+}
+
+impl ActiveRecording {
+    /// 不再接收新记录，写入线程写完队列中剩余的记录后结束
+    fn stop(self) -> StoppedRecording {
+        StoppedRecording {
+            file: self.file,
+            writer: self.writer,
+        }
+    }
+}
+
+/// 已停止的录制；写入线程可能还在写入剩余的记录
+#[derive(Debug)]
+pub struct StoppedRecording {
+    file: String,
+    writer: JoinHandle<()>,
+}
+
+impl StoppedRecording {
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// 等待文件写完，返回文件路径
+    pub async fn finish(self) -> String {
+        let writer = self.writer;
+        if tokio::task::spawn_blocking(move || writer.join()).await.is_err() {
+            tracing::warn!("Recording writer for {} panicked", self.file);
+        }
+        self.file
+    }
+}
+
+/// 单个会话的录制器，客户端通过 SetRecording 按需开启
+#[derive(Debug, Default)]
+pub struct SessionRecorder {
+    active: Mutex<Option<ActiveRecording>>,
+}
+
+impl SessionRecorder {
+    /// 在 config.directory 下创建录制文件并返回文件路径；已在录制时返回当前文件
+    pub async fn start(&self, config: &RecordingConfig, name: &str, header: &RecordingHeader) -> Result<String> {
+        if let Some(recording) = self.active.lock().unwrap().as_ref() {
+            return Ok(recording.file.clone());
+        }
+
+        // 文件名只保留安全字符，避免设备名中的路径分隔符
+        let name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect();
+        let path = Path::new(&config.directory).join(format!("{}-{}.{}", name, header.started_at, FILE_EXTENSION));
+        let file = path.to_string_lossy().into_owned();
+
+        // 文件也在写入线程中创建，创建结果通过 ready 返回
+        let (jobs, queue) = mpsc::sync_channel(QUEUE_LEN);
+        let (ready_tx, ready) = tokio::sync::oneshot::channel();
+        let written = Arc::new(AtomicU64::new(0));
+        let writer = std::thread::Builder::new()
+            .name("recording".to_string())
+            .spawn({
+                let header = header.clone();
+                let written = written.clone();
+                let file = file.clone();
+                move || match create_recording(&path, &header, written) {
+                    Ok(writer) => {
+                        let _ = ready_tx.send(Ok(()));
+                        write_recording(writer, queue, &file);
+                    }
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                    }
+                }
+            })
+            .map_err(|e| e.to_string())?;
+        ready.await.map_err(|_| "Recording writer exited".to_string())??;
+
+        tracing::info!("Recording session to {}", file);
+        *self.active.lock().unwrap() = Some(ActiveRecording {
+            jobs,
+            writer,
+            file: file.clone(),
+            written,
+            started: Instant::now(),
+            max_bytes: config.max_bytes,
+            max_duration: Duration::from_secs(config.max_secs),
+        });
+        Ok(file)
+    }
+
+    /// 停止录制；用 [`StoppedRecording::finish`] 等待文件写完
+    pub fn stop(&self) -> Option<StoppedRecording> {
+        Some(self.active.lock().unwrap().take()?.stop())
+    }
+
+    /// 取出正在进行的录制，交给之后恢复的会话继续写入
+    pub fn detach(&self) -> SessionRecorder {
+        SessionRecorder {
+            active: Mutex::new(self.active.lock().unwrap().take()),
+        }
+    }
+
+    /// 接管另一个录制器中的录制；本会话已在录制时结束对方的录制
+    pub fn adopt(&self, other: SessionRecorder) {
+        let mut active = self.active.lock().unwrap();
+        if active.is_none() {
+            *active = other.active.into_inner().unwrap();
+        } else {
+            drop(active);
+            other.stop();
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.lock().unwrap().is_some()
+    }
+
+    /// 录制因达到上限或写入失败而结束时返回该录制
+    pub fn record_server(&self, message: &ServerMessage) -> Option<StoppedRecording> {
+        if !self.is_active() {
+            return None;
+        }
+        match message {
+            ServerMessage::VideoFrame {
+                display_index,
+                width,
+                height,
+                data,
+                ..
+            } => {
+                let (display_index, width, height, data) = (*display_index, *width, *height, data.clone());
+                self.record(move |writer, now| writer.write_frame(now, display_index, width, height, &data))
+            }
+            other => {
+                let other = other.clone();
+                self.record(move |writer, now| writer.write_server(now, &other))
+            }
+        }
+    }
+
+    /// 同 [`Self::record_server`]
+    pub fn record_client(&self, message: &ClientMessage) -> Option<StoppedRecording> {
+        if !self.is_active() {
+            return None;
+        }
+        let message = message.clone();
+        self.record(move |writer, now| writer.write_client(now, &message))
+    }
+
+    fn record(&self, write: impl FnOnce(&mut Writer, u64) -> Result<()> + Send + 'static) -> Option<StoppedRecording> {
+        let mut active = self.active.lock().unwrap();
+        let recording = active.as_mut()?;
+        if recording.written.load(Ordering::Relaxed) >= recording.max_bytes
+            || recording.started.elapsed() >= recording.max_duration
+        {
+            tracing::info!("Recording {} reached its size or duration limit", recording.file);
+            return active.take().map(ActiveRecording::stop);
+        }
+        let now = timestamp_millis();
+        match recording.jobs.try_send(Box::new(move |writer| write(writer, now))) {
+            Ok(()) => None,
+            Err(TrySendError::Full(_)) => {
+                tracing::debug!("Recording {} is falling behind, dropping an entry", recording.file);
+                None
+            }
+            // 写入线程已因错误（例如磁盘已满）退出，放弃录制，不影响会话本身
+            Err(TrySendError::Disconnected(_)) => active.take().map(ActiveRecording::stop),
+        }
+    }
+}
+
+fn create_recording(path: &Path, header: &RecordingHeader, written: Arc<AtomicU64>) -> Result<Writer> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory).map_err(|e| format!("{}: {}", directory.display(), e))?;
+    }
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    RecordingWriter::new(
+        CountingWriter {
+            inner: BufWriter::new(file),
+            written,
+        },
+        header,
+    )
+}
+
+/// 写入线程：依次写入记录，直到录制停止（队列的发送端被释放）
+fn write_recording(mut writer: Writer, queue: Receiver<Job>, file: &str) {
+    for job in queue {
+        if let Err(e) = job(&mut writer) {
+            tracing::warn!("Recording {} stopped: {}", file, e);
+            return;
+        }
+    }
+    match writer.into_inner() {
+        Ok(_) => tracing::info!("Recording saved to {}", file),
+        Err(e) => tracing::warn!("Failed to finish recording {}: {}", file, e),
+    }
+}
+
+/// 回放录制文件中的视频帧，保持原始的帧间隔；播放完后从头循环
+#[derive(Debug)]
+pub struct ReplaySource {
+    path: PathBuf,
+    reader: RecordingReader<BufReader<File>>,
+    /// 本轮回放的 (开始时刻, 第一帧的录制偏移)
+    clock: Option<(Instant, u64)>,
+    frames_in_pass: u64,
+}
+
+impl ReplaySource {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        Ok(Self {
+            reader: open_recording(&path)?,
+            path,
+            clock: None,
+            frames_in_pass: 0,
+        })
+    }
+
+    pub fn header(&self) -> &RecordingHeader {
+        self.reader.header()
+    }
+}
+
+/// 读取录制文件的头部
+pub fn read_recording_header(path: impl AsRef<Path>) -> Result<RecordingHeader> {
+    Ok(open_recording(path.as_ref())?.header().clone())
+}
+
+fn open_recording(path: &Path) -> Result<RecordingReader<BufReader<File>>> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    RecordingReader::new(BufReader::new(file)).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+impl CaptureSource for ReplaySource {
+    fn capture_frame(&mut self) -> Result<RgbaImage> {
+        loop {
+            let data = match self.reader.next_entry()? {
+                Some(RecordEntry {
+                    offset_ms,
+                    event: RecordedEvent::Frame { data, .. },
+                }) => {
+                    let (started, first_offset) = *self.clock.get_or_insert((Instant::now(), offset_ms));
+                    let due = started + Duration::from_millis(offset_ms.saturating_sub(first_offset));
+                    if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                        std::thread::sleep(wait);
+                    }
+                    data
+                }
+                Some(_) => continue,
+                None => {
+                    if self.frames_in_pass == 0 {
+                        return Err(format!("{} contains no video frames", self.path.display()));
+                    }
+                    self.reader = open_recording(&self.path)?;
+                    self.clock = None;
+                    self.frames_in_pass = 0;
+                    continue;
+                }
+            };
+            self.frames_in_pass += 1;
+            let image = image::load_from_memory_with_format(&data, ImageFormat::Jpeg)
+                .map_err(|e| format!("Invalid frame in recording: {}", e))?;
+            return Ok(image.to_rgba8());
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! 断线重连：异常断开的会话在宽限期内保留状态，客户端用 ResumeSession 取回。
+
+use crate::auth::random_hex;
+use crate::recording::SessionRecorder;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 跨连接保留的会话状态。视频编码和姿态处理由服务端统一配置，没有按会话的选择需要保留
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionState {
+    pub clipboard_enabled: bool,
+    pub audio_enabled: bool,
+    /// 断开时选中的显示器 id，不随其他显示器的增删变化
+    pub display_id: u32,
+}
+
+/// 等待恢复的会话
+#[derive(Debug)]
+pub struct ParkedSession {
+    pub state: SessionState,
+    /// 只有同一设备可以恢复
+    pub device_id: Option<String>,
+    /// 未结束的录制，恢复后继续写入同一文件
+    pub recorder: SessionRecorder,
+    parked_at: Instant,
+}
+
+/// 按会话 ID 保存的待恢复会话
+#[derive(Debug)]
+pub struct ResumableSessions {
+    grace: Duration,
+    parked: Mutex<HashMap<String, ParkedSession>>,
+}
+
+impl ResumableSessions {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            grace,
+            parked: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 新会话的 ID，同时作为恢复时的凭据，因此必须不可猜测
+    pub fn new_session_id() -> String {
+        random_hex(16)
+    }
+
+    pub fn park(&self, session_id: String, state: SessionState, device_id: Option<String>, recorder: SessionRecorder) {
+        let parked = ParkedSession {
+            state,
+            device_id,
+            recorder,
+            parked_at: Instant::now(),
+        };
+        if let Some(previous) = self.parked.lock().unwrap().insert(session_id, parked) {
+            previous.recorder.stop();
+        }
+    }
+
+    /// 取出未过期的会话；设备不一致时保留原会话并返回 None
+    pub fn take(&self, session_id: &str, device_id: Option<&str>) -> Option<ParkedSession> {
+        self.expire();
+        let mut parked = self.parked.lock().unwrap();
+        if parked.get(session_id)?.device_id.as_deref() != device_id {
+            return None;
+        }
+        parked.remove(session_id)
+    }
+
+    /// 丢弃超过宽限期的会话并结束其中的录制，返回丢弃的数量
+    pub fn expire(&self) -> usize {
+        let expired: Vec<_> = {
+            let mut parked = self.parked.lock().unwrap();
+            let ids: Vec<String> = parked
+                .iter()
+                .filter(|(_, session)| session.parked_at.elapsed() >= self.grace)
+                .map(|(id, _)| id.clone())
+                .collect();
+            ids.into_iter().filter_map(|id| parked.remove(&id)).collect()
+        };
+        for session in &expired {
+            session.recorder.stop();
+        }
+        expired.len()
+    }
+
+    /// 服务关闭时丢弃所有会话，返回其中的录制器，由调用方等待录制写完
+    pub fn clear(&self) -> Vec<SessionRecorder> {
+        let parked: Vec<_> = self.parked.lock().unwrap().drain().collect();
+        parked.into_iter().map(|(_, session)| session.recorder).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.parked.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATE: SessionState = SessionState {
+        clipboard_enabled: true,
+        audio_enabled: false,
+        display_id: 2,
+    };
+
+    #[test]
+    fn sessions_resume_once_for_the_same_device() {
+        let sessions = ResumableSessions::new(Duration::from_secs(60));
+        sessions.park("a".to_string(), STATE, Some("phone".to_string()), SessionRecorder::default());
+
+        assert!(sessions.take("a", None).is_none());
+        assert!(sessions.take("a", Some("tablet")).is_none());
+        assert!(sessions.take("b", Some("phone")).is_none());
+        assert_eq!(sessions.take("a", Some("phone")).unwrap().state, STATE);
+        assert!(sessions.take("a", Some("phone")).is_none());
+    }
+
+    #[test]
+    fn sessions_expire_after_the_grace_period() {
+        let sessions = ResumableSessions::new(Duration::ZERO);
+        sessions.park("a".to_string(), STATE, None, SessionRecorder::default());
+        assert_eq!(sessions.expire(), 1);
+        assert_eq!(sessions.len(), 0);
+        assert!(sessions.take("a", None).is_none());
+    }
+}
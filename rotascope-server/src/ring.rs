@@ -0,0 +1,115 @@
+//! 捕获线程与异步编码任务之间的无锁环形缓冲。
+//!
+//! 捕获线程只管写入，缓冲满时覆盖最旧的一帧而不是阻塞：编码跟不上时丢掉过时的画面，
+//! 保证客户端看到的总是最新的一帧。编码任务在 tokio 中异步等待新帧，不占用运行时线程。
+
+use crossbeam_queue::ArrayQueue;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+
+/// 单生产者、单消费者的帧缓冲
+#[derive(Debug)]
+pub struct FrameRing<T> {
+    queue: ArrayQueue<T>,
+    ready: Notify,
+    closed: AtomicBool,
+}
+
+impl<T> FrameRing<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            queue: ArrayQueue::new(capacity.max(1)),
+            ready: Notify::new(),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// 写入一项并唤醒消费者；缓冲已满时覆盖最旧的一项并将其返回，调用方可以回收它的内存
+    pub fn push(&self, item: T) -> Option<T> {
+        let evicted = self.queue.force_push(item);
+        self.ready.notify_one();
+        evicted
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        self.queue.pop()
+    }
+
+    /// 等待下一项；生产者关闭且缓冲已取空后返回 None
+    ///
+    /// 取消等待不会丢失数据，可以放在 `tokio::select!` 中使用
+    pub async fn next(&self) -> Option<T> {
+        loop {
+            if let Some(item) = self.queue.pop() {
+                return Some(item);
+            }
+            if self.closed.load(Ordering::Acquire) {
+                return self.queue.pop();
+            }
+            // notify_one 在没有等待者时会保留一次许可，写入和等待之间不会错过唤醒
+            self.ready.notified().await;
+        }
+    }
+
+    /// 生产者退出时调用，唤醒等待中的消费者
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.ready.notify_one();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn full_ring_overwrites_the_oldest_item() {
+        let ring = FrameRing::new(2);
+        assert_eq!(ring.push(1), None);
+        assert_eq!(ring.push(2), None);
+        assert_eq!(ring.push(3), Some(1));
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.pop(), Some(2));
+        assert_eq!(ring.pop(), Some(3));
+        assert!(ring.is_empty());
+    }
+
+    #[tokio::test]
+    async fn consumer_wakes_for_items_from_another_thread_and_drains_after_close() {
+        let ring = Arc::new(FrameRing::new(4));
+        let producer = {
+            let ring = ring.clone();
+            std::thread::spawn(move || {
+                for i in 0..3 {
+                    std::thread::sleep(Duration::from_millis(5));
+                    ring.push(i);
+                }
+                ring.close();
+            })
+        };
+        let mut received = Vec::new();
+        let collect = async {
+            while let Some(item) = ring.next().await {
+                received.push(item);
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), collect).await.unwrap();
+        producer.join().unwrap();
+        assert_eq!(received, vec![0, 1, 2]);
+        assert!(ring.is_closed());
+    }
+}
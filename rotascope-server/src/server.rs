@@ -1,127 +1,623 @@
-use crate::virtual_display::VirtualDisplayManager;
+use crate::audio::AudioStream;
+use crate::auth::{AuthOutcome, Authenticator};
+use crate::clipboard::{self, ClipboardBackend, ClipboardContent, ClipboardSync};
+use crate::config::ServerConfig;
+use crate::desktop::Viewport;
+use crate::foveation::Foveation;
+use crate::heartbeat::Heartbeat;
+use crate::http::{HttpRequest, Rewind, read_request_head, write_response};
+use crate::metrics::{CaptureStatus, ClientStats, ClientStatus, Metrics, ServerStatus};
+use crate::pipeline::PixelOrder;
+use crate::recording::{SessionRecorder, StoppedRecording};
+use crate::ring::FrameRing;
+use crate::resume::{ResumableSessions, SessionState};
+use crate::tls::{ServerStream, TlsIdentity, is_tls_client_hello};
+use crate::video::{CaptureFactory, FrameEncoder};
+use crate::virtual_display::{self, VirtualDisplayManager};
+use crate::window::{self, WindowCapture, WindowFrame, WindowSystem};
+use crate::zoom::ZoomControl;
+use crossbeam_queue::ArrayQueue;
 use futures::{SinkExt, StreamExt};
+use image::RgbaImage;
 use rotascope_core::{
-    ClientMessage, ServerMessage, SwitchDirection, deserialize_message, serialize_message,
+    Arrangement, ClientMessage, DisplayLayout, PROTOCOL_VERSION, ServerMessage, SwitchDirection,
+    WindowInfo, WindowSelector, Zoom, deserialize_message, serialize_message, timestamp_millis,
 };
-use tokio::runtime::Runtime;
+use std::collections::HashSet;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use std::thread;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::mpsc::Receiver;
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{Mutex, broadcast};
 use tokio::task::JoinHandle;
-use tokio_tungstenite::accept_async;
+use tokio_tungstenite::{WebSocketStream, accept_async};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{Instrument, Span};
+use tungstenite::protocol::CloseFrame;
+use tungstenite::protocol::frame::coding::CloseCode;
 use tungstenite::{Message, Utf8Bytes};
-use crate::{CrossPlatformCapturer::CrossPlatformCapturer};
 use rotascope_core::Result;
-use serde_json;
-use tokio::time::interval;
-use crate::CrossPlatformCapturer::compress_frame;
+use rotascope_core::discovery::{ServiceAdvertiser, ServiceAnnouncement};
+use rotascope_core::media;
+use rotascope_core::recording::RecordingHeader;
 
-use tokio::sync::mpsc::UnboundedSender;
+/// 关闭时等待客户端和捕获任务退出的最长时间
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(5);
+/// 捕获任务出错后重启的最长退避时间
+const MAX_CAPTURE_BACKOFF: Duration = Duration::from_secs(30);
+/// 窗口隐藏或等待关闭处理时，重新检查的间隔
+const WINDOW_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// 捕获线程与编码任务之间最多积压的帧数，更旧的帧被覆盖
+const FRAME_RING_CAPACITY: usize = 2;
+/// 屏幕没有更新时捕获线程的退避等待，从下限开始逐次加倍
+const MIN_IDLE_POLL: Duration = Duration::from_micros(250);
+const MAX_IDLE_POLL: Duration = Duration::from_millis(4);
+
+type WsWriter = futures::stream::SplitSink<WebSocketStream<Rewind<ServerStream>>, Message>;
+type WsReader = futures::stream::SplitStream<WebSocketStream<Rewind<ServerStream>>>;
 
 #[derive(Debug, Clone)]
 pub struct MultiDisplayServer {
+    config: Arc<ServerConfig>,
+    /// 显示器列表和当前显示器
     virtual_displays: Arc<VirtualDisplayManager>,
-    current_display: Arc<RwLock<u8>>,
-    clients: Arc<Mutex<Vec<tokio::sync::mpsc::Sender<ServerMessage>>>>,
+    clients: Arc<Mutex<Vec<Arc<ClientSession>>>>,
+    /// 异常断开、等待客户端恢复的会话
+    sessions: Arc<ResumableSessions>,
+    /// 最近一次发出的视频帧，恢复会话时立即补发
+    latest_frame: Arc<std::sync::Mutex<Option<ServerMessage>>>,
+    clipboard: Option<ClipboardSync>,
+    audio: Option<AudioStream>,
+    auth: Option<Arc<Authenticator>>,
+    tls: Option<TlsIdentity>,
+    capture: CaptureFactory,
+    encoder: Arc<dyn FrameEncoder>,
+    windows: Arc<dyn WindowSystem>,
+    /// 各显示器的放大设置，捕获线程据此裁剪画面
+    zoom: Arc<ZoomControl>,
+    /// 注视点编码使用的头部朝向
+    foveation: Arc<Foveation>,
+    viewport: Arc<Viewport>,
+    metrics: Arc<Metrics>,
+    /// 运行期间的 mDNS 广播，显示器数量变化时更新
+    advertiser: Arc<std::sync::Mutex<Option<ServiceAdvertiser>>>,
+    events: broadcast::Sender<ServerEvent>,
+    shutdown: CancellationToken,
+}
+
+/// 服务端运行期间的事件，供嵌入方订阅
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerEvent {
+    ClientConnected { addr: SocketAddr },
+    ClientAuthenticated { addr: SocketAddr, device_id: String, device_name: String },
+    ClientDisconnected { addr: SocketAddr },
+    /// 客户端在心跳超时时间内没有任何数据，会话已被断开
+    ClientTimedOut { addr: SocketAddr },
+    /// 客户端重连后恢复了之前的会话
+    SessionResumed { addr: SocketAddr, session_id: String },
+    DisplaySwitched { display: u8 },
+    /// 显示器被添加、移除或调整了大小，新的 DisplayConfig 已推送给所有客户端
+    DisplaysChanged { total_displays: usize },
+    CaptureFailed { message: String },
+    ShuttingDown,
+}
+
+/// 捕获线程采集到、等待编码的一帧
+struct CapturedFrame {
+    frame: RgbaImage,
+    /// frame 中像素的通道顺序，编码时按它读取
+    order: PixelOrder,
+    /// 采集时当前显示器的 id，决定使用哪个放大设置
+    display: u32,
+    /// 来自窗口时为该窗口显示器的 id，来自屏幕时为 None
+    window: Option<u32>,
+    capture_time: Duration,
+    /// 写入环形缓冲的时间，用于统计等待编码的时长
+    captured_at: Instant,
+    span: Span,
+}
+
+/// 编码完成、等待发送的帧
+#[derive(Debug)]
+struct EncodedFrame {
+    /// 来自窗口时为该窗口显示器的 id，来自屏幕时为 None
+    window: Option<u32>,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+    timestamp: u64,
+    /// 该帧的 frame span，发送到各客户端时作为父 span
+    span: Span,
+}
+
+/// 发往客户端的消息，附带产生它的 span
+#[derive(Debug)]
+struct Outgoing {
+    message: ServerMessage,
+    span: Span,
+}
+
+/// 单个客户端连接的状态
+#[derive(Debug)]
+struct ClientSession {
+    tx: Sender<Outgoing>,
+    addr: SocketAddr,
+    /// 恢复旧会话后改为旧会话的 ID
+    id: std::sync::Mutex<String>,
+    /// 认证通过的设备，未开启认证时为 None
+    device: Option<AuthOutcome>,
+    /// 剪贴板同步需要客户端按会话显式开启
+    clipboard_enabled: AtomicBool,
+    /// 桌面音频同样按会话开启
+    audio_enabled: AtomicBool,
+    /// 还没有处理过客户端消息；ResumeSession 只能是连接上的第一条消息
+    first_message: AtomicBool,
+    recorder: SessionRecorder,
+    stats: ClientStats,
+    heartbeat: Heartbeat,
+}
+
+impl ClientSession {
+    fn id(&self) -> String {
+        self.id.lock().unwrap().clone()
+    }
+
+    /// 将消息放入发送队列，客户端已断开时返回错误
+    async fn send(&self, message: ServerMessage) -> Result<()> {
+        let outgoing = Outgoing {
+            message,
+            span: Span::current(),
+        };
+        self.tx
+            .send(outgoing)
+            .await
+            .map_err(|_| "Client disconnected".to_string())
+    }
+
+    /// 不等待的发送：队列已满时丢弃消息，避免一个慢客户端阻塞对所有会话的广播
+    fn try_send(&self, message: ServerMessage) {
+        let outgoing = Outgoing {
+            message,
+            span: Span::current(),
+        };
+        // 客户端已断开时由其会话任务负责清理
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(outgoing) {
+            tracing::debug!(client = %self.addr, "Client queue full, message dropped");
+        }
+    }
+
+    /// 录制因达到上限或写入失败而结束时，等文件写完后通知客户端；
+    /// 在单独的任务中发送，发送任务自己调用时不会等待自己的队列
+    fn recording_stopped(self: &Arc<Self>, recording: Option<StoppedRecording>) {
+        let Some(recording) = recording else {
+            return;
+        };
+        let session = self.clone();
+        tokio::spawn(async move {
+            let file = recording.finish().await;
+            let _ = session
+                .send(ServerMessage::RecordingState { active: false, file: Some(file) })
+                .await;
+        });
+    }
 }
 
 impl MultiDisplayServer {
-    pub fn new(display_count: u8) -> Result<Self> {
-      //  let capturer = Arc::new(ScreenCapturer::new()?);
-
-        let virtual_displays = Arc::new(VirtualDisplayManager::new(vec![
-            (0, 1920, 1080),
-            (1, 1920, 1080),
-            (2, 2560, 1440),
-        ])?);
-        let current_display = Arc::new(RwLock::new(0));
+    /// 一般通过 [`crate::ServerBuilder`] 创建
+    pub(crate) fn new(
+        config: ServerConfig,
+        displays: Vec<(u32, u32, u32)>,
+        capture: CaptureFactory,
+        encoder: Arc<dyn FrameEncoder>,
+        windows: Arc<dyn WindowSystem>,
+        clipboard_backend: Option<Box<dyn ClipboardBackend>>,
+    ) -> Result<Self> {
+        let mut virtual_displays = VirtualDisplayManager::new(displays, virtual_display::backend(config.displays.backend))?;
+        if let Some(path) = &config.displays.layout_file {
+            virtual_displays = virtual_displays.with_layout_file(path)?;
+        }
+        let virtual_displays = Arc::new(virtual_displays);
         let clients = Arc::new(Mutex::new(Vec::new()));
+        let sessions = Arc::new(ResumableSessions::new(Duration::from_secs(config.resume.grace_secs)));
+        let zoom = Arc::new(ZoomControl::new(&config.zoom));
+        let foveation = Arc::new(Foveation::new(&config.foveation));
+        let viewport = Arc::new(Viewport::new(&config.composite));
+
+        let clipboard = if config.clipboard.enabled {
+            let backend = match clipboard_backend {
+                Some(backend) => Ok(backend),
+                None => clipboard::default_backend(&config.clipboard),
+            };
+            match backend.and_then(|backend| ClipboardSync::start(&config.clipboard, backend)) {
+                Ok(clipboard) => Some(clipboard),
+                Err(e) => {
+                    tracing::warn!("Clipboard sync unavailable: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        // 关闭时取消，后台线程（如音频）也随之退出
+        let shutdown = CancellationToken::new();
+        let audio = if config.audio.enabled {
+            match AudioStream::start(&config.audio, shutdown.clone()) {
+                Ok(audio) => Some(audio),
+                Err(e) => {
+                    tracing::warn!("Audio streaming unavailable: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let auth = if config.auth.required {
+            Some(Arc::new(Authenticator::new(&config.auth)?))
+        } else {
+            tracing::warn!("Authentication is disabled, any client on the network can connect");
+            None
+        };
+
+        let tls = if config.tls.enabled {
+            let identity = TlsIdentity::load_or_generate(&config.tls)?;
+            tracing::info!("TLS enabled, certificate fingerprint {}", identity.fingerprint());
+            Some(identity)
+        } else {
+            None
+        };
 
         Ok(Self {
+            config: Arc::new(config),
             virtual_displays,
-            current_display,
             clients,
+            sessions,
+            latest_frame: Arc::new(std::sync::Mutex::new(None)),
+            clipboard,
+            audio,
+            auth,
+            tls,
+            capture,
+            encoder,
+            windows,
+            zoom,
+            foveation,
+            viewport,
+            metrics: Arc::new(Metrics::default()),
+            advertiser: Arc::new(std::sync::Mutex::new(None)),
+            events: broadcast::channel(64).0,
+            shutdown,
         })
     }
 
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.events.subscribe()
+    }
+
+    /// 请求停止服务：停止接受连接，向所有客户端发送关闭帧并销毁虚拟显示器
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    pub fn tls_fingerprint(&self) -> Option<&str> {
+        self.tls.as_ref().map(|tls| tls.fingerprint())
+    }
+
+    /// 开启认证时当前有效的配对码
+    pub fn pairing_code(&self) -> Option<String> {
+        self.auth.as_ref().map(|auth| auth.pairing_code())
+    }
+
+    /// 当前的运行状态，与 GET /status 返回的内容相同
+    pub async fn status(&self) -> ServerStatus {
+        let metrics = &self.metrics;
+        let clients: Vec<ClientStatus> = self
+            .clients
+            .lock()
+            .await
+            .iter()
+            .map(|session| ClientStatus {
+                connection: session.stats.connection,
+                connected_secs: session.stats.connected_for().as_secs(),
+                fps: session.stats.fps.rate(),
+                frames_sent: session.stats.frames_sent.load(Ordering::Relaxed),
+                frames_dropped: session.stats.frames_dropped.load(Ordering::Relaxed),
+                queue_depth: session.tx.max_capacity() - session.tx.capacity(),
+                rtt_ms: session.heartbeat.rtt().map(|rtt| rtt.as_secs_f64() * 1000.0),
+            })
+            .collect();
+        ServerStatus {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            uptime_secs: metrics.uptime().as_secs(),
+            total_displays: self.virtual_displays.get_display_count(),
+            current_display: self.virtual_displays.current_display(),
+            connected_clients: clients.len(),
+            parked_sessions: self.sessions.len(),
+            capture: CaptureStatus {
+                frames: metrics.capture_seconds.count(),
+                fps: metrics.capture_rate.rate(),
+                avg_capture_ms: metrics.capture_seconds.mean() * 1000.0,
+                avg_encode_ms: metrics.encode_seconds.mean() * 1000.0,
+                avg_queue_wait_ms: metrics.queue_wait_seconds.mean() * 1000.0,
+                frames_overwritten: metrics.frames_overwritten.load(Ordering::Relaxed),
+                avg_frame_bytes: metrics.frame_bytes.mean(),
+            },
+            frames_sent: metrics.frames_sent.load(Ordering::Relaxed),
+            frames_dropped: metrics.frames_dropped.load(Ordering::Relaxed),
+            sensor_messages_per_second: metrics.sensor_rate.rate(),
+            clients,
+        }
+    }
+
+    fn emit(&self, event: ServerEvent) {
+        // 没有订阅者时发送失败是正常的
+        let _ = self.events.send(event);
+    }
+
     pub async fn start_virtual_displays(&self) -> Result<()> {
         self.virtual_displays.initialize().await?;
-        log::info!("Virtual displays initialized");
-        println!("Virtual displays initialized");
+        tracing::info!("Virtual displays initialized");
         Ok(())
     }
 
-    pub async fn start_server(&self, addr: &str) -> Result<()> {
-        log::info!("Server listening on {}", addr);
-        println!("Server listening on {}", addr);
+    /// 在已绑定的监听器上运行，直到收到关闭请求（或 handle_signals 时的 Ctrl-C / SIGTERM）
+    pub async fn serve(&self, listener: TcpListener, handle_signals: bool) -> Result<()> {
+        let local_addr = listener.local_addr().map_err(|e| e.to_string())?;
+        tracing::info!("Server listening on {}", local_addr);
 
         // 使用 owned clone 放入 Arc，使其可以安全地移动到后台任务中
         let server_arc = Arc::new(self.clone());
+        if let Some(auth) = &self.auth {
+            auth.announce(local_addr, self.tls.as_ref().map(|tls| tls.fingerprint()));
+        }
+        // 广播随监听循环存活，退出时注销
+        *self.advertiser.lock().unwrap() = self.advertise(local_addr.port());
+
+        // 所有后台任务都挂在同一个 tracker 下，关闭时统一等待
+        let tasks = TaskTracker::new();
+        if handle_signals {
+            tasks.spawn(wait_for_shutdown_signal(self.shutdown.clone()));
+        }
         // 启动屏幕捕获和流媒体任务
-        let stream_arc = server_arc.clone();
-       let t1 = thread::spawn(move || {
-            let rt = Runtime::new().unwrap();
-            rt.block_on(async move {
-                let capturer = CrossPlatformCapturer::new_primary().unwrap();
-                stream_arc.start_streaming(capturer).await.unwrap()
-            })});
-        let addr_owned = addr.to_string();
-        let s = std::thread::spawn(move || {
-            let rt = Runtime::new().unwrap();
-            rt.block_on(async move {
-                let listener = TcpListener::bind(&addr_owned).await.unwrap();
-                loop {
-                    let (socket, addr) = listener.accept().await.unwrap();
-                    log::info!("New client connected: {}", addr);
-                    println!("New client connected: {}", addr);
-
-                    let client_arc = server_arc.clone();
-                    tokio::spawn(async move {
-                        if let Err(e) = client_arc.handle_client(socket).await {
-                            log::error!("Client handling error: {}", e);
-                            println!("Client handling error: {}", e);
-                        }
-                    });
+        tasks.spawn(server_arc.clone().supervise_capture());
+        tasks.spawn(server_arc.clone().expire_parked_sessions());
+
+        loop {
+            let (socket, addr) = tokio::select! {
+                _ = self.shutdown.cancelled() => break,
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        // 例如文件描述符耗尽，稍后重试即可
+                        tracing::warn!("Failed to accept connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                },
+            };
+            let client_arc = server_arc.clone();
+            tasks.spawn(async move {
+                let result = match client_arc.accept_stream(socket).await {
+                    Ok(stream) => client_arc.handle_connection(stream, addr).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    tracing::error!(client = %addr, "Client handling error: {}", e);
                 }
             });
-        });
-       // t1.join().unwrap();
-        s.join().unwrap();
+        }
+
+        self.emit(ServerEvent::ShuttingDown);
+        tracing::info!("Shutting down, waiting for {} tasks", tasks.len());
+        drop(listener);
+        drop(self.advertiser.lock().unwrap().take());
+        tasks.close();
+        if tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, tasks.wait()).await.is_err() {
+            tracing::warn!("Some tasks did not stop within {:?}", SHUTDOWN_GRACE_PERIOD);
+        }
+
+        self.virtual_displays.shutdown().await?;
+        tracing::info!("Server stopped");
         Ok(())
     }
 
-    async fn handle_client(&self, mut stream: TcpStream) -> Result<()> {
-        println!("handle_client");
-        let ws_stream = accept_async(stream).await.expect("Failed to accept");
+    /// 通过 mDNS 在局域网中广播本服务
+    fn advertise(&self, port: u16) -> Option<ServiceAdvertiser> {
+        let discovery = &self.config.discovery;
+        if !discovery.enabled {
+            return None;
+        }
+        let announcement = ServiceAnnouncement::new(
+            &discovery.server_name(),
+            port,
+            self.virtual_displays.get_display_count(),
+            self.tls.as_ref().map(|tls| tls.fingerprint()),
+        );
+        match ServiceAdvertiser::start(&announcement, discovery.include_loopback) {
+            Ok(advertiser) => {
+                tracing::info!("Advertising {} on the LAN as {}", rotascope_core::discovery::SERVICE_TYPE, announcement.name);
+                Some(advertiser)
+            }
+            Err(e) => {
+                tracing::warn!("mDNS advertisement failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// 根据客户端发出的第一个字节判断是否为 TLS 连接
+    async fn accept_stream(&self, socket: TcpStream) -> Result<ServerStream> {
+        let Some(tls) = &self.tls else {
+            return Ok(ServerStream::Plain(socket));
+        };
+        let timeout = Duration::from_secs(self.config.auth.handshake_timeout_secs);
+        let is_tls = tokio::time::timeout(timeout, is_tls_client_hello(&socket))
+            .await
+            .map_err(|_| "Client sent nothing".to_string())??;
+        if is_tls {
+            // 发了 ClientHello 之后停住的客户端同样不能一直占用连接
+            tokio::time::timeout(timeout, tls.accept(socket))
+                .await
+                .map_err(|_| "TLS handshake timed out".to_string())?
+        } else if self.config.tls.required {
+            Err("Rejected plaintext connection, TLS is required".to_string())
+        } else {
+            Ok(ServerStream::Plain(socket))
+        }
+    }
+
+    /// 读取请求头：WebSocket 升级请求进入客户端会话，其余按 HTTP 请求处理
+    async fn handle_connection(&self, stream: ServerStream, addr: SocketAddr) -> Result<()> {
+        let timeout = Duration::from_secs(self.config.auth.handshake_timeout_secs);
+        let (stream, request) = tokio::time::timeout(timeout, read_request_head(stream))
+            .await
+            .map_err(|_| "Client sent no request".to_string())??;
+        if !request.upgrade {
+            return self.serve_http(stream, addr, &request).await;
+        }
+
+        tracing::info!("New client connected: {}", addr);
+        self.emit(ServerEvent::ClientConnected { addr });
+        let span = tracing::info_span!("session", client = %addr, device = tracing::field::Empty);
+        let result = self.handle_client(stream, addr).instrument(span).await;
+        self.emit(ServerEvent::ClientDisconnected { addr });
+        result
+    }
+
+    async fn serve_http(&self, mut stream: Rewind<ServerStream>, addr: SocketAddr, request: &HttpRequest) -> Result<()> {
+        tracing::debug!(client = %addr, "HTTP {} {}", request.method, request.path);
+        // 这些接口不需要认证，默认只回应本机的请求
+        let metrics = &self.config.metrics;
+        let enabled = metrics.enabled && (metrics.allow_remote || addr.ip().to_canonical().is_loopback());
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/metrics") if enabled => {
+                let body = self.status().await.render_prometheus(&self.metrics);
+                write_response(&mut stream, "200 OK", "text/plain; version=0.0.4", body.as_bytes()).await
+            }
+            ("GET", "/status") if enabled => {
+                let body = serde_json::to_vec_pretty(&self.status().await).map_err(|e| e.to_string())?;
+                write_response(&mut stream, "200 OK", "application/json", &body).await
+            }
+            _ => write_response(&mut stream, "404 Not Found", "text/plain", b"Not Found\n").await,
+        }
+    }
 
-        let (mut writer, reader) = ws_stream.split();
+    async fn handle_client(&self, stream: Rewind<ServerStream>, addr: SocketAddr) -> Result<()> {
+        let is_tls = stream.get_ref().is_tls();
+        let ws_stream = accept_async(stream)
+            .await
+            .map_err(|e| format!("WebSocket handshake failed: {}", e))?;
+
+        let (mut writer, mut reader) = ws_stream.split();
+
+        // 认证通过前不发送任何画面或配置
+        let device = match &self.auth {
+            Some(auth) => {
+                let timeout = Duration::from_secs(self.config.auth.handshake_timeout_secs);
+                let result = tokio::time::timeout(timeout, Self::authenticate(auth, addr, &mut writer, &mut reader))
+                    .await
+                    .unwrap_or_else(|_| Err("Authentication timed out".to_string()));
+                match result {
+                    Ok(outcome) => Some(outcome),
+                    Err(e) => {
+                        let _ = Self::send_text(&mut writer, &ServerMessage::Error { message: e.clone() }).await;
+                        let _ = writer.close().await;
+                        return Err(format!("Authentication failed: {}", e));
+                    }
+                }
+            }
+            None => None,
+        };
+        if let Some(device) = &device {
+            Span::current().record("device", device.device_id.as_str());
+            tracing::info!(
+                "Device {} ({}) authenticated over {}",
+                device.device_name,
+                device.device_id,
+                if is_tls { "TLS" } else { "plaintext" }
+            );
+            self.emit(ServerEvent::ClientAuthenticated {
+                addr,
+                device_id: device.device_id.clone(),
+                device_name: device.device_name.clone(),
+            });
+        }
 
-        let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+        let (tx, rx) = tokio::sync::mpsc::channel(32);
 
+        let session = Arc::new(ClientSession {
+            tx: tx.clone(),
+            addr,
+            id: std::sync::Mutex::new(ResumableSessions::new_session_id()),
+            device,
+            clipboard_enabled: AtomicBool::new(false),
+            audio_enabled: AtomicBool::new(false),
+            first_message: AtomicBool::new(true),
+            recorder: SessionRecorder::default(),
+            stats: ClientStats::new(self.metrics.connections.fetch_add(1, Ordering::Relaxed) + 1),
+            heartbeat: Heartbeat::default(),
+        });
         // 添加到客户端列表
         {
             let mut clients = self.clients.lock().await;
-            clients.push(tx.clone());
+            clients.push(session.clone());
         }
 
         self.send_config_to_client(&mut writer).await?;
+        if self.resumable(&session) {
+            session.send(self.session_info(&session, false)).await?;
+        }
         // 处理来自客户端的消息
-        let receive_task = self.deal_msg_from_client(reader);
+        let mut receive_task = self.deal_msg_from_client(reader, session.clone());
 
-        let send_task = Self::send_msg2client(writer, rx);
-        // 等待任一任务完成
-        tokio::select! {
-            _ = receive_task => {},
-            _ = send_task => {},
+        let heartbeat = &self.config.heartbeat;
+        let ping_interval = heartbeat.enabled.then(|| Duration::from_millis(heartbeat.interval_ms));
+        let mut send_task = Self::send_msg2client(
+            writer,
+            rx,
+            session.clone(),
+            self.metrics.clone(),
+            ping_interval,
+            self.shutdown.clone(),
+        );
+        let clipboard_task = self.forward_clipboard(session.clone());
+        let audio_task = self.forward_audio(session.clone());
+        // 等待任一任务完成，或客户端超时未响应；记录客户端是否主动关闭了连接
+        let closed = tokio::select! {
+            result = &mut receive_task => result.unwrap_or(false),
+            _ = &mut send_task => false,
+            _ = self.watch_heartbeat(&session) => {
+                tracing::info!("No data from client for {:?}, closing session", session.heartbeat.idle_for());
+                self.emit(ServerEvent::ClientTimedOut { addr });
+                false
+            }
+        };
+        // 超时的客户端可能阻塞在写操作上，直接中止而不等待关闭帧
+        receive_task.abort();
+        for task in [Some(send_task), clipboard_task, audio_task].into_iter().flatten() {
+            task.abort();
+        }
+        if let Some(device) = &session.device {
+            tracing::info!("Device {} disconnected", device.device_id);
+        }
+        if closed || self.shutdown.is_cancelled() {
+            if let Some(recording) = session.recorder.stop() {
+                recording.finish().await;
+            }
+        } else {
+            self.park_session(&session).await;
         }
 
-        // 从客户端列表移除（通过释放 tx 的克隆来标记该客户端已断开）
+        // 从客户端列表移除
         {
             let mut clients = self.clients.lock().await;
-            clients.retain(|client_tx| !client_tx.is_closed());
+            clients.retain(|client| !Arc::ptr_eq(client, &session));
         }
 
         // 显式关闭当前客户端的发送端
@@ -130,109 +626,314 @@ impl MultiDisplayServer {
         Ok(())
     }
 
+    /// 异常断开的会话在宽限期内保留状态，等待客户端用 ResumeSession 恢复
+    async fn park_session(&self, session: &ClientSession) {
+        if !self.resumable(session) {
+            if let Some(recording) = session.recorder.stop() {
+                recording.finish().await;
+            }
+            return;
+        }
+        let state = SessionState {
+            clipboard_enabled: session.clipboard_enabled.load(Ordering::Relaxed),
+            audio_enabled: session.audio_enabled.load(Ordering::Relaxed),
+            display_id: self.virtual_displays.current_id(),
+        };
+        let id = session.id();
+        tracing::info!("Keeping session {} for {}s", id, self.config.resume.grace_secs);
+        self.sessions.park(
+            id,
+            state,
+            session.device.as_ref().map(|d| d.device_id.clone()),
+            session.recorder.detach(),
+        );
+    }
+
+    /// 定期丢弃过期的会话；关闭时结束所有保留会话中的录制
+    async fn expire_parked_sessions(self: Arc<Self>) {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(1)) => {
+                    let expired = self.sessions.expire();
+                    if expired > 0 {
+                        tracing::debug!("{} parked sessions expired", expired);
+                    }
+                }
+                _ = self.shutdown.cancelled() => break,
+            }
+        }
+        for recorder in self.sessions.clear() {
+            if let Some(recording) = recorder.stop() {
+                recording.finish().await;
+            }
+        }
+    }
+
+    /// 会话 ID 是恢复时的唯一凭据，因此只有认证过的设备才能恢复，并且只能恢复自己的会话
+    fn resumable(&self, session: &ClientSession) -> bool {
+        self.config.resume.enabled && session.device.is_some()
+    }
+
+    fn session_info(&self, session: &ClientSession, resumed: bool) -> ServerMessage {
+        ServerMessage::SessionInfo {
+            session_id: session.id(),
+            resumed,
+            resume_grace_secs: self.config.resume.grace_secs,
+        }
+    }
+
+    /// 心跳超时后返回；未开启心跳时永不返回
+    async fn watch_heartbeat(&self, session: &ClientSession) {
+        let config = &self.config.heartbeat;
+        if !config.enabled {
+            return std::future::pending().await;
+        }
+        let timeout = Duration::from_millis(config.timeout_ms);
+        loop {
+            let idle = session.heartbeat.idle_for();
+            if idle >= timeout {
+                return;
+            }
+            tokio::time::sleep(timeout - idle).await;
+        }
+    }
+
+    /// 握手：发送 AuthChallenge，等待客户端的 Hello 并校验；被锁定的地址不发挑战直接拒绝
+    async fn authenticate(
+        auth: &Authenticator,
+        addr: SocketAddr,
+        writer: &mut WsWriter,
+        reader: &mut WsReader,
+    ) -> Result<AuthOutcome> {
+        auth.check_peer(addr.ip())?;
+        let nonce = Authenticator::new_nonce();
+        Self::send_text(writer, &ServerMessage::AuthChallenge { nonce: nonce.clone() }).await?;
+
+        let hello = loop {
+            match reader.next().await {
+                Some(Ok(Message::Text(text))) => break deserialize_message::<ClientMessage>(text.as_bytes())?,
+                Some(Ok(Message::Binary(data))) => break deserialize_message::<ClientMessage>(&data)?,
+                Some(Ok(Message::Close(_))) | None => {
+                    return Err("Client closed the connection during handshake".to_string());
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e.to_string()),
+            }
+        };
+        let ClientMessage::Hello { device_name, credentials } = hello else {
+            return Err("Expected Hello as the first message".to_string());
+        };
+
+        let outcome = auth.verify(addr.ip(), &nonce, &device_name, credentials)?;
+        Self::send_text(
+            writer,
+            &ServerMessage::AuthAccepted {
+                device_id: outcome.device_id.clone(),
+                token: outcome.token.clone(),
+            },
+        )
+        .await?;
+        Ok(outcome)
+    }
+
+    async fn send_text(writer: &mut WsWriter, message: &ServerMessage) -> Result<()> {
+        let text = serialize_message(message)?;
+        writer
+            .send(Message::Text(Utf8Bytes::try_from(text).map_err(|e| e.to_string())?))
+            .await
+            .map_err(|e| e.to_string())
+    }
+
     fn send_msg2client(
-        mut writer: futures::stream::SplitSink<
-            tokio_tungstenite::WebSocketStream<TcpStream>,
-            tokio_tungstenite::tungstenite::Message,
-        >,
-        mut rx: Receiver<ServerMessage>,
+        mut writer: WsWriter,
+        mut rx: Receiver<Outgoing>,
+        session: Arc<ClientSession>,
+        metrics: Arc<Metrics>,
+        ping_interval: Option<Duration>,
+        shutdown: CancellationToken,
     ) -> JoinHandle<()> {
         // 发送视频流到客户端
-        let send_task = tokio::spawn(async move {
-            println!("send_msg2client send_task");
-            while let Some(message) = rx.recv().await {
-                println!("send_msg2client send_task recv message ");
-                let msg = serialize_message(&message).unwrap();
-               // println!("send_msg2client msg:{:?}",message );
+        tokio::spawn(async move {
+            let mut ping = ping_interval.map(|period| {
+                let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                ticker
+            });
+            loop {
+                let Outgoing { message, span } = tokio::select! {
+                    message = rx.recv() => match message {
+                        Some(message) => message,
+                        None => break,
+                    },
+                    _ = tick(&mut ping) => {
+                        // WebSocket Ping 用于测量往返时间，Heartbeat 供无法处理 Ping 的客户端使用
+                        let payload = session.heartbeat.next_ping();
+                        if let Err(e) = writer.send(Message::Ping(payload.into())).await {
+                            tracing::error!("Error sending ping: {}", e);
+                            break;
+                        }
+                        Outgoing {
+                            message: ServerMessage::Heartbeat,
+                            span: Span::current(),
+                        }
+                    }
+                    _ = shutdown.cancelled() => {
+                        let close = CloseFrame {
+                            code: CloseCode::Away,
+                            reason: "Server shutting down".into(),
+                        };
+                        let _ = writer.send(Message::Close(Some(close))).await;
+                        break;
+                    }
+                };
+                session.recording_stopped(session.recorder.record_server(&message));
                 match message {
-                    ServerMessage::VideoFrame { data, .. } => {
-                        println!("send_msg2client message data.len():{:?}",data.len() );
-                        if let Err(e) = writer.send(Message::binary(data)).await {
+                    ServerMessage::VideoFrame { display_index, width, height, data, timestamp } => {
+                        let send_span = tracing::debug_span!(parent: &span, "send", client = %session.addr, bytes = data.len());
+                        let frame = media::encode_video(display_index, width, height, timestamp, &data);
+                        if let Err(e) = writer.send(Message::binary(frame)).instrument(send_span).await {
                            // writer.close();
-                            log::error!("Error sending binary frame: {}", e);
+                            tracing::error!("Error sending binary frame: {}", e);
                              break;
                         }
+                        tracing::debug!(
+                            parent: &span,
+                            client = %session.addr,
+                            age_ms = timestamp_millis().saturating_sub(timestamp),
+                            "Frame sent"
+                        );
+                        session.stats.frames_sent.fetch_add(1, Ordering::Relaxed);
+                        session.stats.fps.record();
+                        metrics.frames_sent.fetch_add(1, Ordering::Relaxed);
+                    }
+                    ServerMessage::AudioChunk { codec, sample_rate, channels, data, timestamp } => {
+                        let chunk = media::encode_audio(codec, sample_rate, channels, timestamp, &data);
+                        if let Err(e) = writer.send(Message::binary(chunk)).await {
+                            tracing::error!("Error sending audio chunk: {}", e);
+                            break;
+                        }
                     }
                     other_message => {
-                        if let Ok(text) = serialize_message(&other_message) {
-                            if let Err(e) = writer.send(Message::Text(Utf8Bytes::try_from(text).unwrap())).await {
+                        if let Ok(text) = serialize_message(&other_message)
+                            && let Err(e) = writer.send(Message::Text(Utf8Bytes::try_from(text).unwrap())).await {
                              //   writer.close();
-                                log::error!("Error sending text message: {}", e);
+                                tracing::error!("Error sending text message: {}", e);
                                 break;
-                            }
                         }
                     }
                 }
             }
-        });
-        send_task
+        }.instrument(Span::current()))
     }
 
+    /// 将桌面和其他会话的剪贴板变化转发给已开启剪贴板同步的会话
+    fn forward_clipboard(&self, session: Arc<ClientSession>) -> Option<JoinHandle<()>> {
+        let mut updates = self.clipboard.as_ref()?.subscribe();
+        Some(tokio::spawn(async move {
+            loop {
+                match updates.recv().await {
+                    Ok(change) => {
+                        if !session.clipboard_enabled.load(Ordering::Relaxed)
+                            || change.origin.as_deref() == Some(session.id().as_str())
+                        {
+                            continue;
+                        }
+                        let message = ServerMessage::ClipboardUpdate {
+                            mime_type: change.content.mime_type,
+                            data: change.content.data,
+                        };
+                        if session.send(message).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }.instrument(Span::current())))
+    }
+
+    /// 将桌面音频转发给已开启音频的会话；客户端处理不过来时丢弃旧的音频块
+    fn forward_audio(&self, session: Arc<ClientSession>) -> Option<JoinHandle<()>> {
+        let mut chunks = self.audio.as_ref()?.subscribe();
+        Some(tokio::spawn(async move {
+            loop {
+                match chunks.recv().await {
+                    Ok(chunk) => {
+                        if !session.audio_enabled.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        if session.send(chunk).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::debug!("Client lagging, dropped {} audio chunks", skipped);
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }.instrument(Span::current())))
+    }
+
+    /// 客户端发送关闭帧时任务返回 true，连接异常断开时返回 false
     fn deal_msg_from_client(
         &self,
-        reader: futures::stream::SplitStream<tokio_tungstenite::WebSocketStream<TcpStream>>,
-    ) -> JoinHandle<()> {
+        reader: WsReader,
+        session: Arc<ClientSession>,
+    ) -> JoinHandle<bool> {
         let client_arc = self.clone();
-        let receive_task = tokio::spawn(async move {
-            println!("deal_msg_from_client receive_task");
+        tokio::spawn(async move {
             let mut read_stream = reader;
             while let Some(result) = read_stream.next().await {
-                println!("deal_msg_from_client message: {:?}", &result);
                 match result {
                     std::result::Result::Ok(msg) => {
-                        println!("deal_msg_from_client msg: {:?}", &msg);
-                        match msg {
-                            Message::Text(text) => {}
-                            Message::Binary(data) => {
-                                if let Ok(client_msg) =
-                                    deserialize_message::<ClientMessage>(&data)
-                                {
-                                    if let Err(e) =
-                                        client_arc.handle_client_message(client_msg).await
-                                    {
-                                        log::error!("Error handling client message: {}", e);
-                                        println!("Error handling client message: {}", e);
-                                    }
-                                }
-                            }
+                        session.heartbeat.touch();
+                        let data = match msg {
+                            Message::Text(text) => Some(text.as_bytes().to_vec()),
+                            Message::Binary(data) => Some(data.to_vec()),
                             Message::Close(_) => {
-                                println!("Client  sent close frame");
-                                break;
+                                tracing::debug!("Client sent close frame");
+                                return true;
                             }
-                            Message::Ping(ping_data) => {
-                                // 自动响应 pong
+                            Message::Ping(_ping_data) => {
+                                // tungstenite 在下一次读写时自动回复 Pong
+                                None
                             }
-                            Message::Pong(_) => {
-                                // 忽略 pong 响应
+                            Message::Pong(payload) => {
+                                if let Some(rtt) = session.heartbeat.on_pong(&payload) {
+                                    tracing::trace!(rtt_ms = rtt.as_secs_f64() * 1000.0, "Pong");
+                                }
+                                None
+                            }
+                            _ => None,
+                        };
+                        if let Some(data) = data
+                            && let Ok(client_msg) = deserialize_message::<ClientMessage>(&data)
+                        {
+                            tracing::trace!(message = ?client_msg, "Client message");
+                            session.recording_stopped(session.recorder.record_client(&client_msg));
+                            if let Err(e) = client_arc.handle_client_message(&session, client_msg).await {
+                                tracing::error!("Error handling client message: {}", e);
                             }
-                            _ => {}
                         }
                     }
                     std::result::Result::Err(e) => {
-                        log::error!("Error reading from client: {}", e);
-                        println!("Error reading from client: {}", e);
+                        tracing::error!("Error reading from client: {}", e);
                         break;
                     }
                 }
             }
-        });
-        receive_task
+            false
+        }.instrument(Span::current()))
     }
 
     async fn send_config_to_client(
         &self,
-        writer: &mut futures::stream::SplitSink<
-            tokio_tungstenite::WebSocketStream<TcpStream>,
-            tokio_tungstenite::tungstenite::Message,
-        >,
+        writer: &mut WsWriter,
     ) -> Result<()> {
-        println!("send_config_to_client");
         // 发送初始配置
-        let config = ServerMessage::DisplayConfig {
-            total_displays: self.virtual_displays.get_display_count(),
-            current_display: *self.current_display.read().await,
-            resolutions: vec![(1920, 1080); 3], // 示例分辨率
-        };
+        let config = self.display_config().await;
 
         let config_data = serialize_message(&config)?;
         writer
@@ -243,92 +944,844 @@ impl MultiDisplayServer {
         Ok(())
     }
 
-    async fn handle_client_message(&self, message: ClientMessage) -> Result<()> {
-        println!("handle_client_message");
+    async fn display_config(&self) -> ServerMessage {
+        self.virtual_displays.display_config()
+    }
+
+    async fn handle_client_message(&self, session: &ClientSession, message: ClientMessage) -> Result<()> {
+        let first_message = session.first_message.swap(false, Ordering::Relaxed);
         match message {
-            ClientMessage::SensorData { rotation_y, .. } => {
-                // 根据旋转数据切换显示器
+            ClientMessage::SensorData {
+                rotation_x, rotation_y, ..
+            } => {
+                self.metrics.record_sensor_message();
+                self.foveation.look(rotation_y, rotation_x);
+                // 放大期间头部转动用于平移画面，拼接桌面时移动视口，否则根据旋转数据切换显示器；
+                // 窗口画面不经过视口，在窗口显示器上转头仍然切换显示器
+                let current = self.virtual_displays.current_id();
+                let on_window = self.virtual_displays.current_window().is_some();
+                if self.zoom.pan(current, rotation_y, rotation_x)
+                    || (!on_window && self.viewport.look(rotation_y, rotation_x))
+                {
+                    return Ok(());
+                }
                 if rotation_y > 30.0 {
                     self.switch_display(SwitchDirection::Next).await?;
                 } else if rotation_y < -30.0 {
                     self.switch_display(SwitchDirection::Previous).await?;
                 }
             }
-            ClientMessage::SwitchDisplay { direction } => {
-                self.switch_display(direction).await?;
-            }
+            ClientMessage::SwitchDisplay { direction } => match self.switch_display(direction).await {
+                // 变化已推送给所有客户端，否则单独确认当前显示器
+                Ok(true) => {}
+                Ok(false) => {
+                    let current_display = self.virtual_displays.current_display();
+                    session.send(ServerMessage::DisplaySwitched { current_display }).await?;
+                }
+                Err(e) => return self.send_error(session, &format!("Cannot switch display: {}", e)).await,
+            },
             ClientMessage::Heartbeat => {
-                // 心跳处理
+                // 收到任何数据时已经刷新了会话的心跳时间
+            }
+            ClientMessage::Hello { .. } => {
+                return self.send_error(session, "Already authenticated").await;
+            }
+            ClientMessage::ClipboardSync { enabled } => {
+                if enabled && self.clipboard.is_none() {
+                    return self
+                        .send_error(session, "Clipboard sync is not available on this server")
+                        .await;
+                }
+                session.clipboard_enabled.store(enabled, Ordering::Relaxed);
+                tracing::info!("Clipboard sync {}", if enabled { "enabled" } else { "disabled" });
+            }
+            ClientMessage::ClipboardUpdate { mime_type, data } => {
+                let Some(clipboard) = &self.clipboard else {
+                    return self
+                        .send_error(session, "Clipboard sync is not available on this server")
+                        .await;
+                };
+                if !session.clipboard_enabled.load(Ordering::Relaxed) {
+                    return self
+                        .send_error(session, "Clipboard sync is not enabled for this session")
+                        .await;
+                }
+                if let Err(e) = clipboard.set(ClipboardContent { mime_type, data }, &session.id()) {
+                    return self.send_error(session, &e).await;
+                }
+            }
+            ClientMessage::SetAudio { enabled } => {
+                if enabled && self.audio.is_none() {
+                    return self
+                        .send_error(session, "Audio streaming is not available on this server")
+                        .await;
+                }
+                session.audio_enabled.store(enabled, Ordering::Relaxed);
+                tracing::info!("Audio {}", if enabled { "enabled" } else { "disabled" });
+            }
+            ClientMessage::SetRecording { enabled } => {
+                let file = if enabled {
+                    if !self.config.recording.enabled {
+                        return self
+                            .send_error(session, "Session recording is disabled on this server")
+                            .await;
+                    }
+                    let device = session.device.as_ref();
+                    let header = RecordingHeader::new(
+                        timestamp_millis(),
+                        device.map(|d| d.device_id.clone()),
+                        &self.display_config().await,
+                    )?;
+                    let name = device
+                        .map(|d| d.device_name.clone())
+                        .unwrap_or_else(|| session.addr.ip().to_string());
+                    match session.recorder.start(&self.config.recording, &name, &header).await {
+                        Ok(file) => Some(file),
+                        Err(e) => {
+                            return self
+                                .send_error(session, &format!("Failed to start recording: {}", e))
+                                .await;
+                        }
+                    }
+                } else {
+                    match session.recorder.stop() {
+                        Some(recording) => Some(recording.finish().await),
+                        None => None,
+                    }
+                };
+                session
+                    .send(ServerMessage::RecordingState { active: enabled, file })
+                    .await?;
+            }
+            ClientMessage::ResizeDisplay { index, width, height } => {
+                if let Err(e) = self.resize_display_for_client(index, width, height).await {
+                    return self.send_error(session, &format!("Cannot resize display: {}", e)).await;
+                }
+            }
+            ClientMessage::ArrangeDisplays { arrangement } => {
+                if let Err(e) = self.arrange_displays_for_client(arrangement).await {
+                    return self.send_error(session, &format!("Cannot arrange displays: {}", e)).await;
+                }
+            }
+            ClientMessage::SetDisplayLayout { index, layout } => {
+                if let Err(e) = self.set_display_layout_for_client(index, layout).await {
+                    return self.send_error(session, &format!("Cannot move display: {}", e)).await;
+                }
+            }
+            ClientMessage::CreateDisplay { width, height, name } => {
+                if let Err(e) = self.create_display_for_client(&name, width, height).await {
+                    return self.send_error(session, &format!("Cannot create display: {}", e)).await;
+                }
+            }
+            ClientMessage::DestroyDisplay { index } => {
+                if let Err(e) = self.destroy_display_for_client(index).await {
+                    return self.send_error(session, &format!("Cannot destroy display: {}", e)).await;
+                }
+            }
+            ClientMessage::ResumeSession { session_id } => {
+                return self.resume_session(session, session_id, first_message).await;
+            }
+            ClientMessage::SetZoom { zoom } => {
+                if let Err(e) = self.set_zoom(zoom).await {
+                    return self.send_error(session, &format!("Cannot zoom: {}", e)).await;
+                }
+            }
+            ClientMessage::ListWindows => match self.list_windows().await {
+                Ok(windows) => session.send(ServerMessage::WindowList { windows }).await?,
+                Err(e) => return self.send_error(session, &format!("Cannot list windows: {}", e)).await,
+            },
+            ClientMessage::CaptureWindow { window } => {
+                if let Err(e) = self.capture_window_for_client(window).await {
+                    return self.send_error(session, &format!("Cannot capture window: {}", e)).await;
+                }
             }
         }
         Ok(())
     }
 
-    async fn switch_display(&self, direction: SwitchDirection) -> Result<()> {
-        println!("switch_display");
-        let total_displays = self.virtual_displays.get_display_count() as u8;
-        let mut current = self.current_display.write().await;
+    /// 将保留的会话状态转移到当前连接，随后补发配置和最新一帧
+    ///
+    /// 显示器是所有客户端共享的：没有其他客户端连接时切回断开前选中的显示器，
+    /// 否则保持不变，以免打断其他客户端的操作
+    async fn resume_session(&self, session: &ClientSession, session_id: String, first_message: bool) -> Result<()> {
+        if !self.config.resume.enabled {
+            return self
+                .send_error(session, "Session resumption is disabled on this server")
+                .await;
+        }
+        if !self.resumable(session) {
+            return self
+                .send_error(session, "Session resumption requires an authenticated device")
+                .await;
+        }
+        // 一个连接只能接管一个会话，且不能混入本连接已有的状态
+        if !first_message {
+            return self
+                .send_error(session, "ResumeSession must be the first message on a connection")
+                .await;
+        }
+        let device_id = session.device.as_ref().map(|d| d.device_id.as_str());
+        let Some(parked) = self.sessions.take(&session_id, device_id) else {
+            return self.send_error(session, "Session expired or unknown").await;
+        };
 
-        match direction {
-            SwitchDirection::Next => {
-                *current = (*current + 1) % total_displays;
-            }
-            SwitchDirection::Previous => {
-                *current = if *current == 0 {
-                    total_displays - 1
-                } else {
-                    *current - 1
-                };
+        session
+            .clipboard_enabled
+            .store(parked.state.clipboard_enabled && self.clipboard.is_some(), Ordering::Relaxed);
+        session
+            .audio_enabled
+            .store(parked.state.audio_enabled && self.audio.is_some(), Ordering::Relaxed);
+        session.recorder.adopt(parked.recorder);
+        let alone = self
+            .clients
+            .lock()
+            .await
+            .iter()
+            .all(|client| std::ptr::eq(client.as_ref(), session));
+        if alone
+            && let Some(index) = self.virtual_displays.index_of(parked.state.display_id)
+            && self.virtual_displays.set_current_display(index) == Ok(true)
+        {
+            tracing::info!("Switched to display {}", index);
+            self.emit(ServerEvent::DisplaySwitched { display: index });
+        }
+        *session.id.lock().unwrap() = session_id.clone();
+        tracing::info!("Resumed session {}", session_id);
+        self.emit(ServerEvent::SessionResumed {
+            addr: session.addr,
+            session_id,
+        });
+
+        session.send(self.session_info(session, true)).await?;
+        session.send(self.display_config().await).await?;
+        // 不必等下一次捕获：当前显示器已有画面时立即补发
+        let current_display = self.virtual_displays.current_display();
+        let frame = self.latest_frame.lock().unwrap().clone();
+        if let Some(frame @ ServerMessage::VideoFrame { display_index, .. }) = frame
+            && display_index == current_display
+        {
+            session.send(frame).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_error(&self, session: &ClientSession, message: &str) -> Result<()> {
+        tracing::warn!("{}", message);
+        session
+            .send(ServerMessage::Error {
+                message: message.to_string(),
+            })
+            .await
+    }
+
+    /// 返回当前显示器是否发生了变化
+    async fn switch_display(&self, direction: SwitchDirection) -> Result<bool> {
+        let displays = &self.virtual_displays;
+        let before = displays.current_display();
+        let current = match direction {
+            SwitchDirection::Next => Some(displays.switch_display(1)),
+            SwitchDirection::Previous => Some(displays.switch_display(-1)),
+            // 按布局查找该方向上的显示器，没有时保持不变
+            SwitchDirection::Left => displays.switch_towards(-1, 0),
+            SwitchDirection::Right => displays.switch_towards(1, 0),
+            SwitchDirection::Up => displays.switch_towards(0, -1),
+            SwitchDirection::Down => displays.switch_towards(0, 1),
+            SwitchDirection::Goto(index) => displays.set_current_display(index).map(|_| Some(index))?,
+            SwitchDirection::Last => displays.switch_to_last(),
+        };
+
+        match current {
+            Some(current) if current != before => {
+                self.display_switched(current).await;
+                Ok(true)
             }
+            _ => Ok(false),
+        }
+    }
+
+    /// 通知所有客户端当前显示器已变化，使各自的 HUD 保持同步
+    async fn display_switched(&self, current: u8) {
+        tracing::info!("Switched to display {}", current);
+        self.emit(ServerEvent::DisplaySwitched { display: current });
+        self.broadcast(ServerMessage::DisplaySwitched { current_display: current }).await;
+    }
+
+    /// 运行时添加一个显示器，返回其编号
+    pub async fn add_display(&self, name: &str, width: u32, height: u32) -> Result<u8> {
+        let index = self.virtual_displays.add_display(name, width, height, None).await?;
+        self.displays_changed().await;
+        Ok(index)
+    }
+
+    /// 客户端改动显示器前检查配置是否允许
+    fn check_client_managed(&self, action: &str) -> Result<()> {
+        if !self.config.displays.client_managed {
+            return Err(format!("Clients are not allowed to {} displays on this server", action));
+        }
+        Ok(())
+    }
+
+    /// 客户端请求的显示器尺寸不能超过配置的上限
+    fn check_client_size(&self, width: u32, height: u32) -> Result<()> {
+        let limits = &self.config.displays;
+        if width > limits.max_width || height > limits.max_height {
+            return Err(format!(
+                "Display size {}x{} exceeds the limit of {}x{}",
+                width, height, limits.max_width, limits.max_height
+            ));
         }
+        Ok(())
+    }
+
+    /// 客户端请求新建显示器：检查配置中的限制，成功后切换到新显示器
+    async fn create_display_for_client(&self, name: &str, width: u32, height: u32) -> Result<u8> {
+        self.check_client_managed("create")?;
+        self.check_client_size(width, height)?;
+        let max_displays = Some(self.config.displays.max_displays);
+        let index = self.virtual_displays.add_display(name, width, height, max_displays).await?;
+        self.virtual_displays.set_current_display(index)?;
+        tracing::info!("Switched to display {}", index);
+        self.emit(ServerEvent::DisplaySwitched { display: index });
+        self.displays_changed().await;
+        Ok(index)
+    }
+
+    /// 放大当前显示器，并通知所有客户端
+    async fn set_zoom(&self, zoom: Zoom) -> Result<()> {
+        let display_index = self.virtual_displays.current_display();
+        let display = self
+            .virtual_displays
+            .display(display_index)
+            .ok_or_else(|| format!("No display {}", display_index))?;
+        // 还没有捕获过画面时按显示器尺寸校验
+        let (width, height) = self
+            .zoom
+            .frame_size(display.id)
+            .unwrap_or((display.width, display.height));
+        self.zoom.set(display.id, zoom, width, height)?;
+        tracing::info!("Zoom on display {}: {:?}", display_index, zoom);
+        self.broadcast(ServerMessage::ZoomChanged { display_index, zoom }).await;
+        Ok(())
+    }
+
+    async fn list_windows(&self) -> Result<Vec<WindowInfo>> {
+        let limits = &self.config.displays;
+        if !limits.client_managed || !limits.window_capture {
+            return Err("Window capture is disabled on this server".to_string());
+        }
+        let windows = self.windows.clone();
+        tokio::task::spawn_blocking(move || windows.list_windows())
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    /// 客户端请求把一个窗口作为显示器推流：新建窗口显示器并切换过去
+    async fn capture_window_for_client(&self, selector: WindowSelector) -> Result<u8> {
+        let windows = self.list_windows().await?;
+        let window = window::find_window(&windows, &selector)?;
+        let max_displays = self.config.displays.max_displays;
+        let index = self
+            .virtual_displays
+            .add_window(&window.title, window.width, window.height, window.id, max_displays)
+            .await?;
+        self.virtual_displays.set_current_display(index)?;
+        tracing::info!("Capturing window {:#x} ({}) as display {}", window.id, window.title, index);
+        self.emit(ServerEvent::DisplaySwitched { display: index });
+        self.displays_changed().await;
+        Ok(index)
+    }
+
+    /// 窗口关闭后移除对应的窗口显示器
+    async fn window_closed(&self, display: u32) {
+        let Some(index) = self.virtual_displays.index_of(display) else {
+            return;
+        };
+        if let Err(e) = self.remove_display(index).await {
+            tracing::warn!("Failed to remove display {} of a closed window: {}", index, e);
+        }
+    }
+
+    /// 客户端只能销毁客户端创建的显示器
+    async fn destroy_display_for_client(&self, index: u8) -> Result<()> {
+        self.check_client_managed("destroy")?;
+        let display = self
+            .virtual_displays
+            .display(index)
+            .ok_or_else(|| format!("No display {}", index))?;
+        if !display.client_created {
+            return Err(format!("Display {} was not created by a client", index));
+        }
+        self.remove_display(index).await
+    }
+
+    /// 移除显示器；正在观看该显示器的客户端会切到相邻的显示器
+    pub async fn remove_display(&self, index: u8) -> Result<()> {
+        let current = self.virtual_displays.current_display();
+        self.virtual_displays.remove_display(index).await?;
+        if index == current {
+            let current = self.virtual_displays.current_display();
+            tracing::info!("Switched to display {}", current);
+            self.emit(ServerEvent::DisplaySwitched { display: current });
+        }
+        self.displays_changed().await;
+        Ok(())
+    }
+
+    pub async fn resize_display(&self, index: u8, width: u32, height: u32) -> Result<()> {
+        self.virtual_displays.resize_display(index, width, height).await?;
+        self.displays_changed().await;
+        Ok(())
+    }
+
+    /// 客户端调整分辨率受与新建显示器相同的限制
+    async fn resize_display_for_client(&self, index: u8, width: u32, height: u32) -> Result<()> {
+        self.check_client_managed("resize")?;
+        self.check_client_size(width, height)?;
+        self.resize_display(index, width, height).await
+    }
+
+    pub async fn arrange_displays(&self, arrangement: Arrangement) -> Result<()> {
+        self.virtual_displays.arrange(arrangement).await?;
+        self.displays_changed().await;
+        Ok(())
+    }
+
+    async fn arrange_displays_for_client(&self, arrangement: Arrangement) -> Result<()> {
+        self.check_client_managed("arrange")?;
+        self.arrange_displays(arrangement).await
+    }
 
-        log::info!("Switched to display {}", *current);
-        println!("Switched to display {}", *current);
+    pub async fn set_display_layout(&self, index: u8, layout: DisplayLayout) -> Result<()> {
+        self.virtual_displays.set_layout(index, layout).await?;
+        self.displays_changed().await;
         Ok(())
     }
 
-    async fn start_streaming(&self, mut capturer:CrossPlatformCapturer) -> Result<()>{
-        println!("start_streaming");
-       // let mut interval = interval(Duration::from_millis(33)); // ~30fps
+    async fn set_display_layout_for_client(&self, index: u8, layout: DisplayLayout) -> Result<()> {
+        self.check_client_managed("move")?;
+        self.set_display_layout(index, layout).await
+    }
+
+    /// 显示器布局变化后向所有会话推送新的 DisplayConfig
+    async fn displays_changed(&self) {
+        let config = self.display_config().await;
+        let total_displays = self.virtual_displays.get_display_count();
+        self.update_advertisement(total_displays);
+        self.emit(ServerEvent::DisplaysChanged { total_displays });
+        self.broadcast(config).await;
+    }
+
+    /// 向所有会话发送同一条消息，队列已满的会话丢弃这条消息
+    async fn broadcast(&self, message: ServerMessage) {
+        let clients = self.clients.lock().await.clone();
+        for client in clients {
+            client.try_send(message.clone());
+        }
+    }
+
+    /// 显示器数量变化时更新 mDNS TXT 记录中的 displays
+    fn update_advertisement(&self, display_count: usize) {
+        let mut advertiser = self.advertiser.lock().unwrap();
+        if let Some(advertiser) = advertiser.as_mut()
+            && advertiser.announcement().display_count != display_count
+        {
+            let announcement = ServiceAnnouncement {
+                display_count,
+                ..advertiser.announcement().clone()
+            };
+            if let Err(e) = advertiser.update(&announcement) {
+                tracing::warn!("Failed to update mDNS advertisement: {}", e);
+            }
+        }
+    }
+
+    /// 监督捕获线程和编码任务：任一出错时按指数退避重启，直到服务关闭
+    async fn supervise_capture(self: Arc<Self>) {
+        let mut backoff = Duration::from_secs(1);
         loop {
-          //  interval.tick().await;
-            let current_display = *self.current_display.read().await;
-            match capturer.capture_frame() {
-                Ok(frame_data) => {
-                    println!("start_streaming frame_data");
-                    let message = ServerMessage::VideoFrame {
-                        display_index: current_display,
-                        width: frame_data.width(),
-                        height: frame_data.height(),
-                        data: compress_frame(&frame_data)?.to_vec(),
-                        timestamp: std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis() as u64,
-                    };
-                   // println!("start_streaming frame_data data.len():{:?}", frame_data.to_vec().len() );
-                    // 发送给所有连接的客户端
-                    // 先克隆出当前的 Sender 列表并释放锁，避免在发送时持有锁
-                    let clients_vec = {
-                        let clients = self.clients.lock().await;
-                        clients.clone()
+            // 每一轮使用新的子令牌，编码出错时只停止这一轮的捕获线程
+            let stop = self.shutdown.child_token();
+            let pipe = Arc::new(CapturePipe::new());
+            let (closed_tx, mut closed_rx) = tokio::sync::mpsc::unbounded_channel();
+            let thread = CaptureThread {
+                stop: stop.clone(),
+                pipe: pipe.clone(),
+                screen: self.capture.clone(),
+                windows: self.windows.clone(),
+                displays: self.virtual_displays.clone(),
+                metrics: self.metrics.clone(),
+                closed_windows: closed_tx,
+                interval: Duration::from_secs(1) / self.config.displays.capture_fps.max(1),
+            };
+            tracing::info!("Capture started with {:?}", self.encoder);
+
+            let mut delivered = false;
+            let result = match spawn_capture_thread(thread) {
+                Ok(worker) => {
+                    let encoded = self.encode_frames(&pipe, &mut closed_rx, &stop, &mut delivered).await;
+                    // 等捕获线程退出后再重启，避免新旧两个线程同时打开屏幕
+                    stop.cancel();
+                    let captured = tokio::task::spawn_blocking(move || worker.join())
+                        .await
+                        .map_err(|e| e.to_string())
+                        .and_then(|joined| joined.unwrap_or_else(|_| Err("Capture thread panicked".to_string())));
+                    encoded.and(captured)
+                }
+                Err(e) => Err(e),
+            };
+            if self.shutdown.is_cancelled() {
+                break;
+            }
+            if delivered {
+                backoff = Duration::from_secs(1);
+            }
+            let e = result.err().unwrap_or_else(|| "Capture stopped unexpectedly".to_string());
+            tracing::error!("Capture error: {}, restarting in {:?}", e, backoff);
+            self.emit(ServerEvent::CaptureFailed { message: e });
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {}
+                _ = self.shutdown.cancelled() => break,
+            }
+            backoff = (backoff * 2).min(MAX_CAPTURE_BACKOFF);
+        }
+        tracing::info!("Capture stopped");
+    }
+
+    /// 从环形缓冲取帧编码并推送给客户端；捕获线程退出或本轮停止时返回
+    async fn encode_frames(
+        &self,
+        pipe: &Arc<CapturePipe>,
+        closed_windows: &mut UnboundedReceiver<u32>,
+        stop: &CancellationToken,
+        delivered: &mut bool,
+    ) -> Result<()> {
+        loop {
+            tokio::select! {
+                captured = pipe.frames.next() => {
+                    let Some(captured) = captured else {
+                        return Ok(());
                     };
+                    let frame = self.encode_frame(captured, pipe).await?;
+                    *delivered = true;
+                    self.start_streaming(frame).await;
+                }
+                Some(display) = closed_windows.recv() => self.window_closed(display).await,
+                _ = stop.cancelled() => return Ok(()),
+            }
+        }
+    }
+
+    /// 在阻塞线程池中编码一帧，编码期间不占用 tokio 的工作线程
+    async fn encode_frame(&self, captured: CapturedFrame, pipe: &Arc<CapturePipe>) -> Result<EncodedFrame> {
+        self.metrics.record_queue_wait(captured.captured_at.elapsed());
+        let encoder = self.encoder.clone();
+        let zoom = self.zoom.clone();
+        let foveation = self.foveation.clone();
+        let viewport = self.viewport.clone();
+        let metrics = self.metrics.clone();
+        let (encoded, frame) = tokio::task::spawn_blocking(move || {
+            encode_captured(captured, encoder.as_ref(), &viewport, &zoom, &foveation, &metrics)
+        })
+        .await
+        .map_err(|e| format!("Encode task panicked: {}", e))??;
+        // 缓冲区交还捕获线程复用，捕获线程来不及取时直接释放
+        let _ = pipe.recycled.push(frame);
+        Ok(encoded)
+    }
 
-                    for client in clients_vec.into_iter() {
-                        if let Err(e) = client.send(message.clone()).await {
-                            log::debug!("Failed to send to client (will cleanup): {}", e);
-                            // 清理已关闭的客户端
-                            let mut clients = self.clients.lock().await;
-                            clients.retain(|c| !c.is_closed());
+    /// 将一帧发送给所有连接的客户端；客户端的发送队列已满时丢弃该帧
+    async fn start_streaming(&self, frame: EncodedFrame) {
+        let EncodedFrame {
+            window,
+            width,
+            height,
+            data,
+            timestamp,
+            span,
+        } = frame;
+        // 捕获期间切换了显示器时，这一帧已不属于当前显示器
+        if window != self.virtual_displays.current_window().map(|(display, _)| display) {
+            return;
+        }
+        if let Some(display) = window
+            && self.virtual_displays.follow_window_size(display, width, height).await.is_some()
+        {
+            self.displays_changed().await;
+        }
+        let current_display = self.virtual_displays.current_display();
+        let message = ServerMessage::VideoFrame {
+            display_index: current_display,
+            width,
+            height,
+            data,
+            timestamp,
+        };
+        // 先克隆出当前的会话列表并释放锁，避免在发送时持有锁
+        let clients_vec = {
+            let clients = self.clients.lock().await;
+            clients.clone()
+        };
+
+        for client in clients_vec.into_iter() {
+            let outgoing = Outgoing {
+                message: message.clone(),
+                span: span.clone(),
+            };
+            match client.tx.try_send(outgoing) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    // 慢客户端只丢自己的帧，不拖慢其他客户端
+                    tracing::debug!(parent: &span, client = %client.addr, "Client queue full, frame dropped");
+                    client.stats.frames_dropped.fetch_add(1, Ordering::Relaxed);
+                    self.metrics.frames_dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(TrySendError::Closed(_)) => {
+                    tracing::debug!("Client {} closed (will cleanup)", client.addr);
+                    // 清理已关闭的客户端
+                    let mut clients = self.clients.lock().await;
+                    clients.retain(|c| !c.tx.is_closed());
+                }
+            }
+        }
+        *self.latest_frame.lock().unwrap() = Some(message);
+    }
+}
+
+/// 等待心跳定时器；未开启心跳时永不返回
+async fn tick(ticker: &mut Option<tokio::time::Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// 捕获线程与编码任务之间的通道
+struct CapturePipe {
+    frames: FrameRing<CapturedFrame>,
+    /// 编码完的画面送回捕获线程，复用其缓冲区
+    recycled: ArrayQueue<RgbaImage>,
+}
+
+impl CapturePipe {
+    fn new() -> Self {
+        Self {
+            frames: FrameRing::new(FRAME_RING_CAPACITY),
+            recycled: ArrayQueue::new(FRAME_RING_CAPACITY + 1),
+        }
+    }
+}
+
+/// 捕获线程持有的资源；当前显示器是窗口显示器时采集该窗口，否则采集屏幕
+struct CaptureThread {
+    stop: CancellationToken,
+    pipe: Arc<CapturePipe>,
+    screen: CaptureFactory,
+    windows: Arc<dyn WindowSystem>,
+    displays: Arc<VirtualDisplayManager>,
+    metrics: Arc<Metrics>,
+    /// 窗口显示器对应的窗口已关闭
+    closed_windows: UnboundedSender<u32>,
+    /// 两次采集之间的最短间隔
+    interval: Duration,
+}
+
+/// 捕获线程退出（包括 panic）时关闭环形缓冲，唤醒等待中的编码任务
+struct CloseOnExit<'a>(&'a FrameRing<CapturedFrame>);
+
+impl Drop for CloseOnExit<'_> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// 启动专用的捕获线程；scrap 的 Capturer 不能跨线程移动，只能在该线程内创建和使用
+fn spawn_capture_thread(thread: CaptureThread) -> Result<std::thread::JoinHandle<Result<()>>> {
+    std::thread::Builder::new()
+        .name("rotascope-capture".to_string())
+        .spawn(move || {
+            let _close = CloseOnExit(&thread.pipe.frames);
+            capture_loop(&thread)
+        })
+        .map_err(|e| format!("Failed to start capture thread: {}", e))
+}
+
+/// 循环采集画面写入环形缓冲，直到本轮停止或出错
+fn capture_loop(thread: &CaptureThread) -> Result<()> {
+    let mut screen = thread.screen.open()?;
+    // 正在采集的窗口：(显示器 id, 窗口, 采集源)
+    let mut window: Option<(u32, u32, Box<dyn WindowCapture>)> = None;
+    // 已报告关闭、等待服务端移除的窗口显示器
+    let mut closed = HashSet::new();
+    let mut idle = MIN_IDLE_POLL;
+    let mut sequence = 0u64;
+    let mut next_at = Instant::now();
+    while !thread.stop.is_cancelled() {
+        while let Some(frame) = thread.pipe.recycled.pop() {
+            screen.recycle(frame);
+        }
+        let target = thread.displays.current_window();
+        match target {
+            None => window = None,
+            Some((display, _)) if closed.contains(&display) => {
+                std::thread::sleep(WINDOW_POLL_INTERVAL);
+                continue;
+            }
+            Some((display, id)) if window.as_ref().map(|(d, w, _)| (*d, *w)) != target => {
+                match thread.windows.open(id) {
+                    Ok(capture) => window = Some((display, id, capture)),
+                    Err(e) => {
+                        tracing::warn!("Cannot capture window {:#x}: {}", id, e);
+                        closed.insert(display);
+                        if thread.closed_windows.send(display).is_err() {
+                            break;
                         }
+                        continue;
                     }
                 }
-                Err(e) => {
-                    log::error!("Capture error: {}", e);
-                    println!("Capture error: {}", e);
+            }
+            Some(_) => {}
+        }
+
+        // 限制采集帧率：X11 的 scrap 每次都立即返回整屏画面，不限制时会空转并和编码器争抢 CPU
+        if let Some(wait) = next_at.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+
+        // frame span 覆盖捕获、编码以及之后发往各客户端的过程；序号在拿到画面后才确定
+        let span = tracing::debug_span!("frame", seq = tracing::field::Empty);
+        let capture_span = tracing::debug_span!(parent: &span, "capture");
+        let started = Instant::now();
+        let display = thread.displays.current_id();
+        let (frame, order, from) = match &mut window {
+            Some((display, _, capture)) => match capture_span.in_scope(|| capture.capture())? {
+                WindowFrame::Frame(frame) => (frame, PixelOrder::Rgba, Some(*display)),
+                WindowFrame::Unmapped => {
+                    std::thread::sleep(WINDOW_POLL_INTERVAL);
+                    continue;
+                }
+                WindowFrame::Closed => {
+                    let id = *display;
+                    tracing::info!("Window of display {} closed", id);
+                    window = None;
+                    closed.insert(id);
+                    if thread.closed_windows.send(id).is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            },
+            None => match capture_span.in_scope(|| screen.try_capture_frame())? {
+                Some(frame) => (frame, screen.pixel_order(), None),
+                None => {
+                    // 屏幕还没有更新：在本线程内退避等待，不占用 tokio 的线程
+                    std::thread::sleep(idle);
+                    idle = (idle * 2).min(MAX_IDLE_POLL);
+                    continue;
                 }
+            },
+        };
+        idle = MIN_IDLE_POLL;
+        next_at = started + thread.interval;
+        sequence += 1;
+        span.record("seq", sequence);
+
+        let captured = CapturedFrame {
+            frame,
+            order,
+            display,
+            window: from,
+            capture_time: started.elapsed(),
+            captured_at: Instant::now(),
+            span,
+        };
+        // 编码跟不上时覆盖最旧的一帧，捕获线程从不阻塞
+        if let Some(stale) = thread.pipe.frames.push(captured) {
+            tracing::debug!(parent: &stale.span, "Frame overwritten before encoding");
+            thread.metrics.frames_overwritten.fetch_add(1, Ordering::Relaxed);
+            screen.recycle(stale.frame);
+        }
+    }
+    Ok(())
+}
+
+/// 对一帧依次裁出视口、放大和合成注视点后编码，同时返回可以复用缓冲区的画面
+fn encode_captured(
+    captured: CapturedFrame,
+    encoder: &dyn FrameEncoder,
+    viewport: &Viewport,
+    zoom: &ZoomControl,
+    foveation: &Foveation,
+    metrics: &Metrics,
+) -> Result<(EncodedFrame, RgbaImage)> {
+    let CapturedFrame {
+        frame,
+        order,
+        display,
+        window,
+        capture_time,
+        span,
+        ..
+    } = captured;
+    span.in_scope(|| {
+        let started = Instant::now();
+        // 视口只用于屏幕画面，窗口画面原样使用
+        let frame = match window {
+            Some(_) => frame,
+            None => tracing::debug_span!("viewport").in_scope(|| viewport.apply(frame)),
+        };
+        zoom.record_frame(display, frame.width(), frame.height());
+        // 放大后的画面已是头部平移选出的区域，按绝对朝向算出的注视点对不上，不再合成
+        let frame = if zoom.is_zoomed(display) {
+            tracing::debug_span!("zoom").in_scope(|| zoom.apply(display, frame))
+        } else {
+            tracing::debug_span!("foveate").in_scope(|| foveation.apply(frame))
+        };
+        let data = tracing::debug_span!("encode").in_scope(|| match order {
+            PixelOrder::Rgba => encoder.encode(&frame),
+            PixelOrder::Bgra => encoder.encode_bgra(&frame),
+        })?;
+        metrics.record_frame(capture_time, started.elapsed(), data.len());
+        let encoded = EncodedFrame {
+            window,
+            width: frame.width(),
+            height: frame.height(),
+            data,
+            timestamp: timestamp_millis(),
+            span: span.clone(),
+        };
+        Ok((encoded, frame))
+    })
+}
+
+/// 等待 Ctrl-C 或 SIGTERM，收到后取消整个任务树
+async fn wait_for_shutdown_signal(shutdown: CancellationToken) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
             }
         }
-        Ok(())
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("Received Ctrl-C"),
+        _ = terminate => tracing::info!("Received SIGTERM"),
+        _ = shutdown.cancelled() => return,
     }
+    shutdown.cancel();
 }
@@ -0,0 +1,173 @@
+use crate::auth::write_private_file;
+use crate::config::TlsConfig;
+use rotascope_core::Result;
+use rotascope_core::auth::certificate_fingerprint;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+
+/// TLS 记录层中 Handshake 类型的首字节
+const TLS_HANDSHAKE_RECORD: u8 = 0x16;
+
+/// 服务端证书及其指纹；证书不存在时自动生成自签名证书
+#[derive(Clone)]
+pub struct TlsIdentity {
+    acceptor: TlsAcceptor,
+    fingerprint: String,
+}
+
+impl std::fmt::Debug for TlsIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsIdentity")
+            .field("fingerprint", &self.fingerprint)
+            .finish()
+    }
+}
+
+impl TlsIdentity {
+    pub fn load_or_generate(config: &TlsConfig) -> Result<Self> {
+        let cert_path = Path::new(&config.cert_path);
+        let key_path = Path::new(&config.key_path);
+        if !cert_path.exists() || !key_path.exists() {
+            generate_self_signed(cert_path, key_path)?;
+            tracing::info!("Generated self-signed certificate {}", cert_path.display());
+        }
+
+        let certs = CertificateDer::pem_file_iter(cert_path)
+            .map_err(|e| format!("Failed to read {}: {}", cert_path.display(), e))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid certificate {}: {}", cert_path.display(), e))?;
+        let key = PrivateKeyDer::from_pem_file(key_path)
+            .map_err(|e| format!("Invalid private key {}: {}", key_path.display(), e))?;
+        let fingerprint = certs
+            .first()
+            .map(|cert| certificate_fingerprint(cert))
+            .ok_or_else(|| format!("No certificate in {}", cert_path.display()))?;
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let server_config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            acceptor: TlsAcceptor::from(Arc::new(server_config)),
+            fingerprint,
+        })
+    }
+
+    /// 证书指纹，通过配对信息告知客户端用于证书固定
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    pub async fn accept(&self, stream: TcpStream) -> Result<ServerStream> {
+        let stream = self
+            .acceptor
+            .accept(stream)
+            .await
+            .map_err(|e| format!("TLS handshake failed: {}", e))?;
+        Ok(ServerStream::Tls(Box::new(stream)))
+    }
+}
+
+fn generate_self_signed(cert_path: &Path, key_path: &Path) -> Result<()> {
+    let names = vec!["localhost".to_string(), "rotascope.local".to_string()];
+    let certified = rcgen::generate_simple_self_signed(names).map_err(|e| e.to_string())?;
+
+    if let Some(parent) = cert_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(cert_path, certified.cert.pem()).map_err(|e| e.to_string())?;
+    write_private_file(key_path, certified.key_pair.serialize_pem().as_bytes())
+}
+
+/// 客户端是否以 TLS ClientHello 开始（只窥视，不消耗数据）
+pub async fn is_tls_client_hello(stream: &TcpStream) -> Result<bool> {
+    let mut first = [0u8; 1];
+    let n = stream.peek(&mut first).await.map_err(|e| e.to_string())?;
+    Ok(n == 1 && first[0] == TLS_HANDSHAKE_RECORD)
+}
+
+/// 明文或 TLS 连接
+#[derive(Debug)]
+pub enum ServerStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl ServerStream {
+    pub fn is_tls(&self) -> bool {
+        matches!(self, ServerStream::Tls(_))
+    }
+}
+
+impl AsyncRead for ServerStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            ServerStream::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            ServerStream::Tls(s) => Pin::new(s.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ServerStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            ServerStream::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            ServerStream::Tls(s) => Pin::new(s.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            ServerStream::Plain(s) => Pin::new(s).poll_flush(cx),
+            ServerStream::Tls(s) => Pin::new(s.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            ServerStream::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            ServerStream::Tls(s) => Pin::new(s.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_certificate_is_reused_on_restart() {
+        let dir = std::env::temp_dir().join(format!("rotascope-tls-{}", std::process::id()));
+        let config = TlsConfig {
+            cert_path: dir.join("cert.pem").to_string_lossy().into_owned(),
+            key_path: dir.join("key.pem").to_string_lossy().into_owned(),
+            ..TlsConfig::default()
+        };
+
+        let first = TlsIdentity::load_or_generate(&config).unwrap();
+        let second = TlsIdentity::load_or_generate(&config).unwrap();
+        assert_eq!(first.fingerprint().len(), 64);
+        assert_eq!(first.fingerprint(), second.fingerprint());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::CrossPlatformCapturer::CrossPlatformCapturer;
+use crate::desktop::DesktopCapture;
+use crate::pipeline::{self, PixelOrder};
+use image::RgbaImage;
+use rotascope_core::Result;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// 画面采集源；每次返回一帧完整的 RGBA 图像，没有新画面时阻塞等待
+///
+/// 采集源在捕获线程内创建和使用，因此不要求 `Send`（scrap 的 Capturer 不能跨线程）
+pub trait CaptureSource {
+    fn capture_frame(&mut self) -> Result<RgbaImage>;
+
+    /// 不阻塞的采集；还没有新画面时返回 None。默认退化为阻塞的 `capture_frame`
+    fn try_capture_frame(&mut self) -> Result<Option<RgbaImage>> {
+        self.capture_frame().map(Some)
+    }
+
+    /// 归还编码完的帧，采集源可以复用它的缓冲区；默认直接丢弃
+    fn recycle(&mut self, _frame: RgbaImage) {}
+
+    /// 返回的帧中像素的通道顺序；默认为 RGBA
+    fn pixel_order(&self) -> PixelOrder {
+        PixelOrder::Rgba
+    }
+}
+
+/// 视频帧编码器，输出直接作为 VideoFrame 的数据发送
+pub trait FrameEncoder: Send + Sync + fmt::Debug {
+    fn encode(&self, frame: &RgbaImage) -> Result<Vec<u8>>;
+
+    /// 编码按 BGRA 顺序存放的帧；默认转换为 RGBA 后调用 `encode`，能直接读取 BGRA 的编码器应覆盖
+    fn encode_bgra(&self, frame: &RgbaImage) -> Result<Vec<u8>> {
+        let mut rgba = RgbaImage::new(frame.width(), frame.height());
+        pipeline::bgra_to_rgba_parallel(frame.as_raw(), &mut rgba, frame.width() as usize);
+        self.encode(&rgba)
+    }
+}
+
+/// 在捕获线程内创建采集源；捕获出错重启时会再次调用
+#[derive(Clone)]
+pub struct CaptureFactory(Arc<dyn Fn() -> Result<Box<dyn CaptureSource>> + Send + Sync>);
+
+impl CaptureFactory {
+    pub fn new<F>(factory: F) -> Self
+    where
+        F: Fn() -> Result<Box<dyn CaptureSource>> + Send + Sync + 'static,
+    {
+        Self(Arc::new(factory))
+    }
+
+    /// 采集主显示器
+    pub fn primary_screen() -> Self {
+        Self::new(|| Ok(Box::new(CrossPlatformCapturer::new_primary()?)))
+    }
+
+    /// 采集所有物理显示器并按位置拼接成一张画布
+    pub fn all_screens() -> Self {
+        Self::new(|| Ok(Box::new(DesktopCapture::new_all()?)))
+    }
+
+    pub fn open(&self) -> Result<Box<dyn CaptureSource>> {
+        (self.0)()
+    }
+}
+
+impl fmt::Debug for CaptureFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CaptureFactory")
+    }
+}
+
+impl CaptureSource for CrossPlatformCapturer {
+    fn capture_frame(&mut self) -> Result<RgbaImage> {
+        CrossPlatformCapturer::capture_frame(self)
+    }
+
+    fn try_capture_frame(&mut self) -> Result<Option<RgbaImage>> {
+        CrossPlatformCapturer::try_capture_frame(self)
+    }
+
+    fn recycle(&mut self, frame: RgbaImage) {
+        CrossPlatformCapturer::recycle(self, frame.into_raw())
+    }
+
+    fn pixel_order(&self) -> PixelOrder {
+        PixelOrder::Bgra
+    }
+}
+
+/// JPEG 编码器，画面按条带在多个核心上并行编码，见 [`pipeline::encode_jpeg`]
+#[derive(Debug, Clone, Copy)]
+pub struct JpegEncoder {
+    pub quality: u8,
+    /// 条带数，0 表示每个 CPU 核心一个，1 表示单线程编码
+    pub stripes: usize,
+}
+
+impl Default for JpegEncoder {
+    fn default() -> Self {
+        Self {
+            quality: 70,
+            stripes: 0,
+        }
+    }
+}
+
+impl FrameEncoder for JpegEncoder {
+    fn encode(&self, frame: &RgbaImage) -> Result<Vec<u8>> {
+        pipeline::encode_jpeg(frame, PixelOrder::Rgba, self.quality, self.stripes)
+    }
+
+    fn encode_bgra(&self, frame: &RgbaImage) -> Result<Vec<u8>> {
+        pipeline::encode_jpeg(frame, PixelOrder::Bgra, self.quality, self.stripes)
+    }
+}
+
+/// 测试图案源，不依赖显示服务器；按给定帧率输出滚动的渐变
+#[derive(Debug)]
+pub struct TestPatternSource {
+    width: u32,
+    height: u32,
+    interval: Duration,
+    frame: u32,
+    next_at: Option<Instant>,
+}
+
+impl TestPatternSource {
+    pub fn new(width: u32, height: u32, fps: u32) -> Self {
+        Self {
+            width,
+            height,
+            interval: Duration::from_secs(1) / fps.max(1),
+            frame: 0,
+            next_at: None,
+        }
+    }
+}
+
+impl CaptureSource for TestPatternSource {
+    fn capture_frame(&mut self) -> Result<RgbaImage> {
+        let now = Instant::now();
+        let next_at = self.next_at.unwrap_or(now);
+        if let Some(wait) = next_at.checked_duration_since(now) {
+            std::thread::sleep(wait);
+        }
+        self.next_at = Some(next_at.max(now) + self.interval);
+
+        let offset = self.frame;
+        self.frame = self.frame.wrapping_add(1);
+        Ok(RgbaImage::from_fn(self.width, self.height, |x, y| {
+            image::Rgba([(x + offset) as u8, y as u8, offset as u8, 255])
+        }))
+    }
+}
@@ -1,64 +1,995 @@
+use crate::config::DisplayBackendKind;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::sync::{Arc, Mutex};
-use rotascope_core::Result;
+use rotascope_core::{Arrangement, DisplayLayout, Result, ServerMessage};
 
+/// 单个显示器的最大宽高
+pub const MAX_DISPLAY_DIMENSION: u32 = 8192;
+
+/// 虚拟显示器列表和当前显示器的唯一来源，运行时可以增删和调整大小
 #[derive(Debug,Clone)]
 pub struct VirtualDisplayManager {
-    pub displays: Arc<Mutex<Vec<VirtualDisplay>>>,
-    pub current_display: Arc<Mutex<usize>>,
+    state: Arc<Mutex<DisplayState>>,
+    backend: Arc<dyn DisplayBackend>,
+    /// 显示器的增删、调整和排列依次进行；后端调用期间不持有 state 的锁
+    changes: Arc<tokio::sync::Mutex<()>>,
+    /// 布局变化后保存到这里，启动时读取
+    layout_file: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+struct DisplayState {
+    displays: Vec<VirtualDisplay>,
+    current: usize,
+    /// 上一个观看的显示器，用于 SwitchDirection::Last
+    previous: Option<usize>,
+    next_id: u32,
+    arrangement: Arrangement,
+}
+
+/// 布局文件的内容，按显示器 id 对应
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedLayout {
+    arrangement: Arrangement,
+    displays: Vec<SavedDisplay>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedDisplay {
+    id: u32,
+    #[serde(flatten)]
+    layout: DisplayLayout,
 }
+
 #[derive(Debug,Clone)]
 pub struct VirtualDisplay {
     pub id: u32,
+    pub name: String,
     pub width: u32,
     pub height: u32,
+    /// 在虚拟桌面上的左上角位置
+    pub x: i32,
+    pub y: i32,
+    /// 偏航角（度）和显示缩放，见 [`DisplayLayout`]
+    pub rotation: f32,
+    pub scale: f32,
+    /// 由客户端通过 CreateDisplay 创建，客户端只能销毁这类显示器
+    pub client_created: bool,
+    /// 窗口显示器：画面来自该 X11 窗口，尺寸跟随窗口变化，不在后端创建显示输出
+    pub window: Option<u32>,
+    /// 旧的捕获路径 (capture.rs) 写入的画面，默认为空
     pub framebuffer: Vec<u8>,
 }
 
+impl VirtualDisplay {
+    fn new(id: u32, name: String, width: u32, height: u32) -> Self {
+        Self {
+            id,
+            name,
+            width,
+            height,
+            x: 0,
+            y: 0,
+            rotation: 0.0,
+            scale: 1.0,
+            client_created: false,
+            window: None,
+            framebuffer: Vec::new(),
+        }
+    }
+
+    pub fn layout(&self) -> DisplayLayout {
+        DisplayLayout {
+            name: self.name.clone(),
+            x: self.x,
+            y: self.y,
+            rotation: self.rotation,
+            scale: self.scale,
+        }
+    }
+
+    fn center(&self) -> (f64, f64) {
+        (
+            self.x as f64 + self.width as f64 / 2.0,
+            self.y as f64 + self.height as f64 / 2.0,
+        )
+    }
+}
+
+/// 在系统中真正创建和销毁虚拟显示器
+pub trait DisplayBackend: Send + Sync + std::fmt::Debug {
+    fn create(&self, display: &VirtualDisplay) -> Result<()>;
+    fn destroy(&self, display: &VirtualDisplay) -> Result<()>;
+}
+
+/// 只记录日志，不改动系统
+#[derive(Debug, Default)]
+pub struct SimulatedBackend;
+
+impl DisplayBackend for SimulatedBackend {
+    fn create(&self, display: &VirtualDisplay) -> Result<()> {
+        let id = display.id;
+        tracing::debug!("Created virtual display {}", id);
+        Ok(())
+    }
+
+    fn destroy(&self, display: &VirtualDisplay) -> Result<()> {
+        let id = display.id;
+        tracing::debug!("Destroyed virtual display {}", id);
+        Ok(())
+    }
+}
+
+/// 用 xrandr 的虚拟 monitor 把 X 屏幕划分成多个显示器；X 屏幕需要足够大（例如 dummy 驱动）
+#[derive(Debug, Default)]
+pub struct XrandrBackend;
+
+impl XrandrBackend {
+    fn monitor_name(display: &VirtualDisplay) -> String {
+        format!("ROTASCOPE-{}", display.id)
+    }
+
+    fn run(args: &[String]) -> Result<()> {
+        let output = Command::new("xrandr")
+            .args(args)
+            .output()
+            .map_err(|e| format!("Failed to run xrandr: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "xrandr {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl DisplayBackend for XrandrBackend {
+    fn create(&self, display: &VirtualDisplay) -> Result<()> {
+        // 物理尺寸按 96 DPI 估算
+        let mm = |px: u32| px as u64 * 254 / 960;
+        let geometry = format!(
+            "{}/{}x{}/{}+{}+{}",
+            display.width,
+            mm(display.width),
+            display.height,
+            mm(display.height),
+            display.x,
+            display.y
+        );
+        Self::run(&["--setmonitor".to_string(), Self::monitor_name(display), geometry, "none".to_string()])
+    }
+
+    fn destroy(&self, display: &VirtualDisplay) -> Result<()> {
+        Self::run(&["--delmonitor".to_string(), Self::monitor_name(display)])
+    }
+}
+
+/// 窗口显示器不对应真实的显示输出，不经过后端
+fn create(backend: &dyn DisplayBackend, display: &VirtualDisplay) -> Result<()> {
+    match display.window {
+        Some(_) => Ok(()),
+        None => backend.create(display),
+    }
+}
+
+fn destroy(backend: &dyn DisplayBackend, display: &VirtualDisplay) -> Result<()> {
+    match display.window {
+        Some(_) => Ok(()),
+        None => backend.destroy(display),
+    }
+}
+
+pub fn backend(kind: DisplayBackendKind) -> Arc<dyn DisplayBackend> {
+    match kind {
+        DisplayBackendKind::Simulated => Arc::new(SimulatedBackend),
+        DisplayBackendKind::Xrandr => Arc::new(XrandrBackend),
+    }
+}
+
 impl VirtualDisplayManager {
-    pub fn new(config: Vec<(u32, u32, u32)>) -> Result<Self> {
-        let displays = config
+    pub fn new(config: Vec<(u32, u32, u32)>, backend: Arc<dyn DisplayBackend>) -> Result<Self> {
+        if config.is_empty() {
+            return Err("At least one display is required".to_string());
+        }
+        if config.len() > u8::MAX as usize {
+            return Err(format!("At most {} displays are supported", u8::MAX));
+        }
+        for (_, w, h) in &config {
+            validate_size(*w, *h)?;
+        }
+        let next_id = config.iter().map(|(id, _, _)| id + 1).max().unwrap_or(0);
+        let mut displays: Vec<VirtualDisplay> = config
             .into_iter()
-            .map(|(id, w, h)| VirtualDisplay {
-                id,
-                width: w,
-                height: h,
-                framebuffer: vec![0; (w * h * 4) as usize],
-            })
+            .map(|(id, w, h)| VirtualDisplay::new(id, format!("Display {}", id), w, h))
             .collect();
+        apply_arrangement(Arrangement::Row, &mut displays);
 
+        let state = DisplayState {
+            displays,
+            current: 0,
+            previous: None,
+            next_id,
+            arrangement: Arrangement::Row,
+        };
         Ok(Self {
-            displays: Arc::new(Mutex::new(displays)),
-            current_display: Arc::new(Mutex::new(0)),
+            state: Arc::new(Mutex::new(state)),
+            backend,
+            changes: Arc::new(tokio::sync::Mutex::new(())),
+            layout_file: None,
         })
     }
 
+    /// 读取之前保存的布局（文件不存在时保持默认的一行排列），之后的布局变化都写回该文件
+    pub fn with_layout_file(mut self, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if path.exists() {
+            let text = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let saved: SavedLayout = serde_json::from_str(&text)
+                .map_err(|e| format!("Invalid display layout {}: {}", path.display(), e))?;
+            let mut state = self.state.lock().unwrap();
+            for entry in saved.displays {
+                if let Some(display) = state.displays.iter_mut().find(|d| d.id == entry.id) {
+                    validate_layout(&entry.layout)?;
+                    let DisplayLayout { name, x, y, rotation, scale } = entry.layout;
+                    display.name = name;
+                    display.x = x;
+                    display.y = y;
+                    display.rotation = rotation;
+                    display.scale = scale;
+                }
+            }
+            validate_arrangement(saved.arrangement)?;
+            state.arrangement = saved.arrangement;
+            apply_arrangement(state.arrangement, &mut state.displays);
+            tracing::info!("Loaded display layout from {}", path.display());
+        }
+        self.layout_file = Some(path);
+        Ok(self)
+    }
+
     pub async fn initialize(&self) -> Result<()> {
-        log::info!("Initializing {} virtual displays", self.displays.lock().unwrap().len());
-        println!("Initializing {} virtual displays", self.displays.lock().unwrap().len());
+        let _changes = self.changes.lock().await;
+        let displays = self.state.lock().unwrap().displays.clone();
+        tracing::info!("Initializing {} virtual displays", displays.len());
+        self.blocking(move |backend| displays.iter().try_for_each(|display| create(backend, display)))
+            .await?
+    }
+
+    pub async fn shutdown(&self) -> Result<()> {
+        let _changes = self.changes.lock().await;
+        let displays = self.state.lock().unwrap().displays.clone();
+        tracing::info!("Destroying {} virtual displays", displays.len());
+
+        // 尽量销毁所有显示器，返回第一个错误
+        self.blocking(move |backend| {
+            let mut result = Ok(());
+            for display in &displays {
+                if let Err(e) = destroy(backend, display) {
+                    let id = display.id;
+                    tracing::warn!("Failed to destroy virtual display {}: {}", id, e);
+                    result = result.and(Err(e));
+                }
+            }
+            result
+        })
+        .await?
+    }
+
+    pub fn resolutions(&self) -> Vec<(u32, u32)> {
+        self.state
+            .lock()
+            .unwrap()
+            .displays
+            .iter()
+            .map(|d| (d.width, d.height))
+            .collect()
+    }
+
+    pub fn get_display_count(&self) -> usize {
+        self.state.lock().unwrap().displays.len()
+    }
 
-        // 在实际实现中，这里会：
-        // 1. 调用操作系统API创建虚拟显示器
-        // 2. 或者使用第三方虚拟显示器驱动
-        // 3. 配置显示器的分辨率和位置
+    pub fn current_display(&self) -> u8 {
+        self.state.lock().unwrap().current as u8
+    }
 
-        // 目前是模拟实现
-        for i in 0..self.displays.lock().unwrap().len() {
-            log::info!("Created virtual display {}", i);
-            println!("Created virtual display {}", i);
+    /// 当前的显示器配置，发给客户端的 DisplayConfig 都从这里生成
+    pub fn display_config(&self) -> ServerMessage {
+        let state = self.state.lock().unwrap();
+        ServerMessage::DisplayConfig {
+            total_displays: state.displays.len(),
+            current_display: state.current as u8,
+            resolutions: state.displays.iter().map(|d| (d.width, d.height)).collect(),
+            layout: state.displays.iter().map(VirtualDisplay::layout).collect(),
+            arrangement: state.arrangement,
         }
+    }
+
+    /// 切换到指定显示器，返回是否发生了变化
+    pub fn set_current_display(&self, index: u8) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        state.check_index(index)?;
+        Ok(state.select(index as usize))
+    }
+
+    /// 相对当前显示器前后移动，首尾循环；返回新的显示器编号
+    pub fn switch_display(&self, delta: i32) -> u8 {
+        let mut state = self.state.lock().unwrap();
+        let n = state.displays.len();
+        let index = ((state.current as i32 + delta).rem_euclid(n as i32)) as usize;
+        state.select(index);
+        tracing::debug!("Switched display: {}", state.current);
+        state.current as u8
+    }
+
+    /// 按布局切换到 (dx, dy) 方向上最近的显示器；该方向没有显示器时返回 None
+    pub fn switch_towards(&self, dx: i32, dy: i32) -> Option<u8> {
+        let mut state = self.state.lock().unwrap();
+        let (cx, cy) = state.displays[state.current].center();
+        let (dx, dy) = (dx as f64, dy as f64);
+        let (index, _) = state
+            .displays
+            .iter()
+            .enumerate()
+            .filter_map(|(index, display)| {
+                let (x, y) = display.center();
+                let (ox, oy) = (x - cx, y - cy);
+                // 沿该方向的距离必须为正，偏离方向的距离加倍计入
+                let along = ox * dx + oy * dy;
+                let across = (ox * dy - oy * dx).abs();
+                (along > 0.0).then_some((index, along + 2.0 * across))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))?;
+        state.select(index);
+        Some(index as u8)
+    }
+
+    /// 切回上一个观看的显示器；还没有切换过时返回 None
+    pub fn switch_to_last(&self) -> Option<u8> {
+        let mut state = self.state.lock().unwrap();
+        let index = state.previous?;
+        state.select(index);
+        Some(index as u8)
+    }
 
+    /// 显示器的当前信息
+    pub fn display(&self, index: u8) -> Option<VirtualDisplay> {
+        self.state.lock().unwrap().displays.get(index as usize).cloned()
+    }
+
+    /// 在末尾创建一个显示器，返回其编号；name 为空时使用默认名称。
+    /// client_limit 不为空时视为客户端创建的显示器，总数不能超过该限制
+    pub async fn add_display(&self, name: &str, width: u32, height: u32, client_limit: Option<usize>) -> Result<u8> {
+        self.insert(name, width, height, client_limit.is_some(), None, client_limit)
+            .await
+    }
+
+    /// 为窗口创建显示器，视为客户端创建的显示器
+    pub async fn add_window(&self, name: &str, width: u32, height: u32, window: u32, max_displays: usize) -> Result<u8> {
+        self.insert(name, width, height, true, Some(window), Some(max_displays))
+            .await
+    }
+
+    async fn insert(
+        &self,
+        name: &str,
+        width: u32,
+        height: u32,
+        client_created: bool,
+        window: Option<u32>,
+        limit: Option<usize>,
+    ) -> Result<u8> {
+        validate_size(width, height)?;
+        let _changes = self.changes.lock().await;
+        let (before, after) = {
+            let state = self.state.lock().unwrap();
+            if state.displays.len() >= u8::MAX as usize {
+                return Err(format!("At most {} displays are supported", u8::MAX));
+            }
+            if let Some(limit) = limit
+                && state.displays.len() >= limit
+            {
+                return Err(format!("Display limit reached ({})", limit));
+            }
+            let id = state.next_id;
+            let name = match name.trim() {
+                "" => format!("Display {}", id),
+                name => name.to_string(),
+            };
+            let mut display = VirtualDisplay::new(id, name, width, height);
+            display.x = next_x(&state.displays);
+            display.client_created = client_created;
+            display.window = window;
+
+            let mut after = state.displays.clone();
+            after.push(display);
+            apply_arrangement(state.arrangement, &mut after);
+            (state.displays.clone(), after)
+        };
+
+        let added = after.last().unwrap().clone();
+        let id = added.id;
+        self.blocking(move |backend| create(backend, &added)).await??;
+        self.follow_moves(&before, &after).await;
+
+        let (index, layout) = {
+            let mut state = self.state.lock().unwrap();
+            state.displays = after;
+            state.next_id += 1;
+            (state.displays.len() - 1, state.saved_layout())
+        };
+        tracing::info!("Created virtual display {} ({}x{})", id, width, height);
+        self.save(layout).await;
+        Ok(index as u8)
+    }
+
+    /// 移除显示器，后面的显示器编号依次前移；当前显示器被移除时切到顶替其位置的下一个显示器，
+    /// 移除的是最后一个时切到前一个
+    pub async fn remove_display(&self, index: u8) -> Result<()> {
+        let _changes = self.changes.lock().await;
+        let (before, after, removed) = {
+            let state = self.state.lock().unwrap();
+            state.check_index(index)?;
+            if state.displays.len() == 1 {
+                return Err("Cannot remove the last display".to_string());
+            }
+            let mut after = state.displays.clone();
+            let removed = after.remove(index as usize);
+            apply_arrangement(state.arrangement, &mut after);
+            (state.displays.clone(), after, removed)
+        };
+
+        let id = removed.id;
+        self.blocking(move |backend| destroy(backend, &removed)).await??;
+        self.follow_moves(&before, &after).await;
+
+        let layout = {
+            let mut state = self.state.lock().unwrap();
+            state.displays = after;
+            state.removed(index as usize);
+            state.saved_layout()
+        };
+        tracing::info!("Destroyed virtual display {}", id);
+        self.save(layout).await;
         Ok(())
     }
 
-    pub fn get_display_count(&self) -> usize {
-        self.displays.lock().unwrap().len()
+    /// 在后端按新尺寸重新创建显示器；创建失败时恢复原来的显示器并返回错误
+    pub async fn resize_display(&self, index: u8, width: u32, height: u32) -> Result<()> {
+        validate_size(width, height)?;
+        let _changes = self.changes.lock().await;
+        let (before, original) = {
+            let state = self.state.lock().unwrap();
+            state.check_index(index)?;
+            (state.displays.clone(), state.displays[index as usize].clone())
+        };
+        if original.window.is_some() {
+            return Err(format!("Display {} follows a window and cannot be resized", index));
+        }
+        let resized = VirtualDisplay {
+            width,
+            height,
+            ..original.clone()
+        };
+
+        let created = resized.clone();
+        self.blocking(move |backend| {
+            destroy(backend, &original)?;
+            create(backend, &created).inspect_err(|_| {
+                if let Err(e) = create(backend, &original) {
+                    let id = original.id;
+                    tracing::warn!("Failed to restore virtual display {}: {}", id, e);
+                }
+            })
+        })
+        .await??;
+        tracing::info!("Resized virtual display {} to {}x{}", resized.id, width, height);
+
+        let mut after = before.clone();
+        after[index as usize] = resized;
+        let arrangement = self.state.lock().unwrap().arrangement;
+        apply_arrangement(arrangement, &mut after);
+        self.follow_moves(&before, &after).await;
+
+        let layout = {
+            let mut state = self.state.lock().unwrap();
+            state.displays = after;
+            state.saved_layout()
+        };
+        self.save(layout).await;
+        Ok(())
+    }
+
+    /// 窗口显示器的尺寸跟随窗口；尺寸变化时返回该显示器的编号
+    pub async fn follow_window_size(&self, id: u32, width: u32, height: u32) -> Option<u8> {
+        // 每一帧都会调用，尺寸没有变化时不等待其他的显示器变更
+        let resized = |state: &DisplayState| {
+            let index = state.displays.iter().position(|d| d.id == id && d.window.is_some())?;
+            let display = &state.displays[index];
+            ((display.width, display.height) != (width, height) && validate_size(width, height).is_ok()).then_some(index)
+        };
+        resized(&self.state.lock().unwrap())?;
+
+        let _changes = self.changes.lock().await;
+        let (index, before, after) = {
+            let state = self.state.lock().unwrap();
+            let index = resized(&state)?;
+            let mut after = state.displays.clone();
+            after[index].width = width;
+            after[index].height = height;
+            apply_arrangement(state.arrangement, &mut after);
+            (index, state.displays.clone(), after)
+        };
+        tracing::info!("Window display {} resized to {}x{}", id, width, height);
+        self.follow_moves(&before, &after).await;
+
+        let layout = {
+            let mut state = self.state.lock().unwrap();
+            state.displays = after;
+            state.saved_layout()
+        };
+        self.save(layout).await;
+        Some(index as u8)
     }
 
-    pub fn switch_display(&self, delta: i32) {
-        let mut curr = self.current_display.lock().unwrap();
-        let displays = self.displays.lock().unwrap();
-        let n = displays.len();
-        *curr = ((*curr as i32 + delta).rem_euclid(n as i32)) as usize;
-        println!("Switched display: {}", *curr);
+    /// 当前显示器的 id，不随其他显示器的增删变化
+    pub fn current_id(&self) -> u32 {
+        let state = self.state.lock().unwrap();
+        state.displays[state.current].id
+    }
+
+    /// 当前显示器是窗口显示器时返回 (显示器 id, 窗口)
+    pub fn current_window(&self) -> Option<(u32, u32)> {
+        let state = self.state.lock().unwrap();
+        let display = &state.displays[state.current];
+        display.window.map(|window| (display.id, window))
+    }
+
+    /// 显示器 id 对应的当前编号
+    pub fn index_of(&self, id: u32) -> Option<u8> {
+        let state = self.state.lock().unwrap();
+        state.displays.iter().position(|d| d.id == id).map(|index| index as u8)
+    }
+
+    /// 按预设方式重新排列所有显示器
+    pub async fn arrange(&self, arrangement: Arrangement) -> Result<()> {
+        validate_arrangement(arrangement)?;
+        let _changes = self.changes.lock().await;
+        let before = self.state.lock().unwrap().displays.clone();
+        let mut after = before.clone();
+        apply_arrangement(arrangement, &mut after);
+        self.follow_moves(&before, &after).await;
+
+        let layout = {
+            let mut state = self.state.lock().unwrap();
+            state.displays = after;
+            state.arrangement = arrangement;
+            state.saved_layout()
+        };
+        tracing::info!("Arranged displays as {:?}", arrangement);
+        self.save(layout).await;
+        Ok(())
+    }
+
+    /// 单独摆放一个显示器，整体排列方式变为 Custom
+    pub async fn set_layout(&self, index: u8, layout: DisplayLayout) -> Result<()> {
+        validate_layout(&layout)?;
+        let _changes = self.changes.lock().await;
+        let before = {
+            let state = self.state.lock().unwrap();
+            state.check_index(index)?;
+            state.displays.clone()
+        };
+        let mut after = before.clone();
+        let display = &mut after[index as usize];
+        display.name = layout.name;
+        display.x = layout.x;
+        display.y = layout.y;
+        display.rotation = layout.rotation;
+        display.scale = layout.scale;
+        self.follow_moves(&before, &after).await;
+
+        let layout = {
+            let mut state = self.state.lock().unwrap();
+            state.displays = after;
+            state.arrangement = Arrangement::Custom;
+            state.saved_layout()
+        };
+        self.save(layout).await;
+        Ok(())
+    }
+
+    /// 位置变化的显示器需要在后端重新创建
+    async fn follow_moves(&self, before: &[VirtualDisplay], after: &[VirtualDisplay]) {
+        let moved: Vec<VirtualDisplay> = after
+            .iter()
+            .filter(|display| {
+                before
+                    .iter()
+                    .any(|d| d.id == display.id && (d.x, d.y) != (display.x, display.y))
+            })
+            .cloned()
+            .collect();
+        if moved.is_empty() {
+            return;
+        }
+        let result = self
+            .blocking(move |backend| {
+                for display in &moved {
+                    if let Err(e) = destroy(backend, display).and_then(|_| create(backend, display)) {
+                        let id = display.id;
+                        tracing::warn!("Failed to move virtual display {}: {}", id, e);
+                    }
+                }
+            })
+            .await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to move virtual displays: {}", e);
+        }
+    }
+
+    /// 后端可能启动 xrandr 等外部进程，放到阻塞线程上执行，不占用运行时
+    async fn blocking<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&dyn DisplayBackend) -> T + Send + 'static,
+    ) -> Result<T> {
+        let backend = self.backend.clone();
+        tokio::task::spawn_blocking(move || call(backend.as_ref()))
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn save(&self, layout: SavedLayout) {
+        let Some(path) = self.layout_file.clone() else {
+            return;
+        };
+        let result = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || write_layout(&path, &layout)
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result);
+        if let Err(e) = result {
+            tracing::warn!("Failed to save display layout to {}: {}", path.display(), e);
+        }
+    }
+}
+
+impl DisplayState {
+    fn check_index(&self, index: u8) -> Result<()> {
+        if (index as usize) < self.displays.len() {
+            Ok(())
+        } else {
+            Err(format!("No display {} (have {})", index, self.displays.len()))
+        }
+    }
+
+    /// 切换当前显示器并记住之前的显示器，返回是否发生了变化
+    fn select(&mut self, index: usize) -> bool {
+        if self.current == index {
+            return false;
+        }
+        self.previous = Some(self.current);
+        self.current = index;
+        true
+    }
+
+    /// 索引为 index 的显示器已被移除，调整当前和上一个显示器的编号
+    fn removed(&mut self, index: usize) {
+        if self.current > index || self.current == self.displays.len() {
+            self.current -= 1;
+        }
+        let current = self.current;
+        self.previous = match self.previous {
+            Some(previous) if previous == index => None,
+            Some(previous) if previous > index => Some(previous - 1),
+            previous => previous,
+        }
+        .filter(|&previous| previous != current);
+    }
+
+    fn saved_layout(&self) -> SavedLayout {
+        SavedLayout {
+            arrangement: self.arrangement,
+            displays: self
+                .displays
+                .iter()
+                .map(|d| SavedDisplay {
+                    id: d.id,
+                    layout: d.layout(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// 按排列方式重新计算位置和朝向；Custom 时保持不变
+fn apply_arrangement(arrangement: Arrangement, displays: &mut [VirtualDisplay]) {
+    match arrangement {
+        Arrangement::Row | Arrangement::Cylinder { .. } => {
+            let mut x = 0i32;
+            for display in displays.iter_mut() {
+                display.x = x;
+                display.y = 0;
+                display.rotation = 0.0;
+                x = x.saturating_add(display.width as i32);
+            }
+        }
+        Arrangement::Grid { columns } => {
+            let columns = columns.max(1) as usize;
+            let cell_width = displays.iter().map(|d| d.width).max().unwrap_or(0) as i32;
+            let cell_height = displays.iter().map(|d| d.height).max().unwrap_or(0) as i32;
+            for (i, display) in displays.iter_mut().enumerate() {
+                display.x = (i % columns) as i32 * cell_width;
+                display.y = (i / columns) as i32 * cell_height;
+                display.rotation = 0.0;
+            }
+        }
+        Arrangement::Custom => {}
+    }
+    // 弧形排列：中间的显示器正对用户，两侧依次转向
+    if let Arrangement::Cylinder { spacing_degrees } = arrangement {
+        let middle = (displays.len() as f32 - 1.0) / 2.0;
+        for (i, display) in displays.iter_mut().enumerate() {
+            display.rotation = (i as f32 - middle) * spacing_degrees;
+        }
+    }
+}
+
+fn write_layout(path: &Path, layout: &SavedLayout) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let text = serde_json::to_string_pretty(layout).map_err(|e| e.to_string())?;
+    std::fs::write(path, text).map_err(|e| e.to_string())
+}
+
+fn validate_arrangement(arrangement: Arrangement) -> Result<()> {
+    match arrangement {
+        Arrangement::Grid { columns: 0 } => Err("A grid needs at least one column".to_string()),
+        Arrangement::Cylinder { spacing_degrees } if !spacing_degrees.is_finite() || spacing_degrees.abs() > 180.0 => {
+            Err(format!("Invalid cylinder spacing {}", spacing_degrees))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_layout(layout: &DisplayLayout) -> Result<()> {
+    if !layout.rotation.is_finite() || !layout.scale.is_finite() || layout.scale <= 0.0 {
+        return Err(format!(
+            "Invalid layout for {}: rotation {}, scale {}",
+            layout.name, layout.rotation, layout.scale
+        ));
+    }
+    Ok(())
+}
+
+/// 新显示器排在已有显示器的右侧
+fn next_x(displays: &[VirtualDisplay]) -> i32 {
+    displays
+        .iter()
+        .map(|d| d.x.saturating_add(d.width as i32))
+        .max()
+        .unwrap_or(0)
+}
+
+fn validate_size(width: u32, height: u32) -> Result<()> {
+    if width == 0 || height == 0 || width > MAX_DISPLAY_DIMENSION || height > MAX_DISPLAY_DIMENSION {
+        return Err(format!(
+            "Invalid display size {}x{}, must be between 1 and {}",
+            width, height, MAX_DISPLAY_DIMENSION
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn removing_displays_keeps_the_current_one_valid() {
+        let displays = vec![(0, 64, 48), (1, 64, 48), (2, 64, 48)];
+        let manager = VirtualDisplayManager::new(displays, Arc::new(SimulatedBackend)).unwrap();
+        manager.set_current_display(2).unwrap();
+        manager.remove_display(0).await.unwrap();
+        assert_eq!(manager.current_display(), 1);
+        manager.remove_display(1).await.unwrap();
+        assert_eq!(manager.current_display(), 0);
+        assert!(manager.remove_display(0).await.is_err());
+        assert!(manager.resize_display(1, 64, 48).await.is_err());
+        assert!(manager.add_display("", 0, 48, None).await.is_err());
+
+        assert_eq!(manager.add_display("", 32, 24, Some(8)).await.unwrap(), 1);
+        assert_eq!(manager.resolutions(), vec![(64, 48), (32, 24)]);
+        let added = manager.display(1).unwrap();
+        assert_eq!((added.id, added.name.as_str(), added.x), (3, "Display 3", 64));
+        assert!(added.client_created);
+
+        // 当前显示器被移除后切到顶替其位置的下一个显示器
+        manager.set_current_display(0).unwrap();
+        manager.remove_display(0).await.unwrap();
+        assert_eq!(manager.current_id(), 3);
+    }
+
+    #[tokio::test]
+    async fn arrangements_position_displays() {
+        let displays = vec![(0, 64, 48), (1, 32, 24), (2, 64, 48)];
+        let manager = VirtualDisplayManager::new(displays, Arc::new(SimulatedBackend)).unwrap();
+        let positions = |m: &VirtualDisplayManager| -> Vec<(i32, i32, f32)> {
+            (0..3).map(|i| m.display(i).unwrap()).map(|d| (d.x, d.y, d.rotation)).collect()
+        };
+        assert_eq!(positions(&manager), vec![(0, 0, 0.0), (64, 0, 0.0), (96, 0, 0.0)]);
+
+        manager.arrange(Arrangement::Grid { columns: 2 }).await.unwrap();
+        assert_eq!(positions(&manager), vec![(0, 0, 0.0), (64, 0, 0.0), (0, 48, 0.0)]);
+
+        manager.arrange(Arrangement::Cylinder { spacing_degrees: 30.0 }).await.unwrap();
+        assert_eq!(positions(&manager), vec![(0, 0, -30.0), (64, 0, 0.0), (96, 0, 30.0)]);
+
+        assert!(manager.arrange(Arrangement::Grid { columns: 0 }).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn directional_switching_follows_the_layout() {
+        let displays = vec![(0, 64, 48), (1, 64, 48), (2, 64, 48), (3, 64, 48)];
+        let manager = VirtualDisplayManager::new(displays, Arc::new(SimulatedBackend)).unwrap();
+        manager.arrange(Arrangement::Grid { columns: 2 }).await.unwrap();
+
+        assert_eq!(manager.switch_towards(0, 1), Some(2));
+        assert_eq!(manager.switch_towards(1, 0), Some(3));
+        assert_eq!(manager.switch_towards(1, 0), None);
+        assert_eq!(manager.switch_towards(0, -1), Some(1));
+        assert_eq!(manager.current_display(), 1);
+    }
+
+    #[tokio::test]
+    async fn window_displays_follow_the_window_size() {
+        let manager = VirtualDisplayManager::new(vec![(0, 64, 48)], Arc::new(SimulatedBackend)).unwrap();
+        assert_eq!(manager.current_window(), None);
+        let index = manager.add_window("Terminal", 32, 24, 0x400001, 8).await.unwrap();
+        manager.set_current_display(index).unwrap();
+        assert_eq!(manager.current_window(), Some((1, 0x400001)));
+
+        assert!(manager.resize_display(index, 64, 48).await.is_err());
+        assert_eq!(manager.follow_window_size(1, 32, 24).await, None);
+        assert_eq!(manager.follow_window_size(1, 40, 30).await, Some(1));
+        assert_eq!(manager.resolutions(), vec![(64, 48), (40, 30)]);
+        assert_eq!(manager.follow_window_size(0, 40, 30).await, None);
+        assert_eq!(manager.index_of(1), Some(1));
+    }
+
+    #[tokio::test]
+    async fn last_toggles_between_the_two_most_recent_displays() {
+        let displays = vec![(0, 64, 48), (1, 64, 48), (2, 64, 48)];
+        let manager = VirtualDisplayManager::new(displays, Arc::new(SimulatedBackend)).unwrap();
+        assert_eq!(manager.switch_to_last(), None);
+
+        manager.set_current_display(2).unwrap();
+        assert_eq!(manager.switch_to_last(), Some(0));
+        assert_eq!(manager.switch_to_last(), Some(2));
+        // 切到同一个显示器不影响记录
+        assert!(!manager.set_current_display(2).unwrap());
+        assert_eq!(manager.switch_to_last(), Some(0));
+
+        // 上一个显示器被移除后不能再切回
+        manager.remove_display(2).await.unwrap();
+        assert_eq!(manager.switch_to_last(), None);
+    }
+
+    /// 记录创建和销毁过的显示器；销毁 id 为 fail 的显示器、创建宽度为 fail_width 的显示器时失败
+    #[derive(Debug, Default)]
+    struct RecordingBackend {
+        created: Mutex<Vec<(u32, u32)>>,
+        destroyed: Mutex<Vec<u32>>,
+        fail: Option<u32>,
+        fail_width: Option<u32>,
+    }
+
+    impl DisplayBackend for RecordingBackend {
+        fn create(&self, display: &VirtualDisplay) -> Result<()> {
+            self.created.lock().unwrap().push((display.id, display.width));
+            if self.fail_width == Some(display.width) {
+                return Err(format!("cannot create {}x{}", display.width, display.height));
+            }
+            Ok(())
+        }
+
+        fn destroy(&self, display: &VirtualDisplay) -> Result<()> {
+            self.destroyed.lock().unwrap().push(display.id);
+            if self.fail == Some(display.id) {
+                return Err(format!("cannot destroy {}", display.id));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_changes_leave_the_displays_intact() {
+        let backend = Arc::new(RecordingBackend {
+            fail_width: Some(128),
+            ..RecordingBackend::default()
+        });
+        let manager = VirtualDisplayManager::new(vec![(0, 64, 48), (1, 64, 48)], backend.clone()).unwrap();
+
+        // 新尺寸创建失败时按原尺寸重新创建
+        assert!(manager.resize_display(0, 128, 96).await.is_err());
+        assert_eq!(manager.resolutions(), vec![(64, 48), (64, 48)]);
+        assert_eq!(*backend.destroyed.lock().unwrap(), vec![0]);
+        assert_eq!(*backend.created.lock().unwrap(), vec![(0, 128), (0, 64)]);
+
+        assert!(manager.add_display("", 128, 96, None).await.is_err());
+        assert_eq!(manager.get_display_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn client_displays_stay_within_the_limit() {
+        let manager = VirtualDisplayManager::new(vec![(0, 64, 48)], Arc::new(SimulatedBackend)).unwrap();
+        // 并发的请求也不能超过限制
+        let results = tokio::join!(
+            manager.add_display("", 32, 24, Some(3)),
+            manager.add_display("", 32, 24, Some(3)),
+            manager.add_window("", 32, 24, 0x400001, 3),
+        );
+        let added = [results.0, results.1, results.2].iter().filter(|r| r.is_ok()).count();
+        assert_eq!(added, 2);
+        assert_eq!(
+            manager.add_display("", 32, 24, Some(3)).await,
+            Err("Display limit reached (3)".to_string())
+        );
+        // 服务端自己添加的显示器不受限制
+        assert_eq!(manager.add_display("", 32, 24, None).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn shutdown_destroys_every_display() {
+        let displays = vec![(0, 64, 48), (1, 64, 48), (2, 64, 48)];
+        let backend = Arc::new(RecordingBackend::default());
+        let manager = VirtualDisplayManager::new(displays.clone(), backend.clone()).unwrap();
+        manager.shutdown().await.unwrap();
+        assert_eq!(*backend.destroyed.lock().unwrap(), vec![0, 1, 2]);
+
+        // 某个显示器销毁失败时仍然销毁其余的，并返回该错误
+        let backend = Arc::new(RecordingBackend { fail: Some(1), ..RecordingBackend::default() });
+        let manager = VirtualDisplayManager::new(displays, backend.clone()).unwrap();
+        assert_eq!(manager.shutdown().await, Err("cannot destroy 1".to_string()));
+        assert_eq!(*backend.destroyed.lock().unwrap(), vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn layout_is_saved_and_restored() {
+        let path = std::env::temp_dir().join(format!("rotascope-layout-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let displays = vec![(0, 64, 48), (1, 64, 48)];
+
+        let manager = VirtualDisplayManager::new(displays.clone(), Arc::new(SimulatedBackend))
+            .unwrap()
+            .with_layout_file(&path)
+            .unwrap();
+        let layout = DisplayLayout {
+            name: "left".to_string(),
+            x: -64,
+            y: 10,
+            rotation: -45.0,
+            scale: 0.5,
+        };
+        manager.set_layout(1, layout.clone()).await.unwrap();
+
+        let restored = VirtualDisplayManager::new(displays, Arc::new(SimulatedBackend))
+            .unwrap()
+            .with_layout_file(&path)
+            .unwrap();
+        let ServerMessage::DisplayConfig { layout: restored_layout, arrangement, .. } = restored.display_config() else {
+            panic!("expected DisplayConfig");
+        };
+        assert_eq!(arrangement, Arrangement::Custom);
+        assert_eq!(restored_layout[1], layout);
+        assert_eq!(restored_layout[0].name, "Display 0");
+        let _ = std::fs::remove_file(path);
     }
 }
@@ -0,0 +1,333 @@
+//! 窗口级捕获：把单个顶层窗口作为一个伪显示器推流。
+
+use crate::pipeline;
+use image::RgbaImage;
+use rotascope_core::{Result, WindowInfo, WindowSelector};
+use std::fmt;
+use std::sync::Arc;
+
+/// 窗口采集一次的结果
+#[derive(Debug)]
+pub enum WindowFrame {
+    /// 窗口当前的内容，尺寸随窗口变化
+    Frame(RgbaImage),
+    /// 窗口被隐藏或最小化，重新显示后继续采集
+    Unmapped,
+    /// 窗口已销毁
+    Closed,
+}
+
+/// 单个窗口的采集源；与 [`crate::video::CaptureSource`] 一样在捕获线程内创建和使用
+pub trait WindowCapture {
+    fn capture(&mut self) -> Result<WindowFrame>;
+}
+
+/// 枚举顶层窗口并打开窗口采集源
+pub trait WindowSystem: Send + Sync + fmt::Debug {
+    fn list_windows(&self) -> Result<Vec<WindowInfo>>;
+    fn open(&self, window: u32) -> Result<Box<dyn WindowCapture>>;
+}
+
+/// 当前平台的窗口系统，窗口采集按 fps 限速
+pub fn default_system(fps: u32) -> Arc<dyn WindowSystem> {
+    Arc::new(platform::X11Windows::new(fps))
+}
+
+/// 按 [`WindowSelector`] 查找窗口
+pub fn find_window<'a>(windows: &'a [WindowInfo], selector: &WindowSelector) -> Result<&'a WindowInfo> {
+    match selector {
+        WindowSelector::Id(id) => windows
+            .iter()
+            .find(|w| w.id == *id)
+            .ok_or_else(|| format!("No window with id {}", id)),
+        WindowSelector::Title(title) => {
+            let title = title.to_lowercase();
+            if let Some(window) = windows.iter().find(|w| w.title.to_lowercase() == title) {
+                return Ok(window);
+            }
+            let matches: Vec<_> = windows
+                .iter()
+                .filter(|w| w.title.to_lowercase().contains(&title))
+                .collect();
+            match matches.as_slice() {
+                [window] => Ok(window),
+                [] => Err(format!("No window titled '{}'", title)),
+                _ => Err(format!(
+                    "{} windows match '{}': {}",
+                    matches.len(),
+                    title,
+                    matches.iter().map(|w| w.title.as_str()).collect::<Vec<_>>().join(", ")
+                )),
+            }
+        }
+    }
+}
+
+/// X11 ZPixmap（32 位，低字节序）的 BGRX 像素转换为 RGBA
+pub fn bgrx_to_rgba(data: &[u8], width: u32, height: u32) -> Result<RgbaImage> {
+    let expected = width as usize * height as usize * 4;
+    if data.len() < expected {
+        return Err(format!(
+            "Window image has {} bytes, expected {} for {}x{}",
+            data.len(),
+            expected,
+            width,
+            height
+        ));
+    }
+    let mut rgba = vec![0; expected];
+    pipeline::bgra_to_rgba(&data[..expected], &mut rgba);
+    RgbaImage::from_raw(width, height, rgba).ok_or_else(|| "Invalid window image".to_string())
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{WindowCapture, WindowFrame, WindowSystem, bgrx_to_rgba};
+    use rotascope_core::{Result, WindowInfo};
+    use std::time::{Duration, Instant};
+    use x11rb::connection::Connection;
+    use x11rb::errors::ReplyError;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _, ImageFormat, ImageOrder, MapState, Window};
+    use x11rb::rust_connection::RustConnection;
+
+    x11rb::atom_manager! {
+        Atoms: AtomsCookie {
+            _NET_CLIENT_LIST,
+            _NET_WM_NAME,
+            UTF8_STRING,
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct X11Windows {
+        interval: Duration,
+    }
+
+    impl X11Windows {
+        pub fn new(fps: u32) -> Self {
+            Self {
+                interval: Duration::from_secs(1) / fps.max(1),
+            }
+        }
+    }
+
+    impl WindowSystem for X11Windows {
+        fn list_windows(&self) -> Result<Vec<WindowInfo>> {
+            let (conn, screen_num) = x11rb::connect(None).map_err(|e| e.to_string())?;
+            let root = conn.setup().roots[screen_num].root;
+            let atoms = Atoms::new(&conn).map_err(|e| e.to_string())?.reply().map_err(|e| e.to_string())?;
+
+            // 优先使用窗口管理器维护的列表，没有窗口管理器时退回到根窗口的子窗口
+            let managed: Vec<Window> = conn
+                .get_property(false, root, atoms._NET_CLIENT_LIST, AtomEnum::WINDOW, 0, u32::MAX)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| e.to_string())?
+                .value32()
+                .map(|ids| ids.collect())
+                .unwrap_or_default();
+            let candidates = if managed.is_empty() {
+                conn.query_tree(root)
+                    .map_err(|e| e.to_string())?
+                    .reply()
+                    .map_err(|e| e.to_string())?
+                    .children
+            } else {
+                managed
+            };
+
+            let mut windows = Vec::new();
+            for window in candidates {
+                // 列举期间窗口可能已经关闭，跳过出错的窗口
+                let Ok(attributes) = conn.get_window_attributes(window).map_err(|e| e.to_string())?.reply() else {
+                    continue;
+                };
+                if attributes.map_state != MapState::VIEWABLE || attributes.override_redirect {
+                    continue;
+                }
+                let Ok(geometry) = conn.get_geometry(window).map_err(|e| e.to_string())?.reply() else {
+                    continue;
+                };
+                let title = window_title(&conn, &atoms, window)?;
+                if title.is_empty() {
+                    continue;
+                }
+                windows.push(WindowInfo {
+                    id: window,
+                    title,
+                    width: geometry.width as u32,
+                    height: geometry.height as u32,
+                });
+            }
+            Ok(windows)
+        }
+
+        fn open(&self, window: u32) -> Result<Box<dyn WindowCapture>> {
+            let (conn, screen_num) = x11rb::connect(None).map_err(|e| e.to_string())?;
+            let setup = conn.setup();
+            let root = setup.roots[screen_num].root;
+            let depth = conn
+                .get_geometry(window)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| format!("Window {:#x} is not available: {}", window, e))?
+                .depth;
+            let bits_per_pixel = setup
+                .pixmap_formats
+                .iter()
+                .find(|format| format.depth == depth)
+                .map(|format| format.bits_per_pixel);
+            if bits_per_pixel != Some(32) || setup.image_byte_order != ImageOrder::LSB_FIRST {
+                return Err(format!("Unsupported pixel format for window {:#x} (depth {})", window, depth));
+            }
+            Ok(Box::new(X11WindowCapture {
+                conn,
+                root,
+                window,
+                interval: self.interval,
+                next_at: None,
+            }))
+        }
+    }
+
+    fn window_title(conn: &RustConnection, atoms: &Atoms, window: Window) -> Result<String> {
+        for (property, kind) in [
+            (atoms._NET_WM_NAME, atoms.UTF8_STRING),
+            (AtomEnum::WM_NAME.into(), AtomEnum::STRING.into()),
+        ] {
+            let Ok(reply) = conn
+                .get_property(false, window, property, kind, 0, 1024)
+                .map_err(|e| e.to_string())?
+                .reply()
+            else {
+                continue;
+            };
+            if !reply.value.is_empty() {
+                return Ok(String::from_utf8_lossy(&reply.value).into_owned());
+            }
+        }
+        Ok(String::new())
+    }
+
+    struct X11WindowCapture {
+        conn: RustConnection,
+        root: Window,
+        window: Window,
+        interval: Duration,
+        next_at: Option<Instant>,
+    }
+
+    impl X11WindowCapture {
+        fn capture_visible(&self) -> std::result::Result<WindowFrame, ReplyError> {
+            let attributes = self.conn.get_window_attributes(self.window)?.reply()?;
+            if attributes.map_state != MapState::VIEWABLE {
+                return Ok(WindowFrame::Unmapped);
+            }
+            // 窗口超出屏幕的部分无法读取，只采集屏幕内的部分
+            let geometry = self.conn.get_geometry(self.window)?.reply()?;
+            let screen = self.conn.get_geometry(self.root)?.reply()?;
+            let origin = self
+                .conn
+                .translate_coordinates(self.window, self.root, 0, 0)?
+                .reply()?;
+            let (x, y) = (origin.dst_x as i32, origin.dst_y as i32);
+            let left = (-x).max(0);
+            let top = (-y).max(0);
+            let right = (geometry.width as i32).min(screen.width as i32 - x);
+            let bottom = (geometry.height as i32).min(screen.height as i32 - y);
+            if right <= left || bottom <= top {
+                return Ok(WindowFrame::Unmapped);
+            }
+            let (width, height) = ((right - left) as u16, (bottom - top) as u16);
+            let image = self
+                .conn
+                .get_image(ImageFormat::Z_PIXMAP, self.window, left as i16, top as i16, width, height, !0)?
+                .reply()?;
+            match bgrx_to_rgba(&image.data, width as u32, height as u32) {
+                Ok(frame) => Ok(WindowFrame::Frame(frame)),
+                Err(e) => {
+                    tracing::warn!("{}", e);
+                    Ok(WindowFrame::Unmapped)
+                }
+            }
+        }
+    }
+
+    impl WindowCapture for X11WindowCapture {
+        fn capture(&mut self) -> Result<WindowFrame> {
+            // X11 没有新画面通知，按固定帧率读取
+            let now = Instant::now();
+            let next_at = self.next_at.unwrap_or(now);
+            if let Some(wait) = next_at.checked_duration_since(now) {
+                std::thread::sleep(wait);
+            }
+            self.next_at = Some(next_at.max(now) + self.interval);
+
+            match self.capture_visible() {
+                Ok(frame) => Ok(frame),
+                // 窗口销毁后请求会返回 BadWindow / BadDrawable
+                Err(ReplyError::X11Error(_)) => Ok(WindowFrame::Closed),
+                Err(e) => Err(e.to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::{WindowCapture, WindowSystem};
+    use rotascope_core::{Result, WindowInfo};
+
+    #[derive(Debug)]
+    pub struct X11Windows;
+
+    impl X11Windows {
+        pub fn new(_fps: u32) -> Self {
+            Self
+        }
+    }
+
+    impl WindowSystem for X11Windows {
+        fn list_windows(&self) -> Result<Vec<WindowInfo>> {
+            Err("Window capture is only supported on Linux (X11)".to_string())
+        }
+
+        fn open(&self, _window: u32) -> Result<Box<dyn WindowCapture>> {
+            Err("Window capture is only supported on Linux (X11)".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u32, title: &str) -> WindowInfo {
+        WindowInfo {
+            id,
+            title: title.to_string(),
+            width: 640,
+            height: 480,
+        }
+    }
+
+    #[test]
+    fn windows_are_found_by_id_or_title() {
+        let windows = [window(1, "Terminal"), window(2, "Terminal - htop"), window(3, "Code")];
+        let find = |selector| find_window(&windows, &selector).map(|w| w.id);
+
+        assert_eq!(find(WindowSelector::Id(3)), Ok(3));
+        assert!(find(WindowSelector::Id(9)).is_err());
+        // 完全相同的标题优先于部分匹配
+        assert_eq!(find(WindowSelector::Title("terminal".to_string())), Ok(1));
+        assert_eq!(find(WindowSelector::Title("HTOP".to_string())), Ok(2));
+        assert!(find(WindowSelector::Title("term".to_string())).unwrap_err().contains("2 windows"));
+    }
+
+    #[test]
+    fn converts_bgrx_pixels() {
+        let image = bgrx_to_rgba(&[1, 2, 3, 0, 4, 5, 6, 0], 2, 1).unwrap();
+        assert_eq!(image.into_raw(), vec![3, 2, 1, 255, 6, 5, 4, 255]);
+        assert!(bgrx_to_rgba(&[0; 4], 2, 1).is_err());
+    }
+}
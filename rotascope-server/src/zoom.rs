@@ -0,0 +1,254 @@
+//! 区域放大：从全分辨率画面中裁剪客户端请求的区域并放大到原尺寸，放大期间用头部转动平移。
+
+use crate::config::ZoomConfig;
+use image::RgbaImage;
+use image::imageops::FilterType;
+use rotascope_core::{Result, Zoom};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// 画面中的矩形区域（像素）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ZoomState {
+    zoom: Zoom,
+    /// 开始平移时的头部朝向 (偏航, 俯仰)，之后的转动相对它计算
+    reference: Option<(f32, f32)>,
+    /// 相对放大区域尺寸的平移量，1.0 为一个区域的宽（高）
+    pan: (f32, f32),
+}
+
+/// 各显示器的放大设置，按显示器 id 保存；捕获线程和会话任务共享
+#[derive(Debug)]
+pub struct ZoomControl {
+    config: ZoomConfig,
+    displays: Mutex<HashMap<u32, ZoomState>>,
+    /// 各显示器最近一帧进入放大前的尺寸；视口裁剪后可能小于显示器本身
+    frames: Mutex<HashMap<u32, (u32, u32)>>,
+}
+
+impl ZoomControl {
+    pub fn new(config: &ZoomConfig) -> Self {
+        Self {
+            config: config.clone(),
+            displays: Mutex::new(HashMap::new()),
+            frames: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 记录显示器最近一帧的尺寸，之后的放大请求按它校验
+    pub fn record_frame(&self, display: u32, width: u32, height: u32) {
+        self.frames.lock().unwrap().insert(display, (width, height));
+    }
+
+    /// 显示器最近一帧的尺寸；还没有捕获过时返回 None
+    pub fn frame_size(&self, display: u32) -> Option<(u32, u32)> {
+        self.frames.lock().unwrap().get(&display).copied()
+    }
+
+    /// 设置显示器的放大区域，`Zoom::Off` 取消放大；frame_width/frame_height 为该显示器实际编码的画面尺寸，
+    /// 区域超出画面的部分在放大时被推回画面内
+    pub fn set(&self, display: u32, zoom: Zoom, frame_width: u32, frame_height: u32) -> Result<()> {
+        if !self.config.enabled {
+            return Err("Zoom is disabled on this server".to_string());
+        }
+        match zoom {
+            Zoom::Off => {
+                self.displays.lock().unwrap().remove(&display);
+                return Ok(());
+            }
+            Zoom::Rect { width, height, .. } if width == 0 || height == 0 => {
+                return Err("Zoom rectangle must not be empty".to_string());
+            }
+            Zoom::Rect { x, y, .. } | Zoom::Around { x, y, .. } if x >= frame_width || y >= frame_height => {
+                return Err(format!(
+                    "Zoom position ({}, {}) is outside the {}x{} frame",
+                    x, y, frame_width, frame_height
+                ));
+            }
+            // 矩形按画面比例扩展后的放大倍数同样受 max_factor 限制
+            Zoom::Rect { width, height, .. }
+                if (frame_width as f32 / width as f32).min(frame_height as f32 / height as f32)
+                    > self.config.max_factor =>
+            {
+                return Err(format!(
+                    "Zoom rectangle {}x{} exceeds the maximum zoom factor of {}",
+                    width, height, self.config.max_factor
+                ));
+            }
+            Zoom::Around { factor, .. } if !(1.0..=self.config.max_factor).contains(&factor) => {
+                return Err(format!(
+                    "Zoom factor must be between 1 and {}",
+                    self.config.max_factor
+                ));
+            }
+            _ => {}
+        }
+        let state = ZoomState {
+            zoom,
+            reference: None,
+            pan: (0.0, 0.0),
+        };
+        self.displays.lock().unwrap().insert(display, state);
+        Ok(())
+    }
+
+    /// 放大期间按头部朝向平移画面；未放大或未开启平移时返回 false，由调用方按原方式处理
+    pub fn pan(&self, display: u32, yaw: f32, pitch: f32) -> bool {
+        if !self.config.head_panning {
+            return false;
+        }
+        let mut displays = self.displays.lock().unwrap();
+        let Some(state) = displays.get_mut(&display) else {
+            return false;
+        };
+        // 第一次收到朝向时作为参考，之后转头多少就平移多少；抬头时画面向上移动
+        let (yaw0, pitch0) = *state.reference.get_or_insert((yaw, pitch));
+        state.pan = (
+            (yaw - yaw0) / self.config.pan_degrees,
+            (pitch0 - pitch) / self.config.pan_degrees,
+        );
+        true
+    }
+
+    /// 显示器是否处于放大状态
+    pub fn is_zoomed(&self, display: u32) -> bool {
+        self.displays.lock().unwrap().contains_key(&display)
+    }
+
+    /// 显示器当前放大的区域；未放大时返回 None
+    pub fn region(&self, display: u32, width: u32, height: u32) -> Option<Region> {
+        let state = *self.displays.lock().unwrap().get(&display)?;
+        Some(zoom_region(&state, width, height))
+    }
+
+    /// 裁剪放大区域并缩放回原画面尺寸；未放大时原样返回
+    pub fn apply(&self, display: u32, frame: RgbaImage) -> RgbaImage {
+        let (width, height) = frame.dimensions();
+        match self.region(display, width, height) {
+            Some(region) if (region.width, region.height) != (width, height) => {
+                let crop = image::imageops::crop_imm(&frame, region.x, region.y, region.width, region.height);
+                image::imageops::resize(&crop.to_image(), width, height, FilterType::Triangle)
+            }
+            _ => frame,
+        }
+    }
+}
+
+/// 计算放大区域：保持画面宽高比，加上平移量后限制在画面内
+fn zoom_region(state: &ZoomState, width: u32, height: u32) -> Region {
+    let (frame_w, frame_h) = (width as f32, height as f32);
+    let (center_x, center_y, mut w, mut h) = match state.zoom {
+        Zoom::Off => (frame_w / 2.0, frame_h / 2.0, frame_w, frame_h),
+        Zoom::Rect {
+            x,
+            y,
+            width: rect_w,
+            height: rect_h,
+        } => {
+            let (rect_w, rect_h) = (rect_w as f32, rect_h as f32);
+            // 宽高比与画面不同时向两侧扩展，保证放大后不变形
+            let aspect = frame_w / frame_h;
+            let (w, h) = if rect_w / rect_h < aspect {
+                (rect_h * aspect, rect_h)
+            } else {
+                (rect_w, rect_w / aspect)
+            };
+            (x as f32 + rect_w / 2.0, y as f32 + rect_h / 2.0, w, h)
+        }
+        Zoom::Around { x, y, factor } => (x as f32, y as f32, frame_w / factor, frame_h / factor),
+    };
+    if w > frame_w || h > frame_h {
+        let shrink = (frame_w / w).min(frame_h / h);
+        w *= shrink;
+        h *= shrink;
+    }
+    let center_x = center_x + state.pan.0 * w;
+    let center_y = center_y + state.pan.1 * h;
+
+    let w = (w.round() as u32).clamp(1, width);
+    let h = (h.round() as u32).clamp(1, height);
+    let x = (center_x - w as f32 / 2.0).round().clamp(0.0, (width - w) as f32) as u32;
+    let y = (center_y - h as f32 / 2.0).round().clamp(0.0, (height - h) as f32) as u32;
+    Region {
+        x,
+        y,
+        width: w,
+        height: h,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control() -> ZoomControl {
+        ZoomControl::new(&ZoomConfig::default())
+    }
+
+    #[test]
+    fn regions_keep_the_frame_aspect_and_stay_inside() {
+        let zoom = control();
+        assert_eq!(zoom.region(0, 640, 480), None);
+
+        zoom.set(0, Zoom::Around { x: 320, y: 240, factor: 2.0 }, 640, 480).unwrap();
+        assert_eq!(zoom.region(0, 640, 480), Some(Region { x: 160, y: 120, width: 320, height: 240 }));
+        // 靠近边缘时区域被推回画面内
+        zoom.set(0, Zoom::Around { x: 10, y: 470, factor: 4.0 }, 640, 480).unwrap();
+        assert_eq!(zoom.region(0, 640, 480), Some(Region { x: 0, y: 360, width: 160, height: 120 }));
+
+        // 细长的矩形按画面比例扩展
+        zoom.set(0, Zoom::Rect { x: 100, y: 100, width: 200, height: 50 }, 640, 480).unwrap();
+        assert_eq!(zoom.region(0, 640, 480), Some(Region { x: 100, y: 50, width: 200, height: 150 }));
+        zoom.set(0, Zoom::Rect { x: 0, y: 0, width: 2000, height: 10 }, 640, 480).unwrap();
+        assert_eq!(zoom.region(0, 640, 480), Some(Region { x: 0, y: 0, width: 640, height: 480 }));
+
+        assert!(zoom.set(0, Zoom::Around { x: 0, y: 0, factor: 0.5 }, 640, 480).is_err());
+        assert!(zoom.set(0, Zoom::Rect { x: 0, y: 0, width: 0, height: 10 }, 640, 480).is_err());
+        // 按画面比例扩展后放大 16 倍，超过默认的 8 倍
+        assert!(zoom.set(0, Zoom::Rect { x: 0, y: 0, width: 40, height: 1 }, 640, 480).is_err());
+        zoom.set(0, Zoom::Rect { x: 0, y: 0, width: 80, height: 1 }, 640, 480).unwrap();
+        // 位置按实际编码的画面校验，视口裁剪后的小画面拒绝原显示器坐标
+        assert!(zoom.set(0, Zoom::Around { x: 400, y: 100, factor: 2.0 }, 320, 240).is_err());
+        assert!(zoom.is_zoomed(0));
+        zoom.set(0, Zoom::Off, 640, 480).unwrap();
+        assert!(!zoom.is_zoomed(0));
+        assert_eq!(zoom.region(0, 640, 480), None);
+    }
+
+    #[test]
+    fn head_motion_pans_the_zoomed_region() {
+        let zoom = control();
+        assert!(!zoom.pan(0, 5.0, 0.0));
+        zoom.set(0, Zoom::Around { x: 320, y: 240, factor: 2.0 }, 640, 480).unwrap();
+
+        // 第一次的朝向作为参考，不产生平移
+        assert!(zoom.pan(0, 30.0, 0.0));
+        assert_eq!(zoom.region(0, 640, 480).unwrap().x, 160);
+        // 向右转半个 pan_degrees 平移半个区域宽度，抬头时向上平移
+        zoom.pan(0, 40.0, 5.0);
+        let region = zoom.region(0, 640, 480).unwrap();
+        assert_eq!((region.x, region.y), (320, 60));
+        // 其他显示器不受影响
+        assert!(!zoom.pan(1, 40.0, 5.0));
+    }
+
+    #[test]
+    fn zoomed_frames_keep_their_size() {
+        let zoom = control();
+        let frame = RgbaImage::from_fn(64, 48, |x, _| image::Rgba([x as u8 * 4, 0, 0, 255]));
+        zoom.set(0, Zoom::Rect { x: 48, y: 0, width: 16, height: 12 }, 64, 48).unwrap();
+        let zoomed = zoom.apply(0, frame.clone());
+        assert_eq!(zoomed.dimensions(), (64, 48));
+        // 放大的是画面右侧，最左列来自原画面的第 48 列附近
+        assert!(zoomed.get_pixel(0, 0)[0] >= 48 * 4 - 4);
+        assert_eq!(zoom.apply(1, frame.clone()), frame);
+    }
+}
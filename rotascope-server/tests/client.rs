@@ -0,0 +1,152 @@
+use rotascope_core::client::{ClientAuth, ClientOptions, RotascopeClient};
+use rotascope_core::{ServerMessage, SwitchDirection};
+use rotascope_server::config::{AuthConfig, ServerConfig, TlsConfig};
+use rotascope_server::video::TestPatternSource;
+use rotascope_server::{ServerBuilder, ServerEvent, ServerHandle};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 开启配对认证和 TLS，证书和信任库放在临时目录
+fn tls_config(dir: &Path) -> ServerConfig {
+    ServerConfig {
+        tls: TlsConfig {
+            enabled: true,
+            required: true,
+            cert_path: dir.join("cert.pem").to_string_lossy().into_owned(),
+            key_path: dir.join("key.pem").to_string_lossy().into_owned(),
+        },
+        auth: AuthConfig {
+            trust_store: dir.join("trusted_devices.json").to_string_lossy().into_owned(),
+            ..AuthConfig::default()
+        },
+        ..ServerConfig::default()
+    }
+}
+
+async fn start(config: ServerConfig) -> ServerHandle {
+    ServerBuilder::new()
+        .config(config)
+        .listen_addr("127.0.0.1:0")
+        .displays(vec![(0, 64, 48), (1, 64, 48)])
+        .capture_source(|| Ok(Box::new(TestPatternSource::new(64, 48, 30))))
+        .start()
+        .await
+        .unwrap()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rotascope-client-{}-{}", name, std::process::id()))
+}
+
+fn options(server: &ServerHandle, auth: ClientAuth) -> ClientOptions {
+    ClientOptions {
+        tls_fingerprint: server.tls_fingerprint().map(str::to_string),
+        ..ClientOptions::new("rust-test", auth)
+    }
+}
+
+async fn recv(client: &mut RotascopeClient) -> ServerMessage {
+    tokio::time::timeout(TIMEOUT, client.recv())
+        .await
+        .expect("timed out waiting for message")
+        .expect("connection closed")
+}
+
+#[tokio::test]
+async fn pairs_over_tls_and_reconnects_with_token() {
+    let dir = temp_dir("pair");
+    let server = start(tls_config(&dir)).await;
+    let url = format!("wss://{}", server.local_addr());
+
+    let code = server.pairing_code().unwrap();
+    let mut client = RotascopeClient::connect(&url, options(&server, ClientAuth::PairingCode(code)))
+        .await
+        .unwrap();
+    let device_id = client.device_id().unwrap().to_string();
+    let token = client.issued_token().expect("pairing issues a token").to_string();
+
+    assert!(matches!(recv(&mut client).await, ServerMessage::DisplayConfig { total_displays: 2, .. }));
+    loop {
+        if let ServerMessage::VideoFrame { width, height, data, .. } = recv(&mut client).await {
+            assert_eq!((width, height), (64, 48));
+            assert!(data.starts_with(&[0xff, 0xd8]));
+            break;
+        }
+    }
+
+    let mut events = server.subscribe();
+    client.switch_display(SwitchDirection::Next).await.unwrap();
+    loop {
+        let event = tokio::time::timeout(TIMEOUT, events.recv()).await.unwrap().unwrap();
+        if let ServerEvent::DisplaySwitched { display } = event {
+            assert_eq!(display, 1);
+            break;
+        }
+    }
+    client.close().await.unwrap();
+
+    let auth = ClientAuth::DeviceToken {
+        device_id: device_id.clone(),
+        token,
+    };
+    let client = RotascopeClient::connect(&url, options(&server, auth)).await.unwrap();
+    assert_eq!(client.device_id(), Some(device_id.as_str()));
+    assert!(client.issued_token().is_none());
+    drop(client);
+
+    server.shutdown();
+    server.wait().await.unwrap();
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn rejects_unpinned_certificate_and_bad_code() {
+    let dir = temp_dir("reject");
+    let server = start(tls_config(&dir)).await;
+    let url = format!("wss://{}", server.local_addr());
+    let code = server.pairing_code().unwrap();
+
+    let mut wrong_pin = options(&server, ClientAuth::PairingCode(code.clone()));
+    wrong_pin.tls_fingerprint = Some("00".repeat(32));
+    let err = RotascopeClient::connect(&url, wrong_pin).await.unwrap_err();
+    assert!(err.contains("TLS handshake failed"), "{}", err);
+
+    let wrong = if code == "000000" { "000001" } else { "000000" };
+    let err = RotascopeClient::connect(&url, options(&server, ClientAuth::PairingCode(wrong.to_string())))
+        .await
+        .unwrap_err();
+    assert!(err.contains("Authentication failed"), "{}", err);
+
+    // TLS 为必需时明文连接被拒绝
+    let plain = format!("ws://{}", server.local_addr());
+    assert!(RotascopeClient::connect(&plain, options(&server, ClientAuth::PairingCode(code))).await.is_err());
+
+    server.shutdown();
+    server.wait().await.unwrap();
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn stalled_tls_handshakes_are_dropped() {
+    let dir = temp_dir("stall");
+    let mut config = tls_config(&dir);
+    config.auth.handshake_timeout_secs = 1;
+    let server = start(config).await;
+
+    // 只发出 ClientHello 的第一个字节后停住
+    let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+    socket.write_all(&[0x16]).await.unwrap();
+    let mut buf = [0u8; 16];
+    let read = tokio::time::timeout(TIMEOUT, socket.read(&mut buf))
+        .await
+        .expect("stalled handshake kept the connection open");
+    assert!(matches!(read, Ok(0) | Err(_)));
+
+    server.shutdown();
+    server.wait().await.unwrap();
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,8 @@
+use rotascope_server::config::ServerConfig;
+
+/// 各集成测试共用的配置：默认配置已关闭有副作用的功能，这里再关闭认证
+pub fn test_config() -> ServerConfig {
+    let mut config = ServerConfig::default();
+    config.auth.required = false;
+    config
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use common::test_config;
+use rotascope_core::client::{ClientAuth, ClientOptions, RotascopeClient};
+use rotascope_core::recording::{RecordedEvent, RecordingReader};
+use rotascope_core::{ClientMessage, ServerMessage, SwitchDirection};
+use rotascope_server::config::ServerConfig;
+use rotascope_server::recording::ReplaySource;
+use rotascope_server::video::{CaptureFactory, TestPatternSource};
+use rotascope_server::{ServerBuilder, ServerHandle};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 允许录制到 recordings 目录
+fn recording_config(recordings: &Path) -> ServerConfig {
+    let mut config = test_config();
+    config.recording.enabled = true;
+    config.recording.directory = recordings.to_string_lossy().into_owned();
+    config
+}
+
+async fn start(config: ServerConfig, capture: CaptureFactory) -> ServerHandle {
+    ServerBuilder::new()
+        .config(config)
+        .listen_addr("127.0.0.1:0")
+        .displays(vec![(0, 64, 48), (1, 64, 48)])
+        .capture(capture)
+        .start()
+        .await
+        .unwrap()
+}
+
+async fn connect(server: &ServerHandle) -> RotascopeClient {
+    let url = format!("ws://{}", server.local_addr());
+    RotascopeClient::connect(&url, ClientOptions::new("recorder", ClientAuth::None))
+        .await
+        .unwrap()
+}
+
+async fn recv(client: &mut RotascopeClient) -> ServerMessage {
+    tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap().unwrap()
+}
+
+async fn next_frame(client: &mut RotascopeClient) -> (u32, u32) {
+    loop {
+        if let ServerMessage::VideoFrame { width, height, .. } = recv(client).await {
+            return (width, height);
+        }
+    }
+}
+
+#[tokio::test]
+async fn records_a_session_and_replays_it() {
+    let dir = std::env::temp_dir().join(format!("rotascope-recordings-{}", std::process::id()));
+    let server = start(
+        recording_config(&dir),
+        CaptureFactory::new(|| Ok(Box::new(TestPatternSource::new(64, 48, 30)))),
+    )
+    .await;
+    let mut client = connect(&server).await;
+
+    client.send(&ClientMessage::SetRecording { enabled: true }).await.unwrap();
+    let file = loop {
+        if let ServerMessage::RecordingState { active: true, file } = recv(&mut client).await {
+            break file.unwrap();
+        }
+    };
+    for _ in 0..5 {
+        next_frame(&mut client).await;
+    }
+    client.switch_display(SwitchDirection::Next).await.unwrap();
+    client.send(&ClientMessage::SetRecording { enabled: false }).await.unwrap();
+    loop {
+        if let ServerMessage::RecordingState { active: false, file: stopped } = recv(&mut client).await {
+            assert_eq!(stopped.as_deref(), Some(file.as_str()));
+            break;
+        }
+    }
+    drop(client);
+    server.shutdown();
+    server.wait().await.unwrap();
+
+    let mut reader = RecordingReader::new(std::fs::File::open(&file).unwrap()).unwrap();
+    assert_eq!(reader.header().resolutions, vec![(64, 48), (64, 48)]);
+    let entries: Vec<_> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+    let frames: Vec<u64> = entries
+        .iter()
+        .filter(|e| matches!(e.event, RecordedEvent::Frame { width: 64, height: 48, .. }))
+        .map(|e| e.offset_ms)
+        .collect();
+    assert!(frames.len() >= 5);
+    assert!(frames.windows(2).all(|w| w[0] <= w[1]));
+    assert!(entries.iter().any(|e| matches!(
+        e.event,
+        RecordedEvent::Client(ClientMessage::SwitchDisplay { direction: SwitchDirection::Next })
+    )));
+
+    // 回放：录制文件作为采集源，经正常的 WebSocket 路径发出
+    let replay_file = file.clone();
+    let replay = start(
+        recording_config(&dir),
+        CaptureFactory::new(move || Ok(Box::new(ReplaySource::open(&replay_file)?))),
+    )
+    .await;
+    let mut client = connect(&replay).await;
+    let started = Instant::now();
+    for _ in 0..frames.len() {
+        assert_eq!(next_frame(&mut client).await, (64, 48));
+    }
+    // 保持原始的帧间隔
+    let recorded_span = Duration::from_millis(frames[frames.len() - 1] - frames[0]);
+    assert!(started.elapsed() + Duration::from_millis(50) >= recorded_span);
+
+    drop(client);
+    replay.shutdown();
+    replay.wait().await.unwrap();
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn recording_stops_at_the_size_limit() {
+    let dir = std::env::temp_dir().join(format!("rotascope-recordings-limit-{}", std::process::id()));
+    let mut config = recording_config(&dir);
+    config.recording.max_bytes = 4096;
+    let server = start(config, CaptureFactory::new(|| Ok(Box::new(TestPatternSource::new(64, 48, 30))))).await;
+    let mut client = connect(&server).await;
+
+    client.send(&ClientMessage::SetRecording { enabled: true }).await.unwrap();
+    let file = loop {
+        if let ServerMessage::RecordingState { active: true, file } = recv(&mut client).await {
+            break file.unwrap();
+        }
+    };
+    // 达到上限后服务端自行结束录制并通知客户端
+    loop {
+        if let ServerMessage::RecordingState { active: false, file: stopped } = recv(&mut client).await {
+            assert_eq!(stopped.as_deref(), Some(file.as_str()));
+            break;
+        }
+    }
+    let size = std::fs::metadata(&file).unwrap().len();
+    assert!((4096..64 * 1024).contains(&size), "{} bytes", size);
+    let reader = RecordingReader::new(std::fs::File::open(&file).unwrap()).unwrap();
+    let entries: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+    assert!(entries.iter().any(|e| matches!(e.event, RecordedEvent::Frame { .. })));
+
+    drop(client);
+    server.shutdown();
+    server.wait().await.unwrap();
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,1068 @@
+mod common;
+
+use common::test_config;
+use futures::{SinkExt, StreamExt};
+use image::RgbaImage;
+use rotascope_core::{
+    Arrangement, ClientMessage, Credentials, DisplayLayout, Result, ServerMessage, SwitchDirection, WindowInfo,
+    WindowSelector, Zoom, deserialize_message, serialize_message,
+};
+use rotascope_core::clipboard::MIME_TEXT;
+use rotascope_core::media;
+use rotascope_server::clipboard::{ClipboardBackend, ClipboardContent};
+use rotascope_server::config::{AudioSourceKind, AuthConfig, ServerConfig};
+use rotascope_server::metrics::ServerStatus;
+use rotascope_server::video::{CaptureSource, TestPatternSource};
+use rotascope_server::window::{WindowCapture, WindowFrame, WindowSystem};
+use rotascope_server::{ServerBuilder, ServerEvent, ServerHandle};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+use tungstenite::Message;
+use tungstenite::protocol::frame::coding::CloseCode;
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn start(config: ServerConfig) -> ServerHandle {
+    ServerBuilder::new()
+        .config(config)
+        .listen_addr("127.0.0.1:0")
+        .displays(vec![(0, 64, 48), (1, 64, 48)])
+        .capture_source(|| Ok(Box::new(TestPatternSource::new(64, 48, 30))))
+        .start()
+        .await
+        .unwrap()
+}
+
+async fn connect(server: &ServerHandle) -> Client {
+    let (ws, _) = connect_async(format!("ws://{}", server.local_addr())).await.unwrap();
+    ws
+}
+
+async fn next_message(ws: &mut Client) -> Message {
+    tokio::time::timeout(TIMEOUT, ws.next())
+        .await
+        .expect("timed out waiting for message")
+        .expect("connection closed")
+        .unwrap()
+}
+
+async fn next_server_message(ws: &mut Client) -> ServerMessage {
+    loop {
+        match next_message(ws).await {
+            Message::Text(text) => return deserialize_message(text.as_bytes()).unwrap(),
+            Message::Binary(data) => {
+                if let Ok(message) = deserialize_message(&data) {
+                    return message;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 二进制视频帧的显示器编号和 JPEG 数据；其他消息返回 None
+fn video_frame(message: Message) -> Option<(u8, Vec<u8>)> {
+    let Message::Binary(data) = message else {
+        return None;
+    };
+    match media::decode(&data).ok()? {
+        ServerMessage::VideoFrame { display_index, data, .. } => Some((display_index, data)),
+        _ => None,
+    }
+}
+
+/// 跳过其他消息，等待下一个视频帧
+async fn next_frame(ws: &mut Client) -> (u8, Vec<u8>) {
+    loop {
+        if let Some(frame) = video_frame(next_message(ws).await) {
+            return frame;
+        }
+    }
+}
+
+async fn next_event(events: &mut tokio::sync::broadcast::Receiver<ServerEvent>) -> ServerEvent {
+    tokio::time::timeout(TIMEOUT, events.recv()).await.unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn streams_frames_and_closes_on_shutdown() {
+    let server = start(test_config()).await;
+    let mut events = server.subscribe();
+    let mut ws = connect(&server).await;
+
+    assert!(matches!(next_event(&mut events).await, ServerEvent::ClientConnected { .. }));
+    match next_server_message(&mut ws).await {
+        ServerMessage::DisplayConfig { total_displays, current_display, .. } => {
+            assert_eq!(total_displays, 2);
+            assert_eq!(current_display, 0);
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+
+    // 视频帧在 JPEG 数据前带有显示器编号、宽高和采集时间
+    loop {
+        if let Message::Binary(data) = next_message(&mut ws).await
+            && let Ok(ServerMessage::VideoFrame { display_index, width, height, data, timestamp }) = media::decode(&data)
+        {
+            assert_eq!((display_index, width, height), (0, 64, 48));
+            assert!(data.starts_with(&[0xff, 0xd8]));
+            assert!(timestamp > 0);
+            break;
+        }
+    }
+
+    server.shutdown();
+    loop {
+        if let Message::Close(frame) = next_message(&mut ws).await {
+            assert_eq!(frame.unwrap().code, CloseCode::Away);
+            break;
+        }
+    }
+    tokio::time::timeout(TIMEOUT, server.wait()).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn capture_restarts_with_backoff_after_failures() {
+    // 前两次打开屏幕失败，第三次成功
+    let opened = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let server = ServerBuilder::new()
+        .config(test_config())
+        .listen_addr("127.0.0.1:0")
+        .capture_source({
+            let opened = opened.clone();
+            move || {
+                let mut opened = opened.lock().unwrap();
+                opened.push(std::time::Instant::now());
+                if opened.len() <= 2 {
+                    return Err("screen unavailable".to_string());
+                }
+                Ok(Box::new(TestPatternSource::new(64, 48, 30)))
+            }
+        })
+        .start()
+        .await
+        .unwrap();
+    let mut events = server.subscribe();
+    let mut ws = connect(&server).await;
+
+    // 第一次失败可能发生在订阅之前，至少能看到第二次
+    loop {
+        if let ServerEvent::CaptureFailed { message } = next_event(&mut events).await {
+            assert!(message.contains("screen unavailable"));
+            break;
+        }
+    }
+    next_frame(&mut ws).await;
+
+    let opened = opened.lock().unwrap().clone();
+    assert_eq!(opened.len(), 3);
+    assert!(opened[1] - opened[0] >= Duration::from_secs(1));
+    assert!(opened[2] - opened[1] >= Duration::from_secs(2));
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+/// 每次都立即返回新画面的采集源，和 X11 上的 scrap 一样
+struct AlwaysReadySource(Arc<AtomicUsize>);
+
+impl CaptureSource for AlwaysReadySource {
+    fn capture_frame(&mut self) -> Result<RgbaImage> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        Ok(RgbaImage::new(64, 48))
+    }
+}
+
+#[tokio::test]
+async fn capture_rate_is_limited_when_the_source_is_always_ready() {
+    let captured = Arc::new(AtomicUsize::new(0));
+    let mut config = test_config();
+    config.displays.capture_fps = 20;
+    let server = ServerBuilder::new()
+        .config(config)
+        .listen_addr("127.0.0.1:0")
+        .capture_source({
+            let captured = captured.clone();
+            move || Ok(Box::new(AlwaysReadySource(captured.clone())))
+        })
+        .start()
+        .await
+        .unwrap();
+    let mut ws = connect(&server).await;
+    next_frame(&mut ws).await;
+
+    // 20 fps 下半秒内约采集 10 帧，不会空转
+    let before = captured.load(Ordering::Relaxed);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let count = captured.load(Ordering::Relaxed) - before;
+    assert!(count > 0 && count <= 12, "captured {} frames in 500ms", count);
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn display_switch_is_reported_as_event() {
+    let server = start(test_config()).await;
+    let mut events = server.subscribe();
+    let mut ws = connect(&server).await;
+    next_server_message(&mut ws).await;
+
+    let switch = ClientMessage::SwitchDisplay { direction: SwitchDirection::Previous };
+    ws.send(Message::binary(serialize_message(&switch).unwrap())).await.unwrap();
+    loop {
+        if let ServerEvent::DisplaySwitched { display } = next_event(&mut events).await {
+            assert_eq!(display, 1);
+            break;
+        }
+    }
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn rejects_wrong_pairing_code_and_locks_out_repeated_failures() {
+    let trust_store = std::env::temp_dir().join(format!("rotascope-it-trust-{}.json", std::process::id()));
+    // auth 在 config 之前设置，不会被 config 覆盖
+    let server = ServerBuilder::new()
+        .auth(AuthConfig {
+            trust_store: trust_store.to_string_lossy().into_owned(),
+            max_failed_attempts: 3,
+            ..AuthConfig::default()
+        })
+        .config(test_config())
+        .listen_addr("127.0.0.1:0")
+        .capture_source(|| Ok(Box::new(TestPatternSource::new(64, 48, 30))))
+        .start()
+        .await
+        .unwrap();
+
+    for _ in 0..3 {
+        let mut ws = connect(&server).await;
+        let ServerMessage::AuthChallenge { nonce } = next_server_message(&mut ws).await else {
+            panic!("expected AuthChallenge");
+        };
+        let hello = ClientMessage::Hello {
+            device_name: "test".to_string(),
+            credentials: Credentials::PairingCode {
+                proof: rotascope_core::auth::auth_proof("not-the-code", &nonce),
+            },
+        };
+        ws.send(Message::binary(serialize_message(&hello).unwrap())).await.unwrap();
+        assert!(matches!(next_server_message(&mut ws).await, ServerMessage::Error { .. }));
+    }
+
+    // 锁定期内的连接拿不到挑战，也就无法继续猜测
+    let mut ws = connect(&server).await;
+    match next_server_message(&mut ws).await {
+        ServerMessage::Error { message } => assert!(message.contains("Too many failed attempts")),
+        other => panic!("expected Error, got {:?}", other),
+    }
+
+    server.shutdown();
+    server.wait().await.unwrap();
+    let _ = std::fs::remove_file(trust_store);
+}
+
+/// 在同一端口上发送一个普通的 HTTP 请求，返回完整响应
+async fn http_get(server: &ServerHandle, path: &str) -> String {
+    let mut stream = TcpStream::connect(server.local_addr()).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(TIMEOUT, stream.read_to_string(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    response
+}
+
+#[tokio::test]
+async fn metrics_are_off_by_default() {
+    let server = start(test_config()).await;
+    assert!(http_get(&server, "/status").await.starts_with("HTTP/1.1 404"));
+    assert!(http_get(&server, "/metrics").await.starts_with("HTTP/1.1 404"));
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn serves_metrics_and_status_next_to_websocket() {
+    let mut config = test_config();
+    config.metrics.enabled = true;
+    let server = start(config).await;
+    let mut ws = connect(&server).await;
+    for _ in 0..3 {
+        next_frame(&mut ws).await;
+    }
+    let sensor = ClientMessage::SensorData {
+        rotation_x: 0.0,
+        rotation_y: 0.0,
+        rotation_z: 0.0,
+    };
+    ws.send(Message::binary(serialize_message(&sensor).unwrap())).await.unwrap();
+
+    let metrics = http_get(&server, "/metrics").await;
+    assert!(metrics.starts_with("HTTP/1.1 200 OK"), "{}", metrics);
+    assert!(metrics.contains("rotascope_connected_clients 1\n"), "{}", metrics);
+    assert!(metrics.contains("# TYPE rotascope_capture_seconds histogram"));
+    assert!(metrics.contains("# TYPE rotascope_capture_queue_wait_seconds histogram"));
+    assert!(metrics.contains("rotascope_capture_frames_overwritten_total "));
+    assert!(metrics.contains("rotascope_connections_total 1\n"), "{}", metrics);
+    // 按连接编号区分客户端，不暴露地址
+    assert!(metrics.contains("rotascope_client_frames_sent_total{client=\"1\"}"), "{}", metrics);
+    assert!(!metrics.contains("127.0.0.1"));
+
+    let response = http_get(&server, "/status?pretty").await;
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.contains("Content-Type: application/json"), "{}", head);
+    let status: ServerStatus = serde_json::from_str(body).unwrap();
+    assert_eq!(status.connected_clients, 1);
+    assert_eq!(status.total_displays, 2);
+    assert!(status.capture.frames >= 3);
+    assert_eq!(status.clients[0].connection, 1);
+    assert!(status.clients[0].frames_sent >= 3);
+    assert!(status.capture.avg_frame_bytes > 0.0);
+    assert!(status.capture.avg_queue_wait_ms >= 0.0);
+
+    assert!(http_get(&server, "/nope").await.starts_with("HTTP/1.1 404"));
+    // 传感器消息由另一个任务处理，可能稍晚才计入
+    tokio::time::timeout(TIMEOUT, async {
+        while !http_get(&server, "/metrics").await.contains("rotascope_sensor_messages_total 1\n") {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    // HTTP 请求不算作客户端连接，WebSocket 会话不受影响
+    assert_eq!(server.status().await.connected_clients, 1);
+    assert!(matches!(next_message(&mut ws).await, Message::Binary(_)));
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn heartbeats_measure_rtt_and_drop_silent_clients() {
+    let mut config = test_config();
+    config.heartbeat.interval_ms = 100;
+    config.heartbeat.timeout_ms = 400;
+    let server = start(config).await;
+    let mut events = server.subscribe();
+
+    // 持续读取的客户端会自动回复 Pong
+    let mut active = connect(&server).await;
+    while !matches!(next_server_message(&mut active).await, ServerMessage::Heartbeat) {}
+    let reader = tokio::spawn(async move { while let Some(Ok(_)) = active.next().await {} });
+    tokio::time::timeout(TIMEOUT, async {
+        while !server.status().await.clients.iter().any(|c| c.rtt_ms.is_some()) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("rtt measured");
+
+    // 不再读取的客户端收不到 Ping，也就不会回复
+    let _silent = connect(&server).await;
+    loop {
+        if let ServerEvent::ClientTimedOut { .. } = next_event(&mut events).await {
+            break;
+        }
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let status = server.status().await;
+    assert_eq!(status.connected_clients, 1);
+    assert!(!reader.is_finished());
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+async fn send(ws: &mut Client, message: &ClientMessage) {
+    ws.send(Message::binary(serialize_message(message).unwrap())).await.unwrap();
+}
+
+/// 回应认证挑战，返回 AuthAccepted 中的 (设备 ID, 新签发的令牌)
+async fn authenticate(ws: &mut Client, secret: &str, device_id: Option<&str>) -> (String, Option<String>) {
+    let ServerMessage::AuthChallenge { nonce } = next_server_message(ws).await else {
+        panic!("expected AuthChallenge");
+    };
+    let proof = rotascope_core::auth::auth_proof(secret, &nonce);
+    let credentials = match device_id {
+        Some(device_id) => Credentials::DeviceToken { device_id: device_id.to_string(), proof },
+        None => Credentials::PairingCode { proof },
+    };
+    send(ws, &ClientMessage::Hello { device_name: "test".to_string(), credentials }).await;
+    match next_server_message(ws).await {
+        ServerMessage::AuthAccepted { device_id, token } => (device_id, token),
+        other => panic!("expected AuthAccepted, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn resumes_dropped_session_of_the_same_device() {
+    let trust_store = std::env::temp_dir().join(format!("rotascope-it-resume-trust-{}.json", std::process::id()));
+    let mut config = test_config();
+    config.auth = AuthConfig {
+        trust_store: trust_store.to_string_lossy().into_owned(),
+        ..AuthConfig::default()
+    };
+    let server = start(config).await;
+    let mut events = server.subscribe();
+
+    let mut ws = connect(&server).await;
+    let (device_id, token) = authenticate(&mut ws, &server.pairing_code().unwrap(), None).await;
+    let token = token.unwrap();
+    let session_id = loop {
+        if let ServerMessage::SessionInfo { session_id, resumed, .. } = next_server_message(&mut ws).await {
+            assert!(!resumed);
+            break session_id;
+        }
+    };
+    send(&mut ws, &ClientMessage::SwitchDisplay { direction: SwitchDirection::Next }).await;
+    while !matches!(next_event(&mut events).await, ServerEvent::DisplaySwitched { display: 1 }) {}
+    // 不发关闭帧直接断开，模拟网络中断
+    drop(ws);
+    while !matches!(next_event(&mut events).await, ServerEvent::ClientDisconnected { .. }) {}
+
+    // 断开期间另一个客户端切回了显示器 0，随后也断开
+    let mut other = connect(&server).await;
+    authenticate(&mut other, &token, Some(&device_id)).await;
+    let other_session = loop {
+        if let ServerMessage::SessionInfo { session_id, .. } = next_server_message(&mut other).await {
+            break session_id;
+        }
+    };
+    send(&mut other, &ClientMessage::SwitchDisplay { direction: SwitchDirection::Previous }).await;
+    while !matches!(next_event(&mut events).await, ServerEvent::DisplaySwitched { display: 0 }) {}
+    drop(other);
+    while !matches!(next_event(&mut events).await, ServerEvent::ClientDisconnected { .. }) {}
+    assert_eq!(server.status().await.parked_sessions, 2);
+
+    // 没有其他客户端时，恢复会话切回断开前选中的显示器
+    let mut ws = connect(&server).await;
+    authenticate(&mut ws, &token, Some(&device_id)).await;
+    send(&mut ws, &ClientMessage::ResumeSession { session_id: session_id.clone() }).await;
+    loop {
+        if let ServerMessage::SessionInfo { session_id: id, resumed: true, .. } = next_server_message(&mut ws).await {
+            assert_eq!(id, session_id);
+            break;
+        }
+    }
+    match next_server_message(&mut ws).await {
+        ServerMessage::DisplayConfig { current_display, .. } => assert_eq!(current_display, 1),
+        other => panic!("unexpected message: {:?}", other),
+    }
+    while !matches!(next_event(&mut events).await, ServerEvent::DisplaySwitched { display: 1 }) {}
+    while next_frame(&mut ws).await.0 != 1 {}
+    assert_eq!(server.status().await.parked_sessions, 1);
+
+    // 显示器是共享的，有其他客户端在看时恢复会话不会覆盖它的选择
+    let mut other = connect(&server).await;
+    authenticate(&mut other, &token, Some(&device_id)).await;
+    send(&mut other, &ClientMessage::ResumeSession { session_id: other_session }).await;
+    loop {
+        if let ServerMessage::SessionInfo { resumed: true, .. } = next_server_message(&mut other).await {
+            break;
+        }
+    }
+    match next_server_message(&mut other).await {
+        ServerMessage::DisplayConfig { current_display, .. } => assert_eq!(current_display, 1),
+        other => panic!("unexpected message: {:?}", other),
+    }
+    // 恢复后紧接着补发一帧，不必等待下一次捕获
+    assert_eq!(video_frame(next_message(&mut other).await).map(|(display, _)| display), Some(1));
+    assert_eq!(server.status().await.parked_sessions, 0);
+
+    // 已经恢复或发送过其他消息的连接不能再接管会话
+    send(&mut ws, &ClientMessage::ResumeSession { session_id: session_id.clone() }).await;
+    assert!(expect_error(&mut ws).await.contains("first message"));
+
+    // 会话只能恢复一次
+    let mut late = connect(&server).await;
+    authenticate(&mut late, &token, Some(&device_id)).await;
+    send(&mut late, &ClientMessage::ResumeSession { session_id }).await;
+    assert!(expect_error(&mut late).await.contains("expired or unknown"));
+
+    server.shutdown();
+    server.wait().await.unwrap();
+    let _ = std::fs::remove_file(trust_store);
+}
+
+#[tokio::test]
+async fn sessions_without_authentication_cannot_be_resumed() {
+    let server = start(test_config()).await;
+    let mut ws = connect(&server).await;
+    send(&mut ws, &ClientMessage::ResumeSession { session_id: "0".repeat(32) }).await;
+    assert!(expect_error(&mut ws).await.contains("requires an authenticated device"));
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+/// 跳过视频帧和心跳，返回下一个 DisplayConfig 的 (当前显示器, 分辨率)
+async fn next_display_config(ws: &mut Client) -> (u8, Vec<(u32, u32)>) {
+    loop {
+        if let ServerMessage::DisplayConfig {
+            current_display,
+            resolutions,
+            ..
+        } = next_server_message(ws).await
+        {
+            return (current_display, resolutions);
+        }
+    }
+}
+
+#[tokio::test]
+async fn display_changes_are_pushed_to_every_client() {
+    let server = start(test_config()).await;
+    let mut first = connect(&server).await;
+    let mut second = connect(&server).await;
+    assert_eq!(next_display_config(&mut first).await, (0, vec![(64, 48), (64, 48)]));
+    assert_eq!(next_display_config(&mut second).await, (0, vec![(64, 48), (64, 48)]));
+    tokio::time::timeout(TIMEOUT, async {
+        while server.status().await.connected_clients < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(server.add_display("wide", 128, 96).await.unwrap(), 2);
+    for ws in [&mut first, &mut second] {
+        assert_eq!(next_display_config(ws).await, (0, vec![(64, 48), (64, 48), (128, 96)]));
+    }
+
+    // 客户端也可以调整分辨率，错误的请求只回复给发起方
+    send(&mut second, &ClientMessage::ResizeDisplay { index: 1, width: 32, height: 24 }).await;
+    for ws in [&mut first, &mut second] {
+        assert_eq!(next_display_config(ws).await, (0, vec![(64, 48), (32, 24), (128, 96)]));
+    }
+    send(&mut second, &ClientMessage::ResizeDisplay { index: 9, width: 32, height: 24 }).await;
+    while !matches!(next_server_message(&mut second).await, ServerMessage::Error { .. }) {}
+
+    // 移除正在观看的显示器时切到相邻的显示器
+    send(&mut first, &ClientMessage::SwitchDisplay { direction: SwitchDirection::Previous }).await;
+    tokio::time::timeout(TIMEOUT, async {
+        while server.status().await.current_display != 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    server.remove_display(2).await.unwrap();
+    assert_eq!(next_display_config(&mut first).await, (1, vec![(64, 48), (32, 24)]));
+    assert!(server.remove_display(5).await.is_err());
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+async fn expect_error(ws: &mut Client) -> String {
+    loop {
+        if let ServerMessage::Error { message } = next_server_message(ws).await {
+            return message;
+        }
+    }
+}
+
+#[tokio::test]
+async fn clients_create_and_destroy_displays_within_limits() {
+    let mut config = test_config();
+    config.displays.max_displays = 3;
+    config.displays.max_width = 1280;
+    config.displays.max_height = 720;
+    let server = start(config).await;
+    let mut ws = connect(&server).await;
+    next_display_config(&mut ws).await;
+
+    let create = |width, height| ClientMessage::CreateDisplay {
+        width,
+        height,
+        name: "headset".to_string(),
+    };
+    send(&mut ws, &create(1920, 1080)).await;
+    assert!(expect_error(&mut ws).await.contains("exceeds the limit"));
+
+    // 新建的显示器成为当前显示器
+    send(&mut ws, &create(1280, 720)).await;
+    assert_eq!(next_display_config(&mut ws).await, (2, vec![(64, 48), (64, 48), (1280, 720)]));
+    send(&mut ws, &create(640, 480)).await;
+    assert!(expect_error(&mut ws).await.contains("limit reached"));
+
+    // 调整分辨率受同样的尺寸限制
+    send(&mut ws, &ClientMessage::ResizeDisplay { index: 0, width: 8192, height: 8192 }).await;
+    assert!(expect_error(&mut ws).await.contains("exceeds the limit"));
+
+    // 配置文件中的显示器不能由客户端销毁
+    send(&mut ws, &ClientMessage::DestroyDisplay { index: 0 }).await;
+    assert!(expect_error(&mut ws).await.contains("not created by a client"));
+    send(&mut ws, &ClientMessage::DestroyDisplay { index: 2 }).await;
+    assert_eq!(next_display_config(&mut ws).await, (1, vec![(64, 48), (64, 48)]));
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn clients_cannot_change_displays_unless_allowed() {
+    let mut config = test_config();
+    config.displays.client_managed = false;
+    let server = start(config).await;
+    let mut ws = connect(&server).await;
+    next_display_config(&mut ws).await;
+
+    let layout = DisplayLayout {
+        name: "moved".to_string(),
+        x: 100,
+        y: 0,
+        rotation: 0.0,
+        scale: 1.0,
+    };
+    for message in [
+        ClientMessage::ResizeDisplay { index: 0, width: 32, height: 24 },
+        ClientMessage::ArrangeDisplays { arrangement: Arrangement::Grid { columns: 1 } },
+        ClientMessage::SetDisplayLayout { index: 0, layout },
+    ] {
+        send(&mut ws, &message).await;
+        assert!(expect_error(&mut ws).await.contains("not allowed"));
+    }
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn directional_switching_follows_the_arrangement() {
+    let server = start(test_config()).await;
+    let mut events = server.subscribe();
+    let mut ws = connect(&server).await;
+    next_display_config(&mut ws).await;
+
+    // 竖排后第二个显示器在下方
+    send(&mut ws, &ClientMessage::ArrangeDisplays { arrangement: Arrangement::Grid { columns: 1 } }).await;
+    let layout = loop {
+        if let ServerMessage::DisplayConfig { layout, arrangement, .. } = next_server_message(&mut ws).await {
+            assert_eq!(arrangement, Arrangement::Grid { columns: 1 });
+            break layout;
+        }
+    };
+    assert_eq!(layout.iter().map(|l| (l.x, l.y)).collect::<Vec<_>>(), vec![(0, 0), (0, 48)]);
+
+    // 左边没有显示器，不切换；向下切到第二个
+    send(&mut ws, &ClientMessage::SwitchDisplay { direction: SwitchDirection::Left }).await;
+    send(&mut ws, &ClientMessage::SwitchDisplay { direction: SwitchDirection::Down }).await;
+    loop {
+        if let ServerEvent::DisplaySwitched { display } = next_event(&mut events).await {
+            assert_eq!(display, 1);
+            break;
+        }
+    }
+
+    send(&mut ws, &ClientMessage::ArrangeDisplays { arrangement: Arrangement::Grid { columns: 0 } }).await;
+    assert!(expect_error(&mut ws).await.contains("at least one column"));
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+/// 跳过视频帧和心跳，返回下一个 DisplaySwitched 中的当前显示器
+async fn next_display_switched(ws: &mut Client) -> u8 {
+    loop {
+        if let ServerMessage::DisplaySwitched { current_display } = next_server_message(ws).await {
+            return current_display;
+        }
+    }
+}
+
+#[tokio::test]
+async fn display_switches_are_acknowledged_to_every_client() {
+    let server = start(test_config()).await;
+    server.add_display("", 64, 48).await.unwrap();
+    let mut first = connect(&server).await;
+    let mut second = connect(&server).await;
+    next_display_config(&mut first).await;
+    next_display_config(&mut second).await;
+    tokio::time::timeout(TIMEOUT, async {
+        while server.status().await.connected_clients < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    send(&mut first, &ClientMessage::SwitchDisplay { direction: SwitchDirection::Goto(2) }).await;
+    for ws in [&mut first, &mut second] {
+        assert_eq!(next_display_switched(ws).await, 2);
+    }
+    send(&mut second, &ClientMessage::SwitchDisplay { direction: SwitchDirection::Last }).await;
+    for ws in [&mut first, &mut second] {
+        assert_eq!(next_display_switched(ws).await, 0);
+    }
+    send(&mut second, &ClientMessage::SwitchDisplay { direction: SwitchDirection::Last }).await;
+    for ws in [&mut first, &mut second] {
+        assert_eq!(next_display_switched(ws).await, 2);
+    }
+
+    // 没有变化时只确认给请求方
+    send(&mut first, &ClientMessage::SwitchDisplay { direction: SwitchDirection::Right }).await;
+    assert_eq!(next_display_switched(&mut first).await, 2);
+    send(&mut first, &ClientMessage::SwitchDisplay { direction: SwitchDirection::Goto(7) }).await;
+    assert!(expect_error(&mut first).await.contains("No display 7"));
+    send(&mut second, &ClientMessage::SwitchDisplay { direction: SwitchDirection::Previous }).await;
+    for ws in [&mut first, &mut second] {
+        assert_eq!(next_display_switched(ws).await, 1);
+    }
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+/// 假的窗口系统：窗口 7 先输出几帧，随后变大、隐藏，最后关闭；stays_open 时一直输出同样大小的画面
+#[derive(Debug, Default)]
+struct FakeWindows {
+    stays_open: bool,
+}
+
+struct FakeWindow {
+    frame: u32,
+    stays_open: bool,
+}
+
+impl WindowCapture for FakeWindow {
+    fn capture(&mut self) -> Result<WindowFrame> {
+        std::thread::sleep(Duration::from_millis(10));
+        if self.stays_open {
+            return Ok(WindowFrame::Frame(RgbaImage::new(32, 24)));
+        }
+        self.frame += 1;
+        Ok(match self.frame {
+            0..=5 => WindowFrame::Frame(RgbaImage::new(32, 24)),
+            6..=10 => WindowFrame::Frame(RgbaImage::new(40, 30)),
+            11..=13 => WindowFrame::Unmapped,
+            _ => WindowFrame::Closed,
+        })
+    }
+}
+
+impl WindowSystem for FakeWindows {
+    fn list_windows(&self) -> Result<Vec<WindowInfo>> {
+        let window = |id, title: &str| WindowInfo {
+            id,
+            title: title.to_string(),
+            width: 32,
+            height: 24,
+        };
+        Ok(vec![window(7, "Terminal"), window(8, "Editor")])
+    }
+
+    fn open(&self, window: u32) -> Result<Box<dyn WindowCapture>> {
+        assert_eq!(window, 7);
+        Ok(Box::new(FakeWindow {
+            frame: 0,
+            stays_open: self.stays_open,
+        }))
+    }
+}
+
+#[tokio::test]
+async fn captured_windows_follow_resizes_and_disappear_when_closed() {
+    let server = ServerBuilder::new()
+        .config(test_config())
+        .listen_addr("127.0.0.1:0")
+        .displays(vec![(0, 64, 48), (1, 64, 48)])
+        .capture_source(|| Ok(Box::new(TestPatternSource::new(64, 48, 30))))
+        .window_system(FakeWindows::default())
+        .start()
+        .await
+        .unwrap();
+    let mut ws = connect(&server).await;
+    next_display_config(&mut ws).await;
+
+    send(&mut ws, &ClientMessage::ListWindows).await;
+    let windows = loop {
+        if let ServerMessage::WindowList { windows } = next_server_message(&mut ws).await {
+            break windows;
+        }
+    };
+    assert_eq!(windows.len(), 2);
+    send(&mut ws, &ClientMessage::CaptureWindow { window: WindowSelector::Id(99) }).await;
+    assert!(expect_error(&mut ws).await.contains("No window with id 99"));
+
+    // 窗口成为新的当前显示器，尺寸随窗口变化，窗口关闭后显示器被移除
+    send(&mut ws, &ClientMessage::CaptureWindow { window: WindowSelector::Title("term".to_string()) }).await;
+    assert_eq!(next_display_config(&mut ws).await, (2, vec![(64, 48), (64, 48), (32, 24)]));
+    assert_eq!(next_display_config(&mut ws).await, (2, vec![(64, 48), (64, 48), (40, 30)]));
+    assert_eq!(next_display_config(&mut ws).await, (1, vec![(64, 48), (64, 48)]));
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn zoom_is_shared_and_head_motion_pans_instead_of_switching() {
+    let server = start(test_config()).await;
+    let mut events = server.subscribe();
+    let mut ws = connect(&server).await;
+    next_display_config(&mut ws).await;
+
+    send(&mut ws, &ClientMessage::SetZoom { zoom: Zoom::Around { x: 0, y: 0, factor: 100.0 } }).await;
+    assert!(expect_error(&mut ws).await.contains("between 1 and 8"));
+    send(&mut ws, &ClientMessage::SetZoom { zoom: Zoom::Rect { x: 0, y: 0, width: 4, height: 3 } }).await;
+    assert!(expect_error(&mut ws).await.contains("maximum zoom factor"));
+    let zoom = Zoom::Around { x: 32, y: 24, factor: 2.0 };
+    send(&mut ws, &ClientMessage::SetZoom { zoom }).await;
+    loop {
+        if let ServerMessage::ZoomChanged { display_index, zoom: changed } = next_server_message(&mut ws).await {
+            assert_eq!((display_index, changed), (0, zoom));
+            break;
+        }
+    }
+    // 放大后的画面尺寸不变
+    let (_, data) = next_frame(&mut ws).await;
+    assert_eq!(rotascope_core::client::jpeg_dimensions(&data), Some((64, 48)));
+
+    // 放大期间转头只平移画面；取消放大后才切换显示器
+    let sensor = ClientMessage::SensorData { rotation_x: 0.0, rotation_y: 45.0, rotation_z: 0.0 };
+    send(&mut ws, &sensor).await;
+    send(&mut ws, &ClientMessage::SetZoom { zoom: Zoom::Off }).await;
+    send(&mut ws, &sensor).await;
+    loop {
+        if let ServerEvent::DisplaySwitched { display } = next_event(&mut events).await {
+            assert_eq!(display, 1);
+            break;
+        }
+    }
+    assert_eq!(server.status().await.current_display, 1);
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn composite_viewport_roams_with_head_motion_instead_of_switching() {
+    let mut config = test_config();
+    config.composite.enabled = true;
+    config.composite.viewport_width = 32;
+    config.composite.viewport_height = 24;
+    let server = start(config).await;
+    let mut ws = connect(&server).await;
+    next_display_config(&mut ws).await;
+
+    // 低头到底时视口移到画布下半部分；测试图案的绿色通道等于行号
+    let sensor = ClientMessage::SensorData { rotation_x: -20.0, rotation_y: 0.0, rotation_z: 0.0 };
+    send(&mut ws, &sensor).await;
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            let (_, data) = next_frame(&mut ws).await;
+            let frame = image::load_from_memory(&data).unwrap().to_rgba8();
+            assert_eq!(frame.dimensions(), (32, 24));
+            if frame.get_pixel(16, 0)[1] >= 16 {
+                break;
+            }
+        }
+    })
+    .await
+    .expect("viewport did not follow the head");
+
+    // 转头没有切换显示器：之后的 Next 从显示器 0 切到 1
+    let sensor = ClientMessage::SensorData { rotation_x: 0.0, rotation_y: 45.0, rotation_z: 0.0 };
+    send(&mut ws, &sensor).await;
+    send(&mut ws, &ClientMessage::SwitchDisplay { direction: SwitchDirection::Next }).await;
+    assert_eq!(next_display_switched(&mut ws).await, 1);
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn head_motion_switches_away_from_window_displays_in_composite_mode() {
+    let mut config = test_config();
+    config.composite.enabled = true;
+    let server = ServerBuilder::new()
+        .config(config)
+        .listen_addr("127.0.0.1:0")
+        .displays(vec![(0, 64, 48), (1, 64, 48)])
+        .capture_source(|| Ok(Box::new(TestPatternSource::new(64, 48, 30))))
+        .window_system(FakeWindows { stays_open: true })
+        .start()
+        .await
+        .unwrap();
+    let mut ws = connect(&server).await;
+    next_display_config(&mut ws).await;
+
+    // 窗口画面不经过视口，转头照常切换显示器
+    send(&mut ws, &ClientMessage::CaptureWindow { window: WindowSelector::Id(7) }).await;
+    assert_eq!(next_display_config(&mut ws).await, (2, vec![(64, 48), (64, 48), (32, 24)]));
+    let sensor = ClientMessage::SensorData { rotation_x: 0.0, rotation_y: 45.0, rotation_z: 0.0 };
+    send(&mut ws, &sensor).await;
+    let switched = tokio::time::timeout(TIMEOUT, next_display_switched(&mut ws)).await;
+    assert_eq!(switched.expect("head motion did not switch displays"), 0);
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+/// 假的桌面剪贴板：记录写入的内容，desktop 收到的内容当作桌面上的复制
+#[derive(Debug)]
+struct FakeClipboard {
+    writes: Arc<std::sync::Mutex<Vec<ClipboardContent>>>,
+    desktop: std::sync::mpsc::Receiver<ClipboardContent>,
+}
+
+impl ClipboardBackend for FakeClipboard {
+    fn run(
+        self: Box<Self>,
+        writes: std::sync::mpsc::Receiver<ClipboardContent>,
+        changed: &mut dyn FnMut(ClipboardContent),
+    ) -> Result<()> {
+        loop {
+            match writes.recv_timeout(Duration::from_millis(5)) {
+                Ok(content) => self.writes.lock().unwrap().push(content),
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+            while let Ok(content) = self.desktop.try_recv() {
+                changed(content);
+            }
+        }
+    }
+}
+
+fn text(value: &str) -> ClipboardContent {
+    ClipboardContent {
+        mime_type: MIME_TEXT.to_string(),
+        data: value.as_bytes().to_vec(),
+    }
+}
+
+async fn send_clipboard(ws: &mut Client, value: &str) {
+    let update = ClientMessage::ClipboardUpdate {
+        mime_type: MIME_TEXT.to_string(),
+        data: value.as_bytes().to_vec(),
+    };
+    send(ws, &update).await;
+}
+
+async fn next_clipboard(ws: &mut Client) -> String {
+    loop {
+        if let ServerMessage::ClipboardUpdate { mime_type, data } = next_server_message(ws).await {
+            assert_eq!(mime_type, MIME_TEXT);
+            return String::from_utf8(data).unwrap();
+        }
+    }
+}
+
+/// 等待内容写入桌面剪贴板，同时说明写入方的会话已经开启了同步
+async fn wait_for_write(writes: &std::sync::Mutex<Vec<ClipboardContent>>, value: &str) {
+    tokio::time::timeout(TIMEOUT, async {
+        while !writes.lock().unwrap().contains(&text(value)) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("clipboard write did not reach the desktop");
+}
+
+#[tokio::test]
+async fn clipboard_updates_reach_the_desktop_and_other_sessions() {
+    let writes = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let (desktop, desktop_rx) = std::sync::mpsc::channel();
+    let mut config = test_config();
+    config.clipboard.enabled = true;
+    let server = ServerBuilder::new()
+        .config(config)
+        .listen_addr("127.0.0.1:0")
+        .displays(vec![(0, 64, 48)])
+        .capture_source(|| Ok(Box::new(TestPatternSource::new(64, 48, 30))))
+        .clipboard_backend(FakeClipboard { writes: writes.clone(), desktop: desktop_rx })
+        .start()
+        .await
+        .unwrap();
+
+    let mut first = connect(&server).await;
+    let mut second = connect(&server).await;
+    send(&mut first, &ClientMessage::ClipboardSync { enabled: true }).await;
+    send_clipboard(&mut first, "first").await;
+    wait_for_write(&writes, "first").await;
+    send(&mut second, &ClientMessage::ClipboardSync { enabled: true }).await;
+    send_clipboard(&mut second, "second").await;
+    wait_for_write(&writes, "second").await;
+
+    // 写入方不会收到自己的内容
+    assert_eq!(next_clipboard(&mut first).await, "second");
+    send_clipboard(&mut first, "again").await;
+    loop {
+        match next_clipboard(&mut second).await.as_str() {
+            "again" => break,
+            other => assert_eq!(other, "first"),
+        }
+    }
+
+    desktop.send(text("desktop")).unwrap();
+    assert_eq!(next_clipboard(&mut first).await, "desktop");
+    assert_eq!(next_clipboard(&mut second).await, "desktop");
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn audio_is_sent_as_binary_chunks_after_opting_in() {
+    let mut config = test_config();
+    config.audio.enabled = true;
+    config.audio.source = AudioSourceKind::Sine;
+    let server = start(config).await;
+    let mut ws = connect(&server).await;
+    next_server_message(&mut ws).await;
+
+    send(&mut ws, &ClientMessage::SetAudio { enabled: true }).await;
+    loop {
+        if let Message::Binary(data) = next_message(&mut ws).await
+            && data.first() == Some(&media::KIND_AUDIO)
+        {
+            match media::decode(&data).unwrap() {
+                ServerMessage::AudioChunk { sample_rate, channels, data, .. } => {
+                    assert_eq!((sample_rate, channels), (48_000, 2));
+                    assert_eq!(data.len(), 960 * 2 * 2);
+                }
+                other => panic!("unexpected message: {:?}", other),
+            }
+            break;
+        }
+    }
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn mdns_advertisement_follows_the_display_count() {
+    let name = format!("rotascope-it-{}", std::process::id());
+    let mut config = test_config();
+    config.discovery.enabled = true;
+    config.discovery.include_loopback = true;
+    config.discovery.name = Some(name.clone());
+    let server = start(config).await;
+
+    server.add_display("extra", 64, 48).await.unwrap();
+    let servers = rotascope_core::discovery::discover(Duration::from_secs(3), true).await.unwrap();
+    let advertised = servers
+        .iter()
+        .find(|s| s.announcement.name == name)
+        .expect("server is advertised");
+    assert_eq!(advertised.announcement.display_count, 3);
+
+    server.shutdown();
+    server.wait().await.unwrap();
+}
@@ -0,0 +1,45 @@
+//! 信号会发给整个测试进程，单独放在一个测试二进制中
+#![cfg(unix)]
+
+mod common;
+
+use common::test_config;
+use rotascope_server::video::TestPatternSource;
+use rotascope_server::{ServerBuilder, ServerEvent};
+use std::time::Duration;
+use tokio::signal::unix::{SignalKind, signal};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn sigterm_shuts_the_server_down() {
+    // 先注册自己的监听，即使服务端还没开始监听，SIGTERM 也不会直接结束测试进程
+    let mut guard = signal(SignalKind::terminate()).unwrap();
+
+    let server = ServerBuilder::new()
+        .config(test_config())
+        .listen_addr("127.0.0.1:0")
+        .displays(vec![(0, 64, 48)])
+        .capture_source(|| Ok(Box::new(TestPatternSource::new(64, 48, 30))))
+        .handle_signals(true)
+        .start()
+        .await
+        .unwrap();
+    let mut events = server.subscribe();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let status = std::process::Command::new("kill")
+        .args(["-TERM", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    tokio::time::timeout(TIMEOUT, guard.recv()).await.unwrap();
+
+    loop {
+        let event = tokio::time::timeout(TIMEOUT, events.recv()).await.unwrap().unwrap();
+        if matches!(event, ServerEvent::ShuttingDown) {
+            break;
+        }
+    }
+    tokio::time::timeout(TIMEOUT, server.wait()).await.unwrap().unwrap();
+}
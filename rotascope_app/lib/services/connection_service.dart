@@ -5,6 +5,10 @@ import 'package:web_socket_channel/web_socket_channel.dart';
 
 enum ConnectionStatus { disconnected, connecting, connected, error }
 
+// 视频帧的二进制头部：类型 0x01 | 显示器 u8 | 宽 u32 | 高 u32 | 时间戳 u64（小端）
+const int _videoFrameKind = 0x01;
+const int _videoHeaderLength = 18;
+
 class ConnectionService extends ChangeNotifier {
   WebSocketChannel? _channel;
   ConnectionStatus _status = ConnectionStatus.disconnected;
@@ -114,6 +118,10 @@ class ConnectionService extends ChangeNotifier {
 
   void _handleBinaryMessage(Uint8List message) {
     try {
+      if (message.length > _videoHeaderLength && message[0] == _videoFrameKind) {
+        message = Uint8List.sublistView(message, _videoHeaderLength);
+      }
+
       // 增强的数据验证
       if (!_isValidImageData(message)) {
         _invalidFramesReceived++;